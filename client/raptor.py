@@ -58,7 +58,7 @@ class Store :
         '''
         return Document(self, name)
 
-    def upload_binary(self, name, filename):
+    def upload_binary(self, name, filename, pipeline=None):
         '''
             Upload a binary to the store.
 
@@ -68,16 +68,27 @@ class Store :
         data = fh.read()
         fh.close()
         bytes = [int(x) for x in data]
-        return self.new_document(name, bytes)
+        return self.new_document(name, bytes, pipeline)
 
-    def new_document(self, name, bytes):
+    def new_document(self, name, bytes, pipeline=None):
         '''
             Create a new document.
 
             Bytes must be an array of integers representing each byte in the
             file you are uploading.
+
+            pipeline optionally configures translation, for example
+            {'passes': ['optimize', 'dead-code-elimination'],
+             'max-iterations': 64, 'keep-unoptimized': True,
+             'discover-functions': True}
+
+            discover-functions looks for functions recursive descent misses,
+            in .eh_frame, pointers in data and function prologues.
         '''
-        self.request('document-new', {'name': name, 'bytes': bytes})
+        params = {'name': name, 'bytes': bytes}
+        if pipeline is not None:
+            params['pipeline'] = pipeline
+        self.request('document-new', params)
         return Document(self, name)
 
 
@@ -102,6 +113,17 @@ class Document:
             {'document-name': self.name})
         return [Function(self, function['index']) for function in response]
 
+    def create_function(self, address):
+        '''
+            Lift and translate a function at address which discovery missed,
+            returning it.
+        '''
+        index = self.store.request('function-create', {
+            'document-name': self.name,
+            'address': address
+        })
+        return self.function(index)
+
     def xrefs(self):
         return self.store.request(
             'document-xrefs',
@@ -119,12 +141,332 @@ class Document:
             self.store.request('function-ir',
                 {'document-name': self.name, 'function-index': index}))
 
+    def unoptimized_function(self, index):
+        '''
+            The function as lifted, before translation. The document must
+            have been created with keep-unoptimized in its pipeline.
+        '''
+        return Function(
+            self,
+            self.store.request('function-ir',
+                {'document-name': self.name,
+                 'function-index': index,
+                 'unoptimized': True}))
+
     def calls_to_symbol(self, symbol):
         calls = self.store.request(
             'calls-to-symbol',
             {'document-name': self.name, 'symbol': symbol})
         return [ProgramLocation(self, call) for call in calls]
 
+    def slice(self, program_location, variable=None, expression=None,
+              direction='backward', interprocedural=False):
+        '''
+            Slice from a variable, or every variable in an expression, at a
+            program location.
+
+            Returns the sliced program locations, and for each function
+            touched the CFG pruned down to the sliced instructions.
+        '''
+        params = {
+            'document-name': self.name,
+            'program-location': program_location.json,
+            'direction': direction,
+            'interprocedural': interprocedural
+        }
+        if variable is not None:
+            params['variable'] = variable
+        if expression is not None:
+            params['expression'] = expression
+        result = self.store.request('slice', params)
+        result['locations'] = \
+            [ProgramLocation(self, pl) for pl in result['locations']]
+        return result
+
+    def path_smtlib(self, start, end, path=None):
+        '''
+            The SMT-LIB 2 encoding of the IR between two program locations
+            in the same function, and the branch conditions along the way.
+
+            path is an optional list of block indices from the start's block
+            to the end's. Returns the path used, the script, and the input
+            symbols with the variables they stand for.
+        '''
+        params = {
+            'document-name': self.name,
+            'start': start.json,
+            'end': end.json
+        }
+        if path is not None:
+            params['path'] = path
+        return self.store.request('path-smtlib', params)
+
+    def function_paths(self, source, target, limit=100, max_visits=1,
+                       shortest=False):
+        '''
+            Paths of block indices through the CFG between two program
+            locations in the same function. max_visits bounds how many times
+            a path may pass through a block, so 1 gives acyclic paths.
+        '''
+        return self.store.request('function-paths', {
+            'document-name': self.name,
+            'source': source.json,
+            'target': target.json,
+            'limit': limit,
+            'max-visits': max_visits,
+            'shortest': shortest
+        })
+
+    def callgraph_paths(self, source, target, limit=100, max_visits=1,
+                        shortest=False):
+        '''
+            Paths of function indices through the call graph. source and
+            target may be functions, function indices or program locations.
+            Each path comes with the call sites linking its functions.
+        '''
+        def function_index(f):
+            if isinstance(f, Function):
+                return f.index
+            if isinstance(f, ProgramLocation):
+                return f.json
+            return f
+
+        result = self.store.request('callgraph-paths', {
+            'document-name': self.name,
+            'source': function_index(source),
+            'target': function_index(target),
+            'limit': limit,
+            'max-visits': max_visits,
+            'shortest': shortest
+        })
+        for path in result['paths']:
+            path['call-sites'] = [
+                [ProgramLocation(self, pl) for pl in sites]
+                for sites in path['call-sites']
+            ]
+        return result
+
+    def reachability(self, roots=None, functions=None, input_sources=None):
+        '''
+            Functions reachable from the binary's ways in, and those on call
+            paths to input sources.
+
+            roots is a list of 'entry', 'exports' and 'init-array', all by
+            default. functions is a list of extra function indices to start
+            from. input_sources replaces the default list of symbols which
+            read outside data.
+        '''
+        params = {'document-name': self.name}
+        if roots is not None:
+            params['roots'] = roots
+        if functions is not None:
+            params['functions'] = functions
+        if input_sources is not None:
+            params['input-sources'] = input_sources
+        return self.store.request('document-reachability', params)
+
+    def taint(self, sources, sinks):
+        '''
+            Find flows of tainted data from source symbols to sink symbols.
+
+            Sources are dicts of the form
+            {'symbol': 'read', 'return': False, 'arguments': [1]}, where
+            arguments are the positions of pointers to tainted data. Sinks are
+            dicts of the form {'symbol': 'system', 'arguments': [0]}.
+        '''
+        flows = self.store.request(
+            'taint',
+            {'document-name': self.name, 'sources': sources, 'sinks': sinks})
+        for flow in flows:
+            flow['source']['location'] = \
+                ProgramLocation(self, flow['source']['location'])
+            flow['sink']['location'] = \
+                ProgramLocation(self, flow['sink']['location'])
+            flow['path'] = [ProgramLocation(self, pl) for pl in flow['path']]
+        return flows
+
+    def audit(self, rules=None, builtin=True):
+        '''
+            Audit calls to dangerous symbols.
+
+            Runs the built-in rule set unless builtin is False, plus any extra
+            rules given. A rule is a dict of the form
+            {'symbol': 'memcpy', 'severity': 'high', 'description': '...',
+             'checks': [{'type': 'size-exceeds-destination',
+                         'destination': 0, 'size': 2}]}.
+            Check types are always, non-constant, stack-destination and
+            size-exceeds-destination.
+        '''
+        params = {'document-name': self.name, 'builtin': builtin}
+        if rules is not None:
+            params['rules'] = rules
+        findings = self.store.request('document-audit', params)
+        for finding in findings:
+            finding['location'] = ProgramLocation(self, finding['location'])
+        return findings
+
+    def format_strings(self):
+        '''
+            Find calls to printf-family functions whose format argument is not
+            a constant pointing into read-only memory.
+        '''
+        results = self.store.request(
+            'format-strings',
+            {'document-name': self.name})
+        for result in results:
+            result['location'] = ProgramLocation(self, result['location'])
+        return results
+
+    def disassemble(self, start, end):
+        '''
+            Decode the machine instructions starting in [start, end). Each
+            lists the IR instructions lifted from it under 'ir'.
+        '''
+        return self.store.request(
+            'disassemble',
+            {'document-name': self.name, 'start': start, 'end': end})
+
+    def instructions_in_range(self, start, end):
+        '''
+            Every IR instruction lifted from an address in [start, end), with
+            its function and block. 'containing' holds the blocks of the
+            machine instructions start or end fall in the middle of, if any.
+        '''
+        return self.store.request(
+            'instructions-in-range',
+            {'document-name': self.name, 'start': start, 'end': end})
+
+    def search_constant(self, value=None, low=None, high=None, mask=None,
+                        function=None):
+        '''
+            Find every use of a constant, including in call arguments and
+            intrinsic operands. Give value for an exact match, low and high
+            for an inclusive range, or mask and value to match the masked
+            bits. function optionally limits the search to one function.
+
+            Each result has the location, the path of fields from the
+            operation down to the constant, and the constant.
+        '''
+        if mask is not None:
+            match = {'type': 'mask', 'mask': mask, 'value': value}
+        elif low is not None or high is not None:
+            match = {'type': 'range', 'low': low, 'high': high}
+        else:
+            match = {'type': 'exact', 'value': value}
+        params = {'document-name': self.name, 'match': match}
+        if function is not None:
+            params['function-index'] = function.index
+        results = self.store.request('search-constant', params)
+        for result in results:
+            result['location'] = ProgramLocation(self, result['location'])
+        return results
+
+    def query(self, query, function=None):
+        '''
+            Find operations or expressions matching a structural query, such
+            as "store(index: add(stack_var, *), src: $value=call_result)".
+            function optionally limits the search to one function.
+
+            Each result has the location, the path to the matched expression
+            (None when an operation matched), and the captured values.
+        '''
+        params = {'document-name': self.name, 'query': query}
+        if function is not None:
+            params['function-index'] = function.index
+        results = self.store.request('query', params)
+        for result in results:
+            result['location'] = ProgramLocation(self, result['location'])
+        return results
+
+    def memory_accesses(self, start, end=None):
+        '''
+            Find loads and stores whose index evaluates to an address in
+            [start, end), and constants pointing into it. Without end, only
+            start itself is searched.
+
+            Returns readers, writers and references.
+        '''
+        params = {'document-name': self.name, 'start': start}
+        if end is not None:
+            params['end'] = end
+        result = self.store.request('memory-accesses', params)
+        for group in ('readers', 'writers', 'references'):
+            for access in result[group]:
+                access['location'] = ProgramLocation(self, access['location'])
+        return result
+
+    def intrinsics(self):
+        '''
+            Instructions falcon could not model, grouped by mnemonic, and how
+            much of each function's instructions are modeled.
+        '''
+        result = self.store.request(
+            'document-intrinsics',
+            {'document-name': self.name})
+        for mnemonic in result['mnemonics']:
+            mnemonic['locations'] = [ProgramLocation(self, location)
+                                     for location in mnemonic['locations']]
+        return result
+
+    def syscalls(self):
+        '''
+            Find direct system calls, with the syscall number, its name where
+            known, and the arguments under the Linux ABI. Values which could
+            not be recovered are None, alongside the expression they hold.
+        '''
+        results = self.store.request(
+            'document-syscalls',
+            {'document-name': self.name})
+        for result in results:
+            result['location'] = ProgramLocation(self, result['location'])
+        return results
+
+    def crypto(self):
+        '''
+            Find well known cryptographic tables in the loaded image, with the
+            functions referencing them, and functions using well known
+            cryptographic constants.
+        '''
+        result = self.store.request(
+            'document-crypto',
+            {'document-name': self.name})
+        for constant in result['constants']:
+            for use in constant['uses']:
+                use['location'] = ProgramLocation(self, use['location'])
+        return result
+
+    def memory_search(self, patterns=None, rules=None, max_hits=10000):
+        '''
+            Search the loaded image for YARA style hex patterns, such as
+            "6a 40 ?? 00 [2-4] (01 | 02)", or with the text of a YARA rule
+            file. Jumps must be bounded and at most 256 bytes wide, with at
+            most two to a pattern. Hits in code come with the function and IR
+            instructions lifted from the instruction they start in. Searching
+            stops after max_hits hits.
+        '''
+        params = {'document-name': self.name, 'max-hits': max_hits}
+        if patterns is not None:
+            params['patterns'] = patterns
+        if rules is not None:
+            params['rules'] = rules
+        hits = self.store.request('memory-search', params)
+        for hit in hits:
+            hit['ir'] = [ProgramLocation(self, location) for location in hit['ir']]
+        return hits
+
+    def unused_results(self, symbols=None):
+        '''
+            Find calls whose results are never used, optionally only calls to
+            the given symbols.
+        '''
+        params = {'document-name': self.name}
+        if symbols is not None:
+            params['symbols'] = symbols
+        results = self.store.request('document-unused-results', params)
+        for result in results:
+            result['location'] = ProgramLocation(self, result['location'])
+        return results
+
 
 class Function:
     def __init__(self, document, json):
@@ -157,6 +499,118 @@ class Function:
                 return block
         return None
 
+    def il(self):
+        '''
+            The falcon IL this function was lifted from. 'mapping' relates
+            each raptor instruction to the IL instructions at its address.
+        '''
+        return self.document.store.request(
+            'function-il',
+            {'document-name': self.document.name, 'function-index': self.index})
+
+    def disassembly(self):
+        '''
+            The machine instructions of this function's blocks, in address
+            order, each with the IR instructions lifted from it. Instructions
+            whose IR was optimized away have none.
+        '''
+        return self.document.store.request(
+            'function-disassembly',
+            {'document-name': self.document.name, 'function-index': self.index})
+
+    def retranslate(self):
+        '''
+            Lift and translate this function again from the binary.
+        '''
+        return self.document.store.request(
+            'function-retranslate',
+            {'document-name': self.document.name, 'function-index': self.index})
+
+    def dominators(self):
+        '''
+            Immediate dominators, immediate post dominators and dominance
+            frontiers for every block.
+        '''
+        return self.document.store.request(
+            'function-dominators',
+            {'document-name': self.document.name, 'function-index': self.index})
+
+    def loops(self):
+        '''
+            Natural loops, with their header, body blocks, exits and nesting
+            depth.
+        '''
+        return self.document.store.request(
+            'function-loops',
+            {'document-name': self.document.name, 'function-index': self.index})
+
+    def ssa(self):
+        '''
+            This function in SSA form.
+
+            Serialized like function-ir, with a version on every variable and
+            the phi nodes of each block.
+        '''
+        return self.document.store.request(
+            'function-ssa',
+            {'document-name': self.document.name, 'function-index': self.index})
+
+    def liveness(self):
+        '''
+            Live variables in and out of every block and instruction, and the
+            calls whose results are never used.
+        '''
+        return self.document.store.request(
+            'function-liveness',
+            {'document-name': self.document.name, 'function-index': self.index})
+
+    def emulate(self, registers=None, memory=None, max_steps=10000):
+        '''
+            Concretely execute this function with falcon's executor.
+
+            registers is a dict of register name to value, or to a
+            (value, bits) tuple. memory is a dict of address to bytes,
+            overriding the loaded image.
+        '''
+        params = {
+            'document-name': self.document.name,
+            'function-index': self.index,
+            'max-steps': max_steps
+        }
+        if registers is not None:
+            params['registers'] = []
+            for name, value in registers.items():
+                if isinstance(value, tuple):
+                    params['registers'].append(
+                        {'name': name, 'value': value[0], 'bits': value[1]})
+                else:
+                    params['registers'].append({'name': name, 'value': value})
+        if memory is not None:
+            params['memory'] = [
+                {'address': address, 'bytes': list(bytearray(data))}
+                for address, data in memory.items()
+            ]
+        return self.document.store.request('function-emulate', params)
+
+    def explore(self, target, max_paths=None, max_visits=None, timeout=None):
+        '''
+            Search for input values which reach the instruction at address
+            target, solving each path through this function with z3.
+            timeout is in seconds.
+        '''
+        params = {
+            'document-name': self.document.name,
+            'function-index': self.index,
+            'target': target
+        }
+        if max_paths is not None:
+            params['max-paths'] = max_paths
+        if max_visits is not None:
+            params['max-visits'] = max_visits
+        if timeout is not None:
+            params['timeout'] = timeout
+        return self.document.store.request('function-explore', params)
+
 
 class Block:
     def __init__(self, function, json):
@@ -0,0 +1,308 @@
+use analysis::dataflow;
+use analysis::expression;
+use analysis::format_string::FORMAT_FUNCTIONS;
+use analysis::values::Values;
+use analysis::InstructionLocation;
+use error::*;
+use raptor::ir;
+
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Severity {
+    Low,
+    Medium,
+    High,
+    Critical
+}
+
+
+impl Severity {
+    pub fn from_name(s: &str) -> Result<Severity> {
+        Ok(match s {
+            "low" => Severity::Low,
+            "medium" => Severity::Medium,
+            "high" => Severity::High,
+            "critical" => Severity::Critical,
+            _ => bail!("Unknown severity {}", s)
+        })
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+            Severity::Critical => "critical"
+        }
+    }
+}
+
+
+/// A property of a call's arguments which makes the call worth reporting.
+#[derive(Clone, Debug)]
+pub enum Check {
+    /// Every call is reported.
+    Always,
+    /// The argument at this position is not a constant, such as a format
+    /// string or shell command built at runtime.
+    NonConstant(usize),
+    /// The argument at this position points into a stack buffer.
+    StackDestination(usize),
+    /// The size argument is a constant larger than the stack buffer the
+    /// destination argument points to.
+    SizeExceedsDestination { destination: usize, size: usize }
+}
+
+
+impl Check {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Check::Always => "always",
+            Check::NonConstant(_) => "non-constant",
+            Check::StackDestination(_) => "stack-destination",
+            Check::SizeExceedsDestination { .. } => "size-exceeds-destination"
+        }
+    }
+}
+
+
+/// A symbol to audit, what to check about calls to it, and how bad it is
+/// when a check fires.
+#[derive(Clone, Debug)]
+pub struct Rule {
+    symbol: String,
+    checks: Vec<Check>,
+    severity: Severity,
+    description: String
+}
+
+
+impl Rule {
+    pub fn new<S: Into<String>, D: Into<String>>(
+        symbol: S,
+        checks: Vec<Check>,
+        severity: Severity,
+        description: D
+    ) -> Rule {
+        Rule {
+            symbol: symbol.into(),
+            checks: checks,
+            severity: severity,
+            description: description.into()
+        }
+    }
+
+    pub fn symbol(&self) -> &str { &self.symbol }
+    pub fn checks(&self) -> &[Check] { &self.checks }
+    pub fn severity(&self) -> Severity { self.severity }
+    pub fn description(&self) -> &str { &self.description }
+}
+
+
+/// The rules `document-audit` runs when the caller doesn't turn them off.
+pub fn builtin_rules() -> Vec<Rule> {
+    use self::Check::*;
+    use self::Severity::*;
+
+    let format = |symbol: &str, position: usize| Rule::new(
+        symbol,
+        vec![NonConstant(position)],
+        High,
+        "Format string is not constant");
+
+    let copy = |symbol: &str| Rule::new(
+        symbol,
+        vec![StackDestination(0)],
+        High,
+        "Unbounded copy into a stack buffer");
+
+    let sized = |symbol: &str, destination: usize, size: usize| Rule::new(
+        symbol,
+        vec![SizeExceedsDestination { destination: destination, size: size }],
+        High,
+        "Size is larger than the destination stack buffer");
+
+    let command = |symbol: &str| Rule::new(
+        symbol,
+        vec![NonConstant(0)],
+        High,
+        "Command is not constant");
+
+    let mut rules = vec![
+        Rule::new("gets", vec![Always], Critical, "gets can never be used safely"),
+        copy("strcpy"),
+        copy("stpcpy"),
+        copy("strcat"),
+        Rule::new("sprintf", vec![StackDestination(0)], High,
+                  "Unbounded formatted write into a stack buffer"),
+        Rule::new("vsprintf", vec![StackDestination(0)], High,
+                  "Unbounded formatted write into a stack buffer"),
+        sized("memcpy", 0, 2),
+        sized("memmove", 0, 2),
+        sized("strncpy", 0, 2),
+        sized("strncat", 0, 2),
+        sized("memset", 0, 2),
+        sized("snprintf", 0, 1),
+        sized("fgets", 0, 1),
+        sized("read", 1, 2),
+        sized("recv", 1, 2),
+        Rule::new("memcpy", vec![NonConstant(2)], Low, "Copy size is not constant"),
+        command("system"),
+        command("popen"),
+        command("execl"),
+        command("execlp"),
+        command("execv"),
+        command("execvp"),
+        command("execve")
+    ];
+
+    rules.extend(
+        FORMAT_FUNCTIONS.iter()
+            .map(|&(symbol, position)| format(symbol, position)));
+
+    rules
+}
+
+
+/// A call which failed one of a rule's checks.
+#[derive(Clone, Debug)]
+pub struct Finding {
+    rule: Rule,
+    check: Check,
+    location: InstructionLocation,
+    details: String
+}
+
+
+impl Finding {
+    pub fn rule(&self) -> &Rule { &self.rule }
+    pub fn check(&self) -> &Check { &self.check }
+    pub fn location(&self) -> &InstructionLocation { &self.location }
+    pub fn details(&self) -> &str { &self.details }
+}
+
+
+/// Estimate the size of the stack buffer starting at `buffer`, as the
+/// distance to the next stack variable the function touches, or to the
+/// frame base when there is none.
+pub fn stack_buffer_size(
+    function: &ir::Function<ir::Constant>,
+    buffer: &ir::StackVariable
+) -> Option<u64> {
+    let next =
+        expression::stack_offsets(function)
+            .into_iter()
+            .find(|offset| *offset > buffer.offset());
+
+    match next {
+        Some(next) => Some((next - buffer.offset()) as u64),
+        None if buffer.offset() < 0 => Some((-buffer.offset()) as u64),
+        None => None
+    }
+}
+
+
+fn check_call(
+    values: &Values,
+    location: &InstructionLocation,
+    arguments: &[&ir::Expression<ir::Constant>],
+    check: &Check
+) -> Option<String> {
+    match *check {
+        Check::Always => Some("call".to_string()),
+        Check::NonConstant(position) => {
+            let argument = arguments.get(position)?;
+            match values.evaluate(location, argument) {
+                Some(_) => None,
+                None => Some(format!("argument {} is not constant", position))
+            }
+        },
+        Check::StackDestination(position) => {
+            let argument = arguments.get(position)?;
+            values.stack_buffer(location, argument)
+                .map(|buffer| format!(
+                    "argument {} points to stack offset {}",
+                    position, buffer.offset()))
+        },
+        Check::SizeExceedsDestination { destination, size } => {
+            let buffer =
+                values.stack_buffer(location, arguments.get(destination)?)?;
+            let buffer_size = stack_buffer_size(values.function(), &buffer)?;
+            let size = values.evaluate(location, arguments.get(size)?)?;
+            if size > buffer_size {
+                Some(format!(
+                    "size {} exceeds stack buffer at offset {} of about {} bytes",
+                    size, buffer.offset(), buffer_size))
+            }
+            else {
+                None
+            }
+        }
+    }
+}
+
+
+/// Run every rule against every call in `program`.
+pub fn audit(program: &ir::Program<ir::Constant>, rules: &[Rule])
+    -> Result<Vec<Finding>> {
+
+    let mut findings = Vec::new();
+
+    for function in program.functions() {
+        let function_index = match function.index() {
+            Some(index) => index,
+            None => continue
+        };
+
+        // Only build value analysis for functions which call something we
+        // have a rule for.
+        let mut values: Option<Values> = None;
+
+        for block in function.blocks() {
+            for location in dataflow::block_locations(function_index, block) {
+                let call =
+                    match dataflow::instruction(function, &location)
+                            .and_then(|instruction| instruction.operation().call()) {
+                        Some(call) => call,
+                        None => continue
+                    };
+                let symbol = match call.target().symbol() {
+                    Some(symbol) => symbol,
+                    None => continue
+                };
+
+                let arguments: Vec<&ir::Expression<ir::Constant>> =
+                    call.arguments()
+                        .map(|arguments| arguments.into_iter().collect())
+                        .unwrap_or_default();
+
+                for rule in rules.iter().filter(|rule| rule.symbol() == symbol) {
+                    if values.is_none() {
+                        values = Values::new(function);
+                    }
+                    let values = match values.as_ref() {
+                        Some(values) => values,
+                        None => continue
+                    };
+                    for check in rule.checks() {
+                        if let Some(details) =
+                            check_call(values, &location, &arguments, check) {
+                            findings.push(Finding {
+                                rule: rule.clone(),
+                                check: check.clone(),
+                                location: location,
+                                details: details
+                            });
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    findings.sort_by(|a, b|
+        b.rule.severity().cmp(&a.rule.severity())
+            .then(a.location.cmp(&b.location)));
+
+    Ok(findings)
+}
@@ -0,0 +1,112 @@
+use analysis::InstructionLocation;
+use raptor::ir;
+use std::collections::{BTreeMap, BTreeSet};
+
+
+/// Direct calls between the functions of a program, and calls out to
+/// symbols we have no IR for.
+///
+/// Indirect calls are not resolved, so they don't appear here.
+#[derive(Clone, Debug)]
+pub struct CallGraph {
+    functions: BTreeSet<usize>,
+    call_sites: BTreeMap<usize, BTreeMap<usize, Vec<InstructionLocation>>>,
+    callers: BTreeMap<usize, BTreeSet<usize>>,
+    symbol_callers: BTreeMap<String, BTreeSet<usize>>
+}
+
+
+impl CallGraph {
+    pub fn new(program: &ir::Program<ir::Constant>) -> CallGraph {
+        let mut functions = BTreeSet::new();
+        let mut call_sites: BTreeMap<usize, BTreeMap<usize, Vec<InstructionLocation>>> =
+            BTreeMap::new();
+        let mut callers: BTreeMap<usize, BTreeSet<usize>> = BTreeMap::new();
+        let mut symbol_callers: BTreeMap<String, BTreeSet<usize>> = BTreeMap::new();
+
+        for function in program.functions() {
+            let function_index = match function.index() {
+                Some(index) => index,
+                None => continue
+            };
+            functions.insert(function_index);
+            call_sites.entry(function_index).or_insert_with(BTreeMap::new);
+            callers.entry(function_index).or_insert_with(BTreeSet::new);
+
+            for block in function.blocks() {
+                for instruction in block.instructions() {
+                    let call = match instruction.operation().call() {
+                        Some(call) => call,
+                        None => continue
+                    };
+                    match call.target() {
+                        ir::CallTarget::FunctionId(callee) => {
+                            call_sites.get_mut(&function_index)
+                                .unwrap()
+                                .entry(*callee)
+                                .or_insert_with(Vec::new)
+                                .push(InstructionLocation::new(
+                                    function_index, block.index(), instruction.index()));
+                            callers.entry(*callee)
+                                .or_insert_with(BTreeSet::new)
+                                .insert(function_index);
+                        },
+                        ir::CallTarget::Symbol(symbol) => {
+                            symbol_callers.entry(symbol.to_string())
+                                .or_insert_with(BTreeSet::new)
+                                .insert(function_index);
+                        },
+                        ir::CallTarget::Expression(_) => {}
+                    }
+                }
+            }
+        }
+
+        CallGraph {
+            functions: functions,
+            call_sites: call_sites,
+            callers: callers,
+            symbol_callers: symbol_callers
+        }
+    }
+
+    /// Indices of every function in the program.
+    pub fn functions(&self) -> &BTreeSet<usize> { &self.functions }
+
+    pub fn callees(&self, index: usize) -> BTreeSet<usize> {
+        self.call_sites.get(&index)
+            .map(|callees| callees.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn callers(&self, index: usize) -> BTreeSet<usize> {
+        self.callers.get(&index).cloned().unwrap_or_default()
+    }
+
+    /// Where `caller` calls `callee`.
+    pub fn call_sites(&self, caller: usize, callee: usize) -> &[InstructionLocation] {
+        self.call_sites.get(&caller)
+            .and_then(|callees| callees.get(&callee))
+            .map(|sites| sites.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Functions which call `symbol` directly.
+    pub fn symbol_callers(&self, symbol: &str) -> BTreeSet<usize> {
+        self.symbol_callers.get(symbol).cloned().unwrap_or_default()
+    }
+
+    /// Every function reachable from `roots` by following calls, including
+    /// the roots themselves.
+    pub fn reachable_from<I: IntoIterator<Item=usize>>(&self, roots: I) -> BTreeSet<usize> {
+        let mut visited = BTreeSet::new();
+        let mut queue: Vec<usize> = roots.into_iter().collect();
+        while let Some(index) = queue.pop() {
+            if !visited.insert(index) {
+                continue;
+            }
+            queue.extend(self.callees(index));
+        }
+        visited
+    }
+}
@@ -0,0 +1,217 @@
+use raptor::ir;
+use std::collections::{BTreeMap, BTreeSet};
+
+
+/// The shape of a function's control flow graph, keyed by block index.
+///
+/// Analyses in this crate only care about which blocks exist and how they
+/// connect, so this is built once from `ir::Function::blocks` and
+/// `ir::Function::edges` and then walked freely.
+#[derive(Clone, Debug)]
+pub struct Cfg {
+    entry: Option<usize>,
+    blocks: BTreeSet<usize>,
+    successors: BTreeMap<usize, BTreeSet<usize>>,
+    predecessors: BTreeMap<usize, BTreeSet<usize>>
+}
+
+
+impl Cfg {
+    pub fn new(function: &ir::Function<ir::Constant>) -> Cfg {
+        let mut blocks = BTreeSet::new();
+        let mut successors = BTreeMap::new();
+        let mut predecessors = BTreeMap::new();
+
+        for block in function.blocks() {
+            blocks.insert(block.index());
+            successors.insert(block.index(), BTreeSet::new());
+            predecessors.insert(block.index(), BTreeSet::new());
+        }
+
+        for edge in function.edges() {
+            blocks.insert(edge.head());
+            blocks.insert(edge.tail());
+            successors.entry(edge.tail()).or_insert_with(BTreeSet::new);
+            predecessors.entry(edge.head()).or_insert_with(BTreeSet::new);
+            successors.entry(edge.head())
+                .or_insert_with(BTreeSet::new)
+                .insert(edge.tail());
+            predecessors.entry(edge.tail())
+                .or_insert_with(BTreeSet::new)
+                .insert(edge.head());
+        }
+
+        // Prefer the single block without predecessors. If there is not
+        // exactly one, fall back to the block holding the function's first
+        // instruction, and finally to the lowest block index.
+        let roots: Vec<usize> =
+            blocks.iter()
+                .filter(|index| predecessors[index].is_empty())
+                .cloned()
+                .collect();
+
+        let entry =
+            if roots.len() == 1 {
+                Some(roots[0])
+            }
+            else {
+                function.blocks()
+                    .into_iter()
+                    .find(|block|
+                        block.instructions()
+                            .first()
+                            .and_then(|instruction| instruction.address())
+                            .map(|address| address == function.address())
+                            .unwrap_or(false))
+                    .map(|block| block.index())
+                    .or_else(|| blocks.iter().next().cloned())
+            };
+
+        Cfg {
+            entry: entry,
+            blocks: blocks,
+            successors: successors,
+            predecessors: predecessors
+        }
+    }
+
+    pub fn entry(&self) -> Option<usize> { self.entry }
+    pub fn blocks(&self) -> &BTreeSet<usize> { &self.blocks }
+
+    pub fn successors(&self, index: usize) -> &BTreeSet<usize> {
+        &self.successors[&index]
+    }
+
+    pub fn predecessors(&self, index: usize) -> &BTreeSet<usize> {
+        &self.predecessors[&index]
+    }
+
+    /// Blocks with no successors.
+    pub fn exits(&self) -> BTreeSet<usize> {
+        self.blocks
+            .iter()
+            .filter(|index| self.successors[index].is_empty())
+            .cloned()
+            .collect()
+    }
+
+    /// Every block reachable from `start`, including `start`.
+    pub fn reachable_from(&self, start: usize) -> BTreeSet<usize> {
+        let mut visited = BTreeSet::new();
+        let mut queue = vec![start];
+        while let Some(index) = queue.pop() {
+            if !visited.insert(index) {
+                continue;
+            }
+            for successor in self.successors(index) {
+                queue.push(*successor);
+            }
+        }
+        visited
+    }
+
+    /// Blocks in reverse post order from the entry. Blocks unreachable from
+    /// the entry are appended in index order.
+    pub fn reverse_post_order(&self) -> Vec<usize> {
+        let mut order = Vec::new();
+        let mut visited = BTreeSet::new();
+
+        if let Some(entry) = self.entry {
+            // Iterative depth-first search, so deep graphs don't blow the
+            // stack.
+            let mut stack: Vec<(usize, Vec<usize>)> = Vec::new();
+            visited.insert(entry);
+            stack.push((entry, self.successors(entry).iter().cloned().collect()));
+            while !stack.is_empty() {
+                let next = stack.last_mut().unwrap().1.pop();
+                match next {
+                    Some(successor) => {
+                        if visited.insert(successor) {
+                            let successors =
+                                self.successors(successor)
+                                    .iter()
+                                    .cloned()
+                                    .collect();
+                            stack.push((successor, successors));
+                        }
+                    },
+                    None => {
+                        order.push(stack.pop().unwrap().0);
+                    }
+                }
+            }
+        }
+
+        order.reverse();
+
+        for index in &self.blocks {
+            if !visited.contains(index) {
+                order.push(*index);
+            }
+        }
+
+        order
+    }
+
+    /// Collapse the graph onto `keep`. There is an edge between two kept
+    /// blocks if the tail is reachable from the head without passing through
+    /// another kept block.
+    pub fn collapse(&self, keep: &BTreeSet<usize>) -> BTreeSet<(usize, usize)> {
+        let mut edges = BTreeSet::new();
+
+        for head in keep {
+            let mut visited = BTreeSet::new();
+            let mut queue: Vec<usize> =
+                self.successors(*head).iter().cloned().collect();
+            while let Some(index) = queue.pop() {
+                if !visited.insert(index) {
+                    continue;
+                }
+                if keep.contains(&index) {
+                    edges.insert((*head, index));
+                    continue;
+                }
+                for successor in self.successors(index) {
+                    queue.push(*successor);
+                }
+            }
+        }
+
+        edges
+    }
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use falcon::il;
+    use raptor::ir;
+    use super::*;
+
+    /// A function of `blocks` blocks, indexed from 0 with 0 the entry,
+    /// joined by `edges`.
+    pub fn function(blocks: usize, edges: &[(usize, usize)]) -> ir::Function<ir::Constant> {
+        let mut cfg = il::ControlFlowGraph::new();
+        for _ in 0..blocks {
+            cfg.new_block()
+                .unwrap()
+                .assign(il::scalar("a", 32), il::expr_const(0, 32));
+        }
+        for &(head, tail) in edges {
+            cfg.unconditional_edge(head, tail).unwrap();
+        }
+        cfg.set_entry(0).unwrap();
+        ir::Function::<ir::Constant>::from_il(&il::Function::new(0, cfg)).unwrap()
+    }
+
+    #[test]
+    fn shape() {
+        let cfg = Cfg::new(&function(4, &[(0, 1), (0, 2), (1, 3), (2, 3)]));
+        assert_eq!(cfg.entry(), Some(0));
+        assert_eq!(cfg.successors(0), &vec![1, 2].into_iter().collect());
+        assert_eq!(cfg.predecessors(3), &vec![1, 2].into_iter().collect());
+        assert_eq!(cfg.exits(), vec![3].into_iter().collect());
+        assert_eq!(cfg.reverse_post_order().first(), Some(&0));
+        assert_eq!(cfg.reverse_post_order().last(), Some(&3));
+    }
+}
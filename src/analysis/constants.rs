@@ -0,0 +1,99 @@
+//! Finding where constants are used.
+
+use analysis::expression;
+use analysis::InstructionLocation;
+use raptor::ir;
+
+
+/// Which constant values to look for.
+#[derive(Clone, Copy, Debug)]
+pub enum Matcher {
+    Exact(u64),
+    /// Between the two values, inclusive.
+    Range(u64, u64),
+    /// Values whose bits under the mask equal the value's.
+    Mask { mask: u64, value: u64 }
+}
+
+
+impl Matcher {
+    pub fn matches(&self, value: u64) -> bool {
+        match *self {
+            Matcher::Exact(exact) => value == exact,
+            Matcher::Range(low, high) => value >= low && value <= high,
+            Matcher::Mask { mask, value: masked } => value & mask == masked & mask
+        }
+    }
+}
+
+
+/// A constant matched in an instruction.
+#[derive(Clone)]
+pub struct ConstantUse {
+    location: InstructionLocation,
+    path: String,
+    constant: ir::Constant
+}
+
+
+impl ConstantUse {
+    pub fn location(&self) -> &InstructionLocation { &self.location }
+
+    /// The fields leading from the operation to the constant, such as
+    /// `index/lhs`, as in `translate::operation_to_json`.
+    pub fn path(&self) -> &str { &self.path }
+
+    pub fn constant(&self) -> &ir::Constant { &self.constant }
+}
+
+
+/// Every use of a constant matching `matcher` in `function`.
+pub fn function_search(function: &ir::Function<ir::Constant>, matcher: &Matcher)
+    -> Vec<ConstantUse> {
+
+    let function_index = match function.index() {
+        Some(index) => index,
+        None => return Vec::new()
+    };
+
+    let mut uses = Vec::new();
+    for block in function.blocks() {
+        for instruction in block.instructions() {
+            let location = InstructionLocation::new(
+                function_index, block.index(), instruction.index());
+            for (field, e) in expression::operation_expressions(instruction.operation()) {
+                expression::walk(&e, &mut |path, e| {
+                    let constant = match expression::constant(e) {
+                        Some(constant) => constant,
+                        None => return
+                    };
+                    // Constants wider than 64 bits can't be matched.
+                    let matched =
+                        constant.value_u64()
+                            .map(|value| matcher.matches(value))
+                            .unwrap_or(false);
+                    if matched {
+                        let mut full_path = vec![field.as_str()];
+                        full_path.extend(path.iter().cloned());
+                        uses.push(ConstantUse {
+                            location: location,
+                            path: full_path.join("/"),
+                            constant: constant.clone()
+                        });
+                    }
+                });
+            }
+        }
+    }
+
+    uses
+}
+
+
+/// Every use of a constant matching `matcher` in `program`.
+pub fn search(program: &ir::Program<ir::Constant>, matcher: &Matcher) -> Vec<ConstantUse> {
+    program.functions()
+        .into_iter()
+        .flat_map(|function| function_search(function, matcher))
+        .collect()
+}
@@ -0,0 +1,205 @@
+//! Spotting well known cryptographic tables and constants.
+
+use analysis::constants::{self, ConstantUse, Matcher};
+use analysis::memory_accesses;
+use error::*;
+use falcon::architecture::Endian;
+use falcon::loader::Loader;
+use raptor::ir;
+use std::collections::BTreeSet;
+
+
+/// The start of a table, as it appears in memory.
+enum Table {
+    Bytes(&'static [u8]),
+    /// 32-bit words, laid out in the document's endianness.
+    Words(&'static [u32])
+}
+
+
+const TABLES: &[(&str, Table)] = &[
+    ("aes-sbox", Table::Bytes(&[
+        0x63, 0x7c, 0x77, 0x7b, 0xf2, 0x6b, 0x6f, 0xc5,
+        0x30, 0x01, 0x67, 0x2b, 0xfe, 0xd7, 0xab, 0x76])),
+    ("aes-inverse-sbox", Table::Bytes(&[
+        0x52, 0x09, 0x6a, 0xd5, 0x30, 0x36, 0xa5, 0x38,
+        0xbf, 0x40, 0xa3, 0x9e, 0x81, 0xf3, 0xd7, 0xfb])),
+    ("chacha-sigma", Table::Bytes(b"expand 32-byte k")),
+    ("chacha-tau", Table::Bytes(b"expand 16-byte k")),
+    ("crc32-table", Table::Words(&[0x00000000, 0x77073096, 0xee0e612c, 0x990951ba])),
+    ("md5-sines", Table::Words(&[0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee])),
+    ("sha256-k", Table::Words(&[0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5])),
+    ("sha1-md5-init", Table::Words(&[0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476])),
+    ("sha256-init", Table::Words(&[0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a])),
+    ("blowfish-p", Table::Words(&[0x243f6a88, 0x85a308d3, 0x13198a2e, 0x03707344]))
+];
+
+
+/// Constants used as immediates, and how many of them a function must use
+/// to count as a match.
+const CONSTANTS: &[(&str, &[u64], usize)] = &[
+    ("sha1-md5-init", &[0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476], 3),
+    ("sha1-init", &[0xc3d2e1f0], 1),
+    ("sha1-round", &[0x5a827999, 0x6ed9eba1, 0x8f1bbcdc, 0xca62c1d6], 2),
+    ("sha256-init", &[0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a], 3),
+    ("md5-sines", &[0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee], 2),
+    ("chacha-sigma", &[0x61707865, 0x3320646e, 0x79622d32, 0x6b206574], 3),
+    ("tea-delta", &[0x9e3779b9], 1),
+    ("crc32-polynomial", &[0xedb88320, 0x04c11db7], 1)
+];
+
+
+fn table_bytes(table: &Table, big_endian: bool) -> Vec<u8> {
+    match *table {
+        Table::Bytes(bytes) => bytes.to_vec(),
+        Table::Words(words) =>
+            words.iter()
+                .flat_map(|word| {
+                    let bytes = [(word >> 24) as u8, (word >> 16) as u8,
+                                 (word >> 8) as u8, *word as u8];
+                    if big_endian {
+                        bytes.to_vec()
+                    }
+                    else {
+                        bytes.iter().rev().cloned().collect()
+                    }
+                })
+                .collect()
+    }
+}
+
+
+/// A table found in the loaded image.
+pub struct TableMatch {
+    name: &'static str,
+    address: u64,
+    length: usize,
+    functions: BTreeSet<usize>
+}
+
+
+impl TableMatch {
+    pub fn name(&self) -> &str { self.name }
+    pub fn address(&self) -> u64 { self.address }
+    pub fn length(&self) -> usize { self.length }
+
+    /// Functions pointing to, reading or writing the matched bytes.
+    pub fn functions(&self) -> &BTreeSet<usize> { &self.functions }
+}
+
+
+/// A function using enough of a signature's constants.
+pub struct ConstantMatch {
+    name: &'static str,
+    function_index: usize,
+    uses: Vec<ConstantUse>
+}
+
+
+impl ConstantMatch {
+    pub fn name(&self) -> &str { self.name }
+    pub fn function_index(&self) -> usize { self.function_index }
+    pub fn uses(&self) -> &[ConstantUse] { &self.uses }
+}
+
+
+pub struct Crypto {
+    tables: Vec<TableMatch>,
+    constants: Vec<ConstantMatch>
+}
+
+
+impl Crypto {
+    pub fn tables(&self) -> &[TableMatch] { &self.tables }
+    pub fn constants(&self) -> &[ConstantMatch] { &self.constants }
+}
+
+
+fn find(haystack: &[u8], needle: &[u8]) -> Vec<usize> {
+    if needle.is_empty() || haystack.len() < needle.len() {
+        return Vec::new();
+    }
+    (0..haystack.len() - needle.len() + 1)
+        .filter(|offset| &haystack[*offset..*offset + needle.len()] == needle)
+        .collect()
+}
+
+
+pub fn crypto(program: &ir::Program<ir::Constant>, loader: &Loader) -> Result<Crypto> {
+    let big_endian = match loader.architecture().endian() {
+        Endian::Big => true,
+        Endian::Little => false
+    };
+    let memory = loader.memory()?;
+
+    let mut tables = Vec::new();
+    for &(name, ref table) in TABLES {
+        let bytes = table_bytes(table, big_endian);
+        for (base, section) in memory.sections() {
+            for offset in find(section.data(), &bytes) {
+                tables.push(TableMatch {
+                    name: name,
+                    address: base + offset as u64,
+                    length: bytes.len(),
+                    functions: BTreeSet::new()
+                });
+            }
+        }
+    }
+
+    // Look for accesses of every table in one pass over the program.
+    let ranges: Vec<(u64, u64)> =
+        tables.iter()
+            .map(|table| (table.address, table.address + table.length as u64))
+            .collect();
+    let accesses = memory_accesses::memory_accesses_in(program, &ranges);
+    for (table, accesses) in tables.iter_mut().zip(accesses.iter()) {
+        table.functions =
+            accesses.readers()
+                .iter()
+                .chain(accesses.writers().iter())
+                .map(|access| access.location().function_index())
+                .chain(accesses.references()
+                    .iter()
+                    .map(|reference| reference.location().function_index()))
+                .collect();
+    }
+
+    let mut constants = Vec::new();
+    for function in program.functions() {
+        let function_index = match function.index() {
+            Some(index) => index,
+            None => continue
+        };
+        // Every constant in the function, checked against each signature.
+        let uses =
+            constants::function_search(function, &Matcher::Mask { mask: 0, value: 0 });
+        for &(name, values, minimum) in CONSTANTS {
+            let matched: Vec<ConstantUse> =
+                uses.iter()
+                    .filter(|constant_use|
+                        constant_use.constant()
+                            .value_u64()
+                            .map(|value| values.contains(&value))
+                            .unwrap_or(false))
+                    .cloned()
+                    .collect();
+            let distinct: BTreeSet<u64> =
+                matched.iter()
+                    .filter_map(|constant_use| constant_use.constant().value_u64())
+                    .collect();
+            if distinct.len() >= minimum {
+                constants.push(ConstantMatch {
+                    name: name,
+                    function_index: function_index,
+                    uses: matched
+                });
+            }
+        }
+    }
+
+    Ok(Crypto {
+        tables: tables,
+        constants: constants
+    })
+}
@@ -0,0 +1,444 @@
+use analysis::cfg::Cfg;
+use analysis::InstructionLocation;
+use raptor::ir;
+use std::collections::{BTreeMap, BTreeSet};
+
+
+/// Something an instruction can read or write.
+///
+/// Memory is treated as a single location. Writes to it never kill earlier
+/// writes, as we can't tell whether two stores alias.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Location {
+    Variable(ir::Variable),
+    Memory
+}
+
+
+impl Location {
+    pub fn variable(&self) -> Option<&ir::Variable> {
+        match *self {
+            Location::Variable(ref variable) => Some(variable),
+            Location::Memory => None
+        }
+    }
+}
+
+
+/// If `expression` is just a variable, return it.
+pub fn expression_variable(expression: &ir::Expression<ir::Constant>)
+    -> Option<&ir::Variable> {
+
+    fn lvalue_variable(lvalue: &ir::LValue<ir::Constant>) -> Option<&ir::Variable> {
+        match lvalue {
+            ir::LValue::Variable(variable) => Some(variable),
+            ir::LValue::Dereference(_) => None
+        }
+    }
+
+    match expression {
+        ir::Expression::LValue(lvalue) => lvalue_variable(lvalue),
+        _ => None
+    }
+}
+
+
+/// If `expression` takes the address of something, return that something.
+pub fn expression_reference(expression: &ir::Expression<ir::Constant>)
+    -> Option<&ir::Expression<ir::Constant>> {
+
+    fn rvalue_reference(rvalue: &ir::RValue<ir::Constant>)
+        -> Option<&ir::Expression<ir::Constant>> {
+
+        match rvalue {
+            ir::RValue::Reference(reference) => Some(reference.expression()),
+            ir::RValue::Value(_) => None
+        }
+    }
+
+    match expression {
+        ir::Expression::RValue(rvalue) => rvalue_reference(rvalue),
+        _ => None
+    }
+}
+
+
+/// Locations read when evaluating `expression`.
+pub fn expression_uses(expression: &ir::Expression<ir::Constant>)
+    -> BTreeSet<Location> {
+
+    fn lvalue(lvalue: &ir::LValue<ir::Constant>, uses: &mut BTreeSet<Location>) {
+        match lvalue {
+            ir::LValue::Variable(variable) => {
+                uses.insert(Location::Variable(variable.clone()));
+            },
+            ir::LValue::Dereference(dereference) => {
+                uses.insert(Location::Memory);
+                walk(dereference.expression(), uses);
+            }
+        }
+    }
+
+    fn rvalue(rvalue: &ir::RValue<ir::Constant>, uses: &mut BTreeSet<Location>) {
+        match rvalue {
+            ir::RValue::Value(_) => {},
+            // Taking the address of a variable does not read it.
+            ir::RValue::Reference(reference) => {
+                if expression_variable(reference.expression()).is_none() {
+                    walk(reference.expression(), uses);
+                }
+            }
+        }
+    }
+
+    fn walk(
+        expression: &ir::Expression<ir::Constant>,
+        uses: &mut BTreeSet<Location>
+    ) {
+        match expression {
+            ir::Expression::LValue(l) => lvalue(l, uses),
+            ir::Expression::RValue(r) => rvalue(r, uses),
+            ir::Expression::Add(lhs, rhs) |
+            ir::Expression::Sub(lhs, rhs) |
+            ir::Expression::Mul(lhs, rhs) |
+            ir::Expression::Divu(lhs, rhs) |
+            ir::Expression::Modu(lhs, rhs) |
+            ir::Expression::Divs(lhs, rhs) |
+            ir::Expression::Mods(lhs, rhs) |
+            ir::Expression::And(lhs, rhs) |
+            ir::Expression::Or(lhs, rhs) |
+            ir::Expression::Xor(lhs, rhs) |
+            ir::Expression::Shl(lhs, rhs) |
+            ir::Expression::Shr(lhs, rhs) |
+            ir::Expression::Cmpeq(lhs, rhs) |
+            ir::Expression::Cmpneq(lhs, rhs) |
+            ir::Expression::Cmplts(lhs, rhs) |
+            ir::Expression::Cmpltu(lhs, rhs) => {
+                walk(lhs, uses);
+                walk(rhs, uses);
+            },
+            ir::Expression::Trun(_, rhs) |
+            ir::Expression::Sext(_, rhs) |
+            ir::Expression::Zext(_, rhs) => walk(rhs, uses),
+            ir::Expression::Ite(cond, then, else_) => {
+                walk(cond, uses);
+                walk(then, uses);
+                walk(else_, uses);
+            }
+        }
+    }
+
+    let mut uses = BTreeSet::new();
+    walk(expression, &mut uses);
+    uses
+}
+
+
+/// Locations read by `operation`.
+pub fn operation_uses(operation: &ir::Operation<ir::Constant>)
+    -> BTreeSet<Location> {
+
+    match operation {
+        ir::Operation::Assign { src, .. } => expression_uses(src),
+        ir::Operation::Store { index, src } => {
+            let mut uses = expression_uses(index);
+            uses.append(&mut expression_uses(src));
+            uses
+        },
+        ir::Operation::Load { index, .. } => {
+            let mut uses = expression_uses(index);
+            uses.insert(Location::Memory);
+            uses
+        },
+        ir::Operation::Branch { target } => expression_uses(target),
+        ir::Operation::Call(call) => {
+            let mut uses = BTreeSet::new();
+            if let ir::CallTarget::Expression(expression) = call.target() {
+                uses.append(&mut expression_uses(expression));
+            }
+            if let Some(arguments) = call.arguments() {
+                for argument in arguments {
+                    uses.append(&mut expression_uses(argument));
+                }
+            }
+            uses
+        },
+        ir::Operation::Intrinsic(intrinsic) => {
+            let mut uses = BTreeSet::new();
+            for expression in intrinsic.arguments() {
+                uses.append(&mut expression_uses(
+                    &ir::Expression::from_il(expression)));
+            }
+            if let Some(read_expressions) = intrinsic.read_expressions() {
+                for expression in read_expressions {
+                    uses.append(&mut expression_uses(
+                        &ir::Expression::from_il(expression)));
+                }
+            }
+            uses
+        },
+        ir::Operation::Return(result) =>
+            result.as_ref()
+                .map(|result| expression_uses(result))
+                .unwrap_or_default(),
+        ir::Operation::Nop => BTreeSet::new()
+    }
+}
+
+
+/// Locations written by `operation`.
+pub fn operation_defs(operation: &ir::Operation<ir::Constant>)
+    -> BTreeSet<Location> {
+
+    let mut defs = BTreeSet::new();
+
+    match operation {
+        ir::Operation::Assign { dst, .. } |
+        ir::Operation::Load { dst, .. } => {
+            defs.insert(Location::Variable(dst.clone()));
+        },
+        ir::Operation::Store { .. } => {
+            defs.insert(Location::Memory);
+        },
+        ir::Operation::Call(call) => {
+            if let Some(variables_written) = call.variables_written() {
+                for variable in variables_written {
+                    defs.insert(Location::Variable(variable.clone()));
+                }
+            }
+        },
+        ir::Operation::Intrinsic(intrinsic) => {
+            if let Some(written_expressions) = intrinsic.written_expressions() {
+                for expression in written_expressions {
+                    let expression = ir::Expression::from_il(expression);
+                    match expression_variable(&expression) {
+                        Some(variable) => {
+                            defs.insert(Location::Variable(variable.clone()));
+                        },
+                        None => {
+                            defs.insert(Location::Memory);
+                        }
+                    }
+                }
+            }
+        },
+        ir::Operation::Branch { .. } |
+        ir::Operation::Return(_) |
+        ir::Operation::Nop => {}
+    }
+
+    defs
+}
+
+
+/// Instructions of `block` in order, as locations within `function_index`.
+pub fn block_locations(
+    function_index: usize,
+    block: &ir::Block<ir::Constant>
+) -> Vec<InstructionLocation> {
+    block.instructions()
+        .into_iter()
+        .map(|instruction|
+            InstructionLocation::new(
+                function_index, block.index(), instruction.index()))
+        .collect()
+}
+
+
+/// Find the instruction at `location` in `function`.
+pub fn instruction<'f>(
+    function: &'f ir::Function<ir::Constant>,
+    location: &InstructionLocation
+) -> Option<&'f ir::Instruction<ir::Constant>> {
+    function.blocks()
+        .into_iter()
+        .find(|block| block.index() == location.block_index())
+        .and_then(|block|
+            block.instructions()
+                .into_iter()
+                .find(|instruction|
+                    instruction.index() == location.instruction_index()))
+}
+
+
+type Definitions = BTreeMap<Location, BTreeSet<InstructionLocation>>;
+
+
+/// Reaching definitions for every instruction of a function.
+///
+/// For each instruction this records, for every location, which
+/// instructions may have last written that location before the instruction
+/// executes. The reverse, which instructions read each definition, is
+/// indexed as well.
+pub struct ReachingDefinitions {
+    before: BTreeMap<InstructionLocation, Definitions>,
+    uses: BTreeMap<InstructionLocation, BTreeSet<Location>>,
+    defs: BTreeMap<InstructionLocation, BTreeSet<Location>>,
+    def_uses: BTreeMap<(InstructionLocation, Location), BTreeSet<InstructionLocation>>,
+    exposed_uses: BTreeMap<Location, BTreeSet<InstructionLocation>>
+}
+
+
+impl ReachingDefinitions {
+    /// `None` when `function` has no index, as its instructions then have
+    /// no location.
+    pub fn new(function: &ir::Function<ir::Constant>) -> Option<ReachingDefinitions> {
+        let function_index = function.index()?;
+        let cfg = Cfg::new(function);
+
+        let mut uses = BTreeMap::new();
+        let mut defs = BTreeMap::new();
+        let mut block_instructions = BTreeMap::new();
+
+        for block in function.blocks() {
+            let mut locations = Vec::new();
+            for instruction in block.instructions() {
+                let location = InstructionLocation::new(
+                    function_index, block.index(), instruction.index());
+                uses.insert(location, operation_uses(instruction.operation()));
+                defs.insert(location, operation_defs(instruction.operation()));
+                locations.push(location);
+            }
+            block_instructions.insert(block.index(), locations);
+        }
+
+        let transfer = |state: &mut Definitions, location: &InstructionLocation| {
+            for def in &defs[location] {
+                let entry = state.entry(def.clone()).or_insert_with(BTreeSet::new);
+                if *def != Location::Memory {
+                    entry.clear();
+                }
+                entry.insert(*location);
+            }
+        };
+
+        let mut block_in: BTreeMap<usize, Definitions> = BTreeMap::new();
+        let mut block_out: BTreeMap<usize, Definitions> = BTreeMap::new();
+
+        let order = cfg.reverse_post_order();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for index in &order {
+                let mut state = Definitions::new();
+                for predecessor in cfg.predecessors(*index) {
+                    if let Some(out) = block_out.get(predecessor) {
+                        for (location, sites) in out {
+                            state.entry(location.clone())
+                                .or_insert_with(BTreeSet::new)
+                                .extend(sites.iter().cloned());
+                        }
+                    }
+                }
+                block_in.insert(*index, state.clone());
+
+                if let Some(locations) = block_instructions.get(index) {
+                    for location in locations {
+                        transfer(&mut state, location);
+                    }
+                }
+
+                if block_out.get(index) != Some(&state) {
+                    block_out.insert(*index, state);
+                    changed = true;
+                }
+            }
+        }
+
+        let mut before = BTreeMap::new();
+        for (index, locations) in &block_instructions {
+            let mut state = block_in.remove(index).unwrap_or_default();
+            for location in locations {
+                before.insert(*location, state.clone());
+                transfer(&mut state, location);
+            }
+        }
+
+        let mut def_uses = BTreeMap::new();
+        let mut exposed_uses = BTreeMap::new();
+        for (instruction, definitions) in &before {
+            for location in &uses[instruction] {
+                match definitions.get(location) {
+                    Some(sites) if !sites.is_empty() =>
+                        for site in sites {
+                            def_uses.entry((*site, location.clone()))
+                                .or_insert_with(BTreeSet::new)
+                                .insert(*instruction);
+                        },
+                    _ => {
+                        exposed_uses.entry(location.clone())
+                            .or_insert_with(BTreeSet::new)
+                            .insert(*instruction);
+                    }
+                }
+            }
+        }
+
+        Some(ReachingDefinitions {
+            before: before,
+            uses: uses,
+            defs: defs,
+            def_uses: def_uses,
+            exposed_uses: exposed_uses
+        })
+    }
+
+    /// Definitions of `location` that reach `instruction`.
+    pub fn reaching(
+        &self,
+        instruction: &InstructionLocation,
+        location: &Location
+    ) -> BTreeSet<InstructionLocation> {
+        self.before
+            .get(instruction)
+            .and_then(|definitions| definitions.get(location))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Locations read by `instruction`.
+    pub fn uses(&self, instruction: &InstructionLocation) -> BTreeSet<Location> {
+        self.uses.get(instruction).cloned().unwrap_or_default()
+    }
+
+    /// Locations written by `instruction`.
+    pub fn defs(&self, instruction: &InstructionLocation) -> BTreeSet<Location> {
+        self.defs.get(instruction).cloned().unwrap_or_default()
+    }
+
+    /// Every instruction in the function.
+    pub fn instructions(&self) -> Vec<InstructionLocation> {
+        self.uses.keys().cloned().collect()
+    }
+
+    /// Instructions which read `location` as defined by `definition`.
+    pub fn uses_of(
+        &self,
+        definition: &InstructionLocation,
+        location: &Location
+    ) -> BTreeSet<InstructionLocation> {
+        self.def_uses
+            .get(&(*definition, location.clone()))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Instructions which read `location` with no definition in this
+    /// function reaching them.
+    pub fn exposed_uses_of(&self, location: &Location) -> BTreeSet<InstructionLocation> {
+        self.exposed_uses.get(location).cloned().unwrap_or_default()
+    }
+
+    /// Reads of a location for which no definition in this function reaches
+    /// the instruction. These are the function's inputs.
+    pub fn upward_exposed_uses(&self)
+        -> BTreeSet<(InstructionLocation, Location)> {
+
+        self.exposed_uses
+            .iter()
+            .flat_map(|(location, instructions)|
+                instructions.iter()
+                    .map(move |instruction| (*instruction, location.clone())))
+            .collect()
+    }
+}
@@ -0,0 +1,280 @@
+use analysis::cfg::Cfg;
+use std::collections::{BTreeMap, BTreeSet};
+
+
+/// The block index used for the virtual exit when computing post
+/// dominators. Every block without successors flows into it.
+const VIRTUAL_EXIT: usize = ::std::usize::MAX;
+
+
+/// Immediate dominators over an arbitrary graph, using the iterative
+/// algorithm of Cooper, Harvey and Kennedy. Blocks unreachable from `entry`
+/// are absent from the result, and `entry` is its own immediate dominator.
+fn immediate_dominators(
+    entry: usize,
+    successors: &BTreeMap<usize, BTreeSet<usize>>,
+    predecessors: &BTreeMap<usize, BTreeSet<usize>>
+) -> BTreeMap<usize, usize> {
+    // Reverse post order from the entry.
+    let mut order = Vec::new();
+    let mut visited = BTreeSet::new();
+    let mut stack: Vec<(usize, Vec<usize>)> = Vec::new();
+    let children = |index: usize| -> Vec<usize> {
+        successors.get(&index)
+            .map(|s| s.iter().cloned().collect())
+            .unwrap_or_default()
+    };
+    visited.insert(entry);
+    stack.push((entry, children(entry)));
+    while !stack.is_empty() {
+        let next = stack.last_mut().unwrap().1.pop();
+        match next {
+            Some(child) => {
+                if visited.insert(child) {
+                    stack.push((child, children(child)));
+                }
+            },
+            None => order.push(stack.pop().unwrap().0)
+        }
+    }
+    order.reverse();
+
+    let position: BTreeMap<usize, usize> =
+        order.iter().enumerate().map(|(i, index)| (*index, i)).collect();
+
+    let mut idom: BTreeMap<usize, usize> = BTreeMap::new();
+    idom.insert(entry, entry);
+
+    let intersect = |idom: &BTreeMap<usize, usize>, mut a: usize, mut b: usize| {
+        while a != b {
+            while position[&a] > position[&b] {
+                a = idom[&a];
+            }
+            while position[&b] > position[&a] {
+                b = idom[&b];
+            }
+        }
+        a
+    };
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for index in order.iter().skip(1) {
+            let mut new_idom: Option<usize> = None;
+            if let Some(preds) = predecessors.get(index) {
+                for pred in preds {
+                    if !idom.contains_key(pred) {
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                        None => *pred,
+                        Some(current) => intersect(&idom, *pred, current)
+                    });
+                }
+            }
+            if let Some(new_idom) = new_idom {
+                if idom.get(index) != Some(&new_idom) {
+                    idom.insert(*index, new_idom);
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    idom
+}
+
+
+/// Dominance frontiers from immediate dominators.
+fn dominance_frontiers(
+    idom: &BTreeMap<usize, usize>,
+    predecessors: &BTreeMap<usize, BTreeSet<usize>>
+) -> BTreeMap<usize, BTreeSet<usize>> {
+    let mut frontiers: BTreeMap<usize, BTreeSet<usize>> =
+        idom.keys().map(|index| (*index, BTreeSet::new())).collect();
+
+    for (index, dominator) in idom {
+        let preds: Vec<usize> =
+            predecessors.get(index)
+                .map(|preds|
+                    preds.iter()
+                        .filter(|pred| idom.contains_key(pred))
+                        .cloned()
+                        .collect())
+                .unwrap_or_default();
+        if preds.len() < 2 {
+            continue;
+        }
+        for pred in preds {
+            let mut runner = pred;
+            while runner != *dominator {
+                frontiers.get_mut(&runner).unwrap().insert(*index);
+                let next = idom[&runner];
+                if next == runner {
+                    break;
+                }
+                runner = next;
+            }
+        }
+    }
+
+    frontiers
+}
+
+
+/// Dominator and post-dominator trees of a function, with dominance
+/// frontiers for both.
+pub struct Dominators {
+    entry: Option<usize>,
+    idom: BTreeMap<usize, usize>,
+    ipdom: BTreeMap<usize, usize>,
+    frontiers: BTreeMap<usize, BTreeSet<usize>>,
+    post_frontiers: BTreeMap<usize, BTreeSet<usize>>
+}
+
+
+impl Dominators {
+    pub fn new(cfg: &Cfg) -> Dominators {
+        let mut successors = BTreeMap::new();
+        let mut predecessors = BTreeMap::new();
+        for index in cfg.blocks() {
+            successors.insert(*index, cfg.successors(*index).clone());
+            predecessors.insert(*index, cfg.predecessors(*index).clone());
+        }
+
+        let (idom, frontiers) = match cfg.entry() {
+            Some(entry) => {
+                let idom = immediate_dominators(entry, &successors, &predecessors);
+                let frontiers = dominance_frontiers(&idom, &predecessors);
+                (idom, frontiers)
+            },
+            None => (BTreeMap::new(), BTreeMap::new())
+        };
+
+        // Post dominators are dominators of the reversed graph, rooted at a
+        // virtual exit joined to every block without successors. Blocks
+        // which can't reach an exit, like the body of an infinite loop,
+        // have no post dominator.
+        let mut reversed_successors = predecessors.clone();
+        let mut reversed_predecessors = successors.clone();
+        let exits = cfg.exits();
+        reversed_successors.insert(VIRTUAL_EXIT, exits.clone());
+        reversed_predecessors.insert(VIRTUAL_EXIT, BTreeSet::new());
+        for exit in exits {
+            reversed_predecessors.get_mut(&exit).unwrap().insert(VIRTUAL_EXIT);
+        }
+
+        let ipdom = immediate_dominators(
+            VIRTUAL_EXIT, &reversed_successors, &reversed_predecessors);
+        let mut post_frontiers = dominance_frontiers(&ipdom, &reversed_predecessors);
+        post_frontiers.remove(&VIRTUAL_EXIT);
+        for frontier in post_frontiers.values_mut() {
+            frontier.remove(&VIRTUAL_EXIT);
+        }
+
+        Dominators {
+            entry: cfg.entry(),
+            idom: idom,
+            ipdom: ipdom,
+            frontiers: frontiers,
+            post_frontiers: post_frontiers
+        }
+    }
+
+    pub fn entry(&self) -> Option<usize> { self.entry }
+
+    /// The immediate dominator of `index`. `None` for the entry, and for
+    /// blocks unreachable from the entry.
+    pub fn immediate_dominator(&self, index: usize) -> Option<usize> {
+        self.idom.get(&index).cloned().filter(|idom| *idom != index)
+    }
+
+    /// The immediate post dominator of `index`. `None` when the block flows
+    /// straight out of the function, or can never leave it.
+    pub fn immediate_post_dominator(&self, index: usize) -> Option<usize> {
+        self.ipdom.get(&index).cloned().filter(|ipdom| *ipdom != VIRTUAL_EXIT)
+    }
+
+    /// Whether `a` dominates `b`. Every block dominates itself.
+    pub fn dominates(&self, a: usize, b: usize) -> bool {
+        let mut runner = b;
+        loop {
+            if runner == a {
+                return true;
+            }
+            match self.idom.get(&runner) {
+                Some(idom) if *idom != runner => runner = *idom,
+                _ => return false
+            }
+        }
+    }
+
+    pub fn frontier(&self, index: usize) -> Option<&BTreeSet<usize>> {
+        self.frontiers.get(&index)
+    }
+
+    pub fn post_frontier(&self, index: usize) -> Option<&BTreeSet<usize>> {
+        self.post_frontiers.get(&index)
+    }
+
+    /// Children of each block in the dominator tree.
+    pub fn tree(&self) -> BTreeMap<usize, BTreeSet<usize>> {
+        let mut tree: BTreeMap<usize, BTreeSet<usize>> =
+            self.idom.keys().map(|index| (*index, BTreeSet::new())).collect();
+        for (index, idom) in &self.idom {
+            if index != idom {
+                tree.get_mut(idom).unwrap().insert(*index);
+            }
+        }
+        tree
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use analysis::cfg::tests::function;
+    use super::*;
+
+    #[test]
+    fn diamond() {
+        // 0 branches to 1 and 2, which join at 3. 4 is unreachable.
+        let cfg = Cfg::new(&function(5, &[(0, 1), (0, 2), (1, 3), (2, 3), (4, 3)]));
+        let dominators = Dominators::new(&cfg);
+
+        assert_eq!(dominators.entry(), Some(0));
+        assert_eq!(dominators.immediate_dominator(0), None);
+        assert_eq!(dominators.immediate_dominator(1), Some(0));
+        assert_eq!(dominators.immediate_dominator(2), Some(0));
+        assert_eq!(dominators.immediate_dominator(3), Some(0));
+        assert_eq!(dominators.immediate_dominator(4), None);
+        assert!(dominators.dominates(0, 3));
+        assert!(dominators.dominates(3, 3));
+        assert!(!dominators.dominates(1, 3));
+
+        assert_eq!(dominators.immediate_post_dominator(0), Some(3));
+        assert_eq!(dominators.immediate_post_dominator(1), Some(3));
+        assert_eq!(dominators.immediate_post_dominator(3), None);
+
+        assert_eq!(dominators.frontier(1), Some(&vec![3].into_iter().collect()));
+        assert_eq!(dominators.frontier(0), Some(&BTreeSet::new()));
+        assert_eq!(dominators.post_frontier(1), Some(&vec![0].into_iter().collect()));
+
+        let tree = dominators.tree();
+        assert_eq!(tree[&0], vec![1, 2, 3].into_iter().collect());
+        assert!(tree[&3].is_empty());
+    }
+
+    #[test]
+    fn infinite_loop() {
+        // 1 and 2 loop forever, and never reach the exit at 3.
+        let cfg = Cfg::new(&function(4, &[(0, 1), (0, 3), (1, 2), (2, 1)]));
+        let dominators = Dominators::new(&cfg);
+
+        assert_eq!(dominators.immediate_dominator(2), Some(1));
+        assert_eq!(dominators.immediate_post_dominator(0), Some(3));
+        assert_eq!(dominators.immediate_post_dominator(1), None);
+        assert_eq!(dominators.immediate_post_dominator(2), None);
+    }
+}
@@ -0,0 +1,184 @@
+use raptor::ir;
+
+
+/// The direct subexpressions of `expression`, each with the name of the
+/// field holding it, as used by `translate::expression_to_json`.
+pub fn children(expression: &ir::Expression<ir::Constant>)
+    -> Vec<(&'static str, &ir::Expression<ir::Constant>)> {
+
+    fn lvalue_children(lvalue: &ir::LValue<ir::Constant>)
+        -> Vec<(&'static str, &ir::Expression<ir::Constant>)> {
+
+        match lvalue {
+            ir::LValue::Variable(_) => Vec::new(),
+            ir::LValue::Dereference(dereference) =>
+                vec![("expression", dereference.expression())]
+        }
+    }
+
+    fn rvalue_children(rvalue: &ir::RValue<ir::Constant>)
+        -> Vec<(&'static str, &ir::Expression<ir::Constant>)> {
+
+        match rvalue {
+            ir::RValue::Value(_) => Vec::new(),
+            ir::RValue::Reference(reference) =>
+                vec![("expression", reference.expression())]
+        }
+    }
+
+    match expression {
+        ir::Expression::LValue(lvalue) => lvalue_children(lvalue),
+        ir::Expression::RValue(rvalue) => rvalue_children(rvalue),
+        ir::Expression::Add(lhs, rhs) |
+        ir::Expression::Sub(lhs, rhs) |
+        ir::Expression::Mul(lhs, rhs) |
+        ir::Expression::Divu(lhs, rhs) |
+        ir::Expression::Modu(lhs, rhs) |
+        ir::Expression::Divs(lhs, rhs) |
+        ir::Expression::Mods(lhs, rhs) |
+        ir::Expression::And(lhs, rhs) |
+        ir::Expression::Or(lhs, rhs) |
+        ir::Expression::Xor(lhs, rhs) |
+        ir::Expression::Shl(lhs, rhs) |
+        ir::Expression::Shr(lhs, rhs) |
+        ir::Expression::Cmpeq(lhs, rhs) |
+        ir::Expression::Cmpneq(lhs, rhs) |
+        ir::Expression::Cmplts(lhs, rhs) |
+        ir::Expression::Cmpltu(lhs, rhs) => vec![("lhs", lhs), ("rhs", rhs)],
+        ir::Expression::Trun(_, rhs) |
+        ir::Expression::Sext(_, rhs) |
+        ir::Expression::Zext(_, rhs) => vec![("rhs", rhs)],
+        ir::Expression::Ite(cond, then, else_) =>
+            vec![("cond", cond), ("then", then), ("else", else_)]
+    }
+}
+
+
+/// Call `f` with `expression` and every subexpression beneath it, outermost
+/// first. Each call is given the path of field names leading from
+/// `expression` to the subexpression.
+pub fn walk<'e, F>(expression: &'e ir::Expression<ir::Constant>, f: &mut F)
+    where F: FnMut(&[&'static str], &'e ir::Expression<ir::Constant>) {
+
+    fn walk_path<'e, F>(
+        expression: &'e ir::Expression<ir::Constant>,
+        path: &mut Vec<&'static str>,
+        f: &mut F
+    ) where F: FnMut(&[&'static str], &'e ir::Expression<ir::Constant>) {
+        f(path, expression);
+        for (name, child) in children(expression) {
+            path.push(name);
+            walk_path(child, path, f);
+            path.pop();
+        }
+    }
+
+    walk_path(expression, &mut Vec::new(), f)
+}
+
+
+/// Every expression held directly by `operation`, each with the name of the
+/// field holding it, as used by `translate::operation_to_json`. Intrinsic
+/// expressions are lifted out of falcon IL, so everything here is owned.
+pub fn operation_expressions(operation: &ir::Operation<ir::Constant>)
+    -> Vec<(String, ir::Expression<ir::Constant>)> {
+
+    let mut expressions = Vec::new();
+
+    match operation {
+        ir::Operation::Assign { src, .. } => {
+            expressions.push(("src".to_string(), src.clone()));
+        },
+        ir::Operation::Store { index, src } => {
+            expressions.push(("index".to_string(), index.clone()));
+            expressions.push(("src".to_string(), src.clone()));
+        },
+        ir::Operation::Load { index, .. } => {
+            expressions.push(("index".to_string(), index.clone()));
+        },
+        ir::Operation::Branch { target } => {
+            expressions.push(("target".to_string(), target.clone()));
+        },
+        ir::Operation::Call(call) => {
+            if let ir::CallTarget::Expression(expression) = call.target() {
+                expressions.push(("target".to_string(), expression.clone()));
+            }
+            if let Some(arguments) = call.arguments() {
+                for (i, argument) in arguments.into_iter().enumerate() {
+                    expressions.push((format!("arguments/{}", i), argument.clone()));
+                }
+            }
+        },
+        ir::Operation::Intrinsic(intrinsic) => {
+            for (i, argument) in intrinsic.arguments().into_iter().enumerate() {
+                expressions.push((format!("arguments/{}", i),
+                                  ir::Expression::from_il(argument)));
+            }
+        },
+        ir::Operation::Return(result) => {
+            if let Some(result) = result {
+                expressions.push(("result".to_string(), result.clone()));
+            }
+        },
+        ir::Operation::Nop => {}
+    }
+
+    expressions
+}
+
+
+/// If `expression` is a constant, return it.
+pub fn constant(expression: &ir::Expression<ir::Constant>) -> Option<&ir::Constant> {
+    fn rvalue_constant(rvalue: &ir::RValue<ir::Constant>) -> Option<&ir::Constant> {
+        match rvalue {
+            ir::RValue::Value(constant) => Some(constant),
+            ir::RValue::Reference(_) => None
+        }
+    }
+
+    match expression {
+        ir::Expression::RValue(rvalue) => rvalue_constant(rvalue),
+        _ => None
+    }
+}
+
+
+/// If `expression` is a stack variable, return it.
+pub fn stack_variable(expression: &ir::Expression<ir::Constant>)
+    -> Option<&ir::StackVariable> {
+
+    match ::analysis::dataflow::expression_variable(expression) {
+        Some(ir::Variable::StackVariable(stack_variable)) => Some(stack_variable),
+        _ => None
+    }
+}
+
+
+/// Offsets of every stack variable referenced in `function`, including those
+/// only ever referenced by address.
+pub fn stack_offsets(function: &ir::Function<ir::Constant>) -> Vec<isize> {
+    let mut offsets = Vec::new();
+
+    for block in function.blocks() {
+        for instruction in block.instructions() {
+            let operation = instruction.operation();
+            match operation {
+                ir::Operation::Assign { dst: ir::Variable::StackVariable(sv), .. } |
+                ir::Operation::Load { dst: ir::Variable::StackVariable(sv), .. } =>
+                    offsets.push(sv.offset()),
+                _ => {}
+            }
+            for (_, expression) in operation_expressions(operation) {
+                walk(&expression, &mut |_, e| {
+                    if let Some(sv) = stack_variable(e) {
+                        offsets.push(sv.offset());
+                    }
+                });
+            }
+        }
+    }
+
+    offsets.sort();
+    offsets.dedup();
+    offsets
+}
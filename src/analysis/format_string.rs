@@ -0,0 +1,225 @@
+use analysis::dataflow::{self, Location};
+use analysis::slice::{Direction, Slicer};
+use analysis::values::Values;
+use analysis::InstructionLocation;
+use error::*;
+use falcon::loader::Loader;
+use falcon::memory::MemoryPermissions;
+use raptor::ir;
+use std::collections::BTreeSet;
+
+
+/// printf-family symbols, and the position of their format argument.
+pub const FORMAT_FUNCTIONS: &[(&str, usize)] = &[
+    ("printf", 0),
+    ("vprintf", 0),
+    ("wprintf", 0),
+    ("fprintf", 1),
+    ("vfprintf", 1),
+    ("dprintf", 1),
+    ("vdprintf", 1),
+    ("sprintf", 1),
+    ("vsprintf", 1),
+    ("asprintf", 1),
+    ("vasprintf", 1),
+    ("syslog", 1),
+    ("vsyslog", 1),
+    ("snprintf", 2),
+    ("vsnprintf", 2),
+    ("err", 1),
+    ("errx", 1),
+    ("warn", 0),
+    ("warnx", 0)
+];
+
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Reason {
+    /// The format is not a constant.
+    NonConstant,
+    /// The format is a constant address in writable memory.
+    Writable,
+    /// The format is a constant address outside the loaded image.
+    Unmapped
+}
+
+
+impl Reason {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Reason::NonConstant => "non-constant",
+            Reason::Writable => "writable",
+            Reason::Unmapped => "unmapped"
+        }
+    }
+}
+
+
+/// A call whose format argument may be controlled at runtime.
+pub struct FormatString {
+    symbol: String,
+    location: InstructionLocation,
+    argument: usize,
+    expression: ir::Expression<ir::Constant>,
+    address: Option<u64>,
+    reason: Reason,
+    parameter_influenced: bool
+}
+
+
+impl FormatString {
+    pub fn symbol(&self) -> &str { &self.symbol }
+    pub fn location(&self) -> &InstructionLocation { &self.location }
+    pub fn argument(&self) -> usize { self.argument }
+    pub fn expression(&self) -> &ir::Expression<ir::Constant> { &self.expression }
+
+    /// The address the format resolved to, when it is a constant.
+    pub fn address(&self) -> Option<u64> { self.address }
+    pub fn reason(&self) -> Reason { self.reason }
+
+    /// Whether the format's value depends on one of the function's inputs.
+    pub fn parameter_influenced(&self) -> bool { self.parameter_influenced }
+}
+
+
+/// Whether the value of `variables` at `location` depends on one of the
+/// function's parameters, passed in `argument_registers` or on the stack.
+fn parameter_influenced(
+    slicer: &mut Slicer,
+    values: &Values,
+    location: InstructionLocation,
+    variables: &[ir::Variable],
+    argument_registers: &[String]
+) -> Result<bool> {
+    let is_parameter = |location: &Location| match location.variable() {
+        Some(ir::Variable::Scalar(scalar)) =>
+            argument_registers.iter().any(|register| register == scalar.name()),
+        // Positive offsets are above the frame, where stack arguments live.
+        Some(ir::Variable::StackVariable(sv)) => sv.offset() > 0,
+        None => false
+    };
+
+    let rd = values.reaching_definitions();
+
+    for variable in variables {
+        let location_ = Location::Variable(variable.clone());
+        if rd.reaching(&location, &location_).is_empty() && is_parameter(&location_) {
+            return Ok(true);
+        }
+    }
+
+    let slice = slicer.slice(location, variables, Direction::Backward)?;
+
+    let exposed: BTreeSet<(InstructionLocation, Location)> =
+        rd.upward_exposed_uses();
+
+    Ok(exposed.iter()
+        .any(|&(ref instruction, ref used)|
+            *instruction != location &&
+            slice.locations().contains(instruction) &&
+            is_parameter(used)))
+}
+
+
+/// Find calls to printf-family symbols whose format argument is not a
+/// constant pointing into read-only memory of the loaded binary.
+pub fn format_strings(program: &ir::Program<ir::Constant>, loader: &Loader)
+    -> Result<Vec<FormatString>> {
+
+    let memory = loader.memory()?;
+    let argument_registers: Vec<String> =
+        loader.architecture()
+            .calling_convention()
+            .argument_registers()
+            .iter()
+            .map(|register| register.name().to_string())
+            .collect();
+
+    // Slices never leave the function, and the slicer keeps each
+    // function's reaching definitions between call sites.
+    let mut slicer = Slicer::new(program, false);
+    let mut results = Vec::new();
+
+    for function in program.functions() {
+        let function_index = match function.index() {
+            Some(index) => index,
+            None => continue
+        };
+
+        let mut values: Option<Values> = None;
+
+        for block in function.blocks() {
+            for location in dataflow::block_locations(function_index, block) {
+                let call =
+                    match dataflow::instruction(function, &location)
+                            .and_then(|instruction| instruction.operation().call()) {
+                        Some(call) => call,
+                        None => continue
+                    };
+                let symbol = match call.target().symbol() {
+                    Some(symbol) => symbol.to_string(),
+                    None => continue
+                };
+                let position =
+                    match FORMAT_FUNCTIONS.iter()
+                                          .find(|&&(name, _)| name == symbol) {
+                        Some(&(_, position)) => position,
+                        None => continue
+                    };
+                let argument =
+                    match call.arguments()
+                              .and_then(|arguments|
+                                  arguments.into_iter()
+                                      .nth(position)
+                                      .cloned()) {
+                        Some(argument) => argument,
+                        None => continue
+                    };
+
+                if values.is_none() {
+                    values = Values::new(function);
+                }
+                let values = match values.as_ref() {
+                    Some(values) => values,
+                    None => continue
+                };
+
+                let address = values.evaluate(&location, &argument);
+                let reason = match address {
+                    None => Reason::NonConstant,
+                    Some(address) => match memory.permissions(address) {
+                        None => Reason::Unmapped,
+                        Some(permissions) =>
+                            if permissions.contains(MemoryPermissions::WRITE) {
+                                Reason::Writable
+                            }
+                            else {
+                                continue
+                            }
+                    }
+                };
+
+                let variables: Vec<ir::Variable> =
+                    dataflow::expression_uses(&argument)
+                        .into_iter()
+                        .filter_map(|location| location.variable().cloned())
+                        .collect();
+
+                let parameter_influenced = parameter_influenced(
+                    &mut slicer, values, location, &variables, &argument_registers)?;
+
+                results.push(FormatString {
+                    symbol: symbol,
+                    location: location,
+                    argument: position,
+                    expression: argument,
+                    address: address,
+                    reason: reason,
+                    parameter_influenced: parameter_influenced
+                });
+            }
+        }
+    }
+
+    Ok(results)
+}
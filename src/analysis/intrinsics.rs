@@ -0,0 +1,135 @@
+//! How much of a program falcon could not model, as intrinsics.
+
+use analysis::InstructionLocation;
+use raptor::ir;
+use std::collections::{BTreeMap, BTreeSet};
+
+
+/// Every intrinsic with a given mnemonic.
+pub struct Mnemonic {
+    locations: Vec<InstructionLocation>,
+    instruction_strs: BTreeSet<String>
+}
+
+
+impl Mnemonic {
+    pub fn count(&self) -> usize { self.locations.len() }
+    pub fn locations(&self) -> &[InstructionLocation] { &self.locations }
+
+    /// Distinct disassembly of the instructions, such as `rdtsc` or
+    /// `cpuid`.
+    pub fn instruction_strs(&self) -> &BTreeSet<String> { &self.instruction_strs }
+}
+
+
+/// Machine instructions lifted, and how many of them became intrinsics.
+pub struct Coverage {
+    instructions: usize,
+    intrinsics: usize
+}
+
+
+impl Coverage {
+    /// Distinct addresses IR was lifted from.
+    pub fn instructions(&self) -> usize { self.instructions }
+
+    /// Distinct addresses lifted to an intrinsic.
+    pub fn intrinsics(&self) -> usize { self.intrinsics }
+
+    /// The fraction of instructions modeled, 1.0 when there are no
+    /// instructions.
+    pub fn modeled(&self) -> f64 {
+        if self.instructions == 0 {
+            1.0
+        }
+        else {
+            (self.instructions - self.intrinsics) as f64 / self.instructions as f64
+        }
+    }
+}
+
+
+/// Coverage of a single function.
+pub struct FunctionCoverage {
+    function_index: usize,
+    coverage: Coverage
+}
+
+
+impl FunctionCoverage {
+    pub fn function_index(&self) -> usize { self.function_index }
+    pub fn coverage(&self) -> &Coverage { &self.coverage }
+}
+
+
+pub struct Intrinsics {
+    mnemonics: BTreeMap<String, Mnemonic>,
+    coverage: Vec<FunctionCoverage>
+}
+
+
+impl Intrinsics {
+    pub fn mnemonics(&self) -> &BTreeMap<String, Mnemonic> { &self.mnemonics }
+    pub fn coverage(&self) -> &[FunctionCoverage] { &self.coverage }
+
+    /// Coverage over every function together.
+    pub fn total(&self) -> Coverage {
+        Coverage {
+            instructions: self.coverage.iter().map(|c| c.coverage.instructions).sum(),
+            intrinsics: self.coverage.iter().map(|c| c.coverage.intrinsics).sum()
+        }
+    }
+}
+
+
+pub fn intrinsics(program: &ir::Program<ir::Constant>) -> Intrinsics {
+    let mut mnemonics: BTreeMap<String, Mnemonic> = BTreeMap::new();
+    let mut coverage = Vec::new();
+
+    for function in program.functions() {
+        let function_index = match function.index() {
+            Some(index) => index,
+            None => continue
+        };
+
+        let mut addresses = BTreeSet::new();
+        let mut intrinsic_addresses = BTreeSet::new();
+
+        for block in function.blocks() {
+            for instruction in block.instructions() {
+                if let Some(address) = instruction.address() {
+                    addresses.insert(address);
+                }
+                let intrinsic = match instruction.operation() {
+                    ir::Operation::Intrinsic(intrinsic) => intrinsic,
+                    _ => continue
+                };
+                if let Some(address) = instruction.address() {
+                    intrinsic_addresses.insert(address);
+                }
+                let mnemonic =
+                    mnemonics.entry(intrinsic.mnemonic().to_string())
+                        .or_insert_with(|| Mnemonic {
+                            locations: Vec::new(),
+                            instruction_strs: BTreeSet::new()
+                        });
+                mnemonic.locations.push(InstructionLocation::new(
+                    function_index, block.index(), instruction.index()));
+                mnemonic.instruction_strs.insert(intrinsic.instruction_str().to_string());
+            }
+        }
+
+        coverage.push(FunctionCoverage {
+            function_index: function_index,
+            coverage: Coverage {
+                instructions: addresses.len(),
+                intrinsics: intrinsic_addresses.len()
+            }
+        });
+    }
+
+    Intrinsics {
+        mnemonics: mnemonics,
+        coverage: coverage
+    }
+}
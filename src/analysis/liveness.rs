@@ -0,0 +1,214 @@
+use analysis::cfg::Cfg;
+use analysis::dataflow;
+use analysis::InstructionLocation;
+use falcon::loader::Loader;
+use raptor::ir;
+use std::collections::{BTreeMap, BTreeSet};
+
+
+type Variables = BTreeSet<ir::Variable>;
+
+
+fn variables(locations: BTreeSet<dataflow::Location>) -> Variables {
+    locations.into_iter()
+        .filter_map(|location| location.variable().cloned())
+        .collect()
+}
+
+
+/// The register values are returned in under `loader`'s calling convention.
+pub fn return_register(loader: &Loader) -> ir::Variable {
+    let register = loader.architecture().calling_convention().return_register().clone();
+    ir::Variable::Scalar(ir::Scalar::new(register.name(), register.bits()))
+}
+
+
+/// Live variables at the boundaries of every block and instruction of a
+/// function.
+pub struct Liveness {
+    function_index: usize,
+    block_in: BTreeMap<usize, Variables>,
+    block_out: BTreeMap<usize, Variables>,
+    instruction_in: BTreeMap<InstructionLocation, Variables>,
+    instruction_out: BTreeMap<InstructionLocation, Variables>
+}
+
+
+impl Liveness {
+    /// `return_register` is live where the function returns without a
+    /// result, as the caller may read it. `None` when `function` has no
+    /// index, as its instructions then have no location.
+    pub fn new(function: &ir::Function<ir::Constant>, return_register: &ir::Variable)
+        -> Option<Liveness> {
+
+        let function_index = function.index()?;
+        let cfg = Cfg::new(function);
+
+        let mut block_instructions: BTreeMap<usize, Vec<(InstructionLocation, Variables, Variables)>> =
+            BTreeMap::new();
+        for block in function.blocks() {
+            block_instructions.insert(
+                block.index(),
+                block.instructions()
+                    .into_iter()
+                    .map(|instruction| {
+                        let mut uses =
+                            variables(dataflow::operation_uses(instruction.operation()));
+                        if let ir::Operation::Return(None) = instruction.operation() {
+                            uses.insert(return_register.clone());
+                        }
+                        (InstructionLocation::new(
+                            function_index, block.index(), instruction.index()),
+                         uses,
+                         variables(dataflow::operation_defs(instruction.operation())))
+                    })
+                    .collect());
+        }
+
+        let transfer = |live: &mut Variables, uses: &Variables, defs: &Variables| {
+            for variable in defs {
+                live.remove(variable);
+            }
+            live.extend(uses.iter().cloned());
+        };
+
+        let mut block_in: BTreeMap<usize, Variables> = BTreeMap::new();
+        let mut block_out: BTreeMap<usize, Variables> = BTreeMap::new();
+
+        // Visit in post order so most successors are done first.
+        let mut order = cfg.reverse_post_order();
+        order.reverse();
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for index in &order {
+                let mut live = Variables::new();
+                for successor in cfg.successors(*index) {
+                    if let Some(successor_in) = block_in.get(successor) {
+                        live.extend(successor_in.iter().cloned());
+                    }
+                }
+                block_out.insert(*index, live.clone());
+
+                if let Some(instructions) = block_instructions.get(index) {
+                    for &(_, ref uses, ref defs) in instructions.iter().rev() {
+                        transfer(&mut live, uses, defs);
+                    }
+                }
+
+                if block_in.get(index) != Some(&live) {
+                    block_in.insert(*index, live);
+                    changed = true;
+                }
+            }
+        }
+
+        let mut instruction_in = BTreeMap::new();
+        let mut instruction_out = BTreeMap::new();
+        for (index, instructions) in &block_instructions {
+            let mut live = block_out.get(index).cloned().unwrap_or_default();
+            for &(location, ref uses, ref defs) in instructions.iter().rev() {
+                instruction_out.insert(location, live.clone());
+                transfer(&mut live, uses, defs);
+                instruction_in.insert(location, live.clone());
+            }
+        }
+
+        Some(Liveness {
+            function_index: function_index,
+            block_in: block_in,
+            block_out: block_out,
+            instruction_in: instruction_in,
+            instruction_out: instruction_out
+        })
+    }
+
+    pub fn function_index(&self) -> usize { self.function_index }
+
+    pub fn block_in(&self, index: usize) -> Option<&Variables> {
+        self.block_in.get(&index)
+    }
+
+    pub fn block_out(&self, index: usize) -> Option<&Variables> {
+        self.block_out.get(&index)
+    }
+
+    pub fn instruction_in(&self, location: &InstructionLocation) -> Option<&Variables> {
+        self.instruction_in.get(location)
+    }
+
+    pub fn instruction_out(&self, location: &InstructionLocation) -> Option<&Variables> {
+        self.instruction_out.get(location)
+    }
+}
+
+
+/// A call whose result is never read.
+pub struct UnusedResult {
+    location: InstructionLocation,
+    target: String,
+    variables_written: Vec<ir::Variable>
+}
+
+
+impl UnusedResult {
+    pub fn location(&self) -> &InstructionLocation { &self.location }
+
+    /// The called symbol, or a description of the target when it isn't a
+    /// symbol.
+    pub fn target(&self) -> &str { &self.target }
+    pub fn variables_written(&self) -> &[ir::Variable] { &self.variables_written }
+}
+
+
+/// Calls in `function`, whose `liveness` is given, which write variables,
+/// none of which are live after the call.
+pub fn unused_results(function: &ir::Function<ir::Constant>, liveness: &Liveness)
+    -> Vec<UnusedResult> {
+
+    let function_index = liveness.function_index();
+    let mut unused = Vec::new();
+
+    for block in function.blocks() {
+        for instruction in block.instructions() {
+            let call = match instruction.operation().call() {
+                Some(call) => call,
+                None => continue
+            };
+            let variables_written: Vec<ir::Variable> =
+                match call.variables_written() {
+                    Some(variables_written) =>
+                        variables_written.into_iter().cloned().collect(),
+                    None => continue
+                };
+            if variables_written.is_empty() {
+                continue;
+            }
+
+            let location = InstructionLocation::new(
+                function_index, block.index(), instruction.index());
+            let live = match liveness.instruction_out(&location) {
+                Some(live) => live,
+                None => continue
+            };
+            if variables_written.iter().any(|variable| live.contains(variable)) {
+                continue;
+            }
+
+            let target = match call.target() {
+                ir::CallTarget::Symbol(symbol) => symbol.to_string(),
+                ir::CallTarget::FunctionId(function_id) => format!("id_{}", function_id),
+                ir::CallTarget::Expression(_) => "indirect".to_string()
+            };
+
+            unused.push(UnusedResult {
+                location: location,
+                target: target,
+                variables_written: variables_written
+            });
+        }
+    }
+
+    unused
+}
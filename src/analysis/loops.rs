@@ -0,0 +1,166 @@
+use analysis::cfg::Cfg;
+use analysis::dominators::Dominators;
+use std::collections::{BTreeMap, BTreeSet};
+
+
+/// A natural loop. Back edges sharing a header are merged into one loop.
+#[derive(Clone, Debug)]
+pub struct Loop {
+    header: usize,
+    blocks: BTreeSet<usize>,
+    back_edges: BTreeSet<(usize, usize)>,
+    exits: BTreeSet<(usize, usize)>,
+    depth: usize,
+    parent: Option<usize>
+}
+
+
+impl Loop {
+    pub fn header(&self) -> usize { self.header }
+    pub fn blocks(&self) -> &BTreeSet<usize> { &self.blocks }
+    pub fn back_edges(&self) -> &BTreeSet<(usize, usize)> { &self.back_edges }
+
+    /// Edges leaving the loop, from a block in the body to one outside it.
+    pub fn exits(&self) -> &BTreeSet<(usize, usize)> { &self.exits }
+
+    /// How many loops contain this one, counting itself. Outermost loops
+    /// have a depth of 1.
+    pub fn depth(&self) -> usize { self.depth }
+
+    /// The header of the innermost loop containing this one.
+    pub fn parent(&self) -> Option<usize> { self.parent }
+}
+
+
+/// Find the natural loops of a function, outermost first. Blocks the entry
+/// can't reach are not part of any loop.
+pub fn natural_loops(cfg: &Cfg, dominators: &Dominators) -> Vec<Loop> {
+    let mut bodies: BTreeMap<usize, (BTreeSet<usize>, BTreeSet<(usize, usize)>)> =
+        BTreeMap::new();
+
+    // Nothing dominates an unreachable block, so every edge out of one
+    // would otherwise pass for a back edge.
+    let reachable =
+        cfg.entry()
+            .map(|entry| cfg.reachable_from(entry))
+            .unwrap_or_default();
+
+    for tail in &reachable {
+        for header in cfg.successors(*tail) {
+            if !dominators.dominates(*header, *tail) {
+                continue;
+            }
+
+            let entry = bodies.entry(*header)
+                .or_insert_with(|| (BTreeSet::new(), BTreeSet::new()));
+            entry.1.insert((*tail, *header));
+
+            let body = &mut entry.0;
+            body.insert(*header);
+            let mut queue = vec![*tail];
+            while let Some(index) = queue.pop() {
+                if !body.insert(index) {
+                    continue;
+                }
+                for predecessor in cfg.predecessors(index) {
+                    if reachable.contains(predecessor) {
+                        queue.push(*predecessor);
+                    }
+                }
+            }
+        }
+    }
+
+    let mut loops: Vec<Loop> =
+        bodies.into_iter()
+            .map(|(header, (blocks, back_edges))| {
+                let mut exits = BTreeSet::new();
+                for index in &blocks {
+                    for successor in cfg.successors(*index) {
+                        if !blocks.contains(successor) {
+                            exits.insert((*index, *successor));
+                        }
+                    }
+                }
+                Loop {
+                    header: header,
+                    blocks: blocks,
+                    back_edges: back_edges,
+                    exits: exits,
+                    depth: 0,
+                    parent: None
+                }
+            })
+            .collect();
+
+    // Loops containing this loop's header contain the whole loop. The
+    // innermost of those is the one with the smallest body.
+    let containing: Vec<Vec<(usize, usize)>> =
+        loops.iter()
+            .map(|l|
+                loops.iter()
+                    .filter(|other|
+                        other.header != l.header &&
+                        other.blocks.contains(&l.header))
+                    .map(|other| (other.blocks.len(), other.header))
+                    .collect())
+            .collect();
+
+    for (l, containing) in loops.iter_mut().zip(containing.into_iter()) {
+        l.depth = containing.len() + 1;
+        l.parent = containing.iter().min().map(|&(_, header)| header);
+    }
+
+    loops.sort_by_key(|l| (l.depth, l.header));
+    loops
+}
+
+
+#[cfg(test)]
+mod tests {
+    use analysis::cfg::tests::function;
+    use super::*;
+
+    #[test]
+    fn nested() {
+        // 1 heads a loop through 3, holding a self loop at 2, and leaves to 4.
+        let cfg = Cfg::new(&function(5, &[(0, 1), (1, 2), (2, 2), (2, 3), (3, 1), (1, 4)]));
+        let loops = natural_loops(&cfg, &Dominators::new(&cfg));
+
+        assert_eq!(loops.len(), 2);
+
+        let outer = &loops[0];
+        assert_eq!(outer.header(), 1);
+        assert_eq!(outer.blocks(), &vec![1, 2, 3].into_iter().collect());
+        assert_eq!(outer.back_edges(), &vec![(3, 1)].into_iter().collect());
+        assert_eq!(outer.exits(), &vec![(1, 4)].into_iter().collect());
+        assert_eq!(outer.depth(), 1);
+        assert_eq!(outer.parent(), None);
+
+        let inner = &loops[1];
+        assert_eq!(inner.header(), 2);
+        assert_eq!(inner.blocks(), &vec![2].into_iter().collect());
+        assert_eq!(inner.back_edges(), &vec![(2, 2)].into_iter().collect());
+        assert_eq!(inner.exits(), &vec![(2, 3)].into_iter().collect());
+        assert_eq!(inner.depth(), 2);
+        assert_eq!(inner.parent(), Some(1));
+    }
+
+    #[test]
+    fn unreachable() {
+        // 3 can't be reached, but loops on itself and jumps into the loop
+        // at 1.
+        let cfg = Cfg::new(&function(4, &[(0, 1), (1, 2), (2, 1), (3, 2), (3, 3)]));
+        let loops = natural_loops(&cfg, &Dominators::new(&cfg));
+
+        assert_eq!(loops.len(), 1);
+        assert_eq!(loops[0].header(), 1);
+        assert_eq!(loops[0].blocks(), &vec![1, 2].into_iter().collect());
+    }
+
+    #[test]
+    fn no_loops() {
+        let cfg = Cfg::new(&function(4, &[(0, 1), (0, 2), (1, 3), (2, 3)]));
+        assert!(natural_loops(&cfg, &Dominators::new(&cfg)).is_empty());
+    }
+}
@@ -0,0 +1,245 @@
+//! Finding the instructions which read, write or point to a range of
+//! memory, such as a global.
+
+use analysis::constants::{self, Matcher};
+use analysis::expression;
+use analysis::values::Values;
+use analysis::InstructionLocation;
+use raptor::ir;
+
+
+/// An instruction reading or writing memory at a known address.
+#[derive(Clone)]
+pub struct Access {
+    location: InstructionLocation,
+    path: String,
+    address: u64,
+    index: ir::Expression<ir::Constant>
+}
+
+
+impl Access {
+    pub fn location(&self) -> &InstructionLocation { &self.location }
+
+    /// Fields leading from the operation to the index expression.
+    pub fn path(&self) -> &str { &self.path }
+
+    pub fn address(&self) -> u64 { self.address }
+
+    /// The index expression, as written in the instruction.
+    pub fn index(&self) -> &ir::Expression<ir::Constant> { &self.index }
+}
+
+
+pub struct MemoryAccesses {
+    readers: Vec<Access>,
+    writers: Vec<Access>,
+    references: Vec<constants::ConstantUse>
+}
+
+
+impl MemoryAccesses {
+    /// Loads, and dereferences in expressions.
+    pub fn readers(&self) -> &[Access] { &self.readers }
+
+    /// Stores.
+    pub fn writers(&self) -> &[Access] { &self.writers }
+
+    /// Constants pointing into the range, other than those used directly as
+    /// the index of a reader or writer.
+    pub fn references(&self) -> &[constants::ConstantUse] { &self.references }
+}
+
+
+/// If `expression` reads memory, the index it reads from.
+fn dereference(expression: &ir::Expression<ir::Constant>)
+    -> Option<&ir::Expression<ir::Constant>> {
+
+    fn lvalue_dereference(lvalue: &ir::LValue<ir::Constant>)
+        -> Option<&ir::Expression<ir::Constant>> {
+
+        match lvalue {
+            ir::LValue::Dereference(dereference) => Some(dereference.expression()),
+            ir::LValue::Variable(_) => None
+        }
+    }
+
+    match expression {
+        ir::Expression::LValue(lvalue) => lvalue_dereference(lvalue),
+        _ => None
+    }
+}
+
+
+impl MemoryAccesses {
+    fn new() -> MemoryAccesses {
+        MemoryAccesses {
+            readers: Vec::new(),
+            writers: Vec::new(),
+            references: Vec::new()
+        }
+    }
+
+    fn append(&mut self, other: &mut MemoryAccesses) {
+        self.readers.append(&mut other.readers);
+        self.writers.append(&mut other.writers);
+        self.references.append(&mut other.references);
+    }
+}
+
+
+/// Accesses of memory in `[start, end)` by `function`.
+pub fn function_memory_accesses(
+    function: &ir::Function<ir::Constant>,
+    start: u64,
+    end: u64
+) -> MemoryAccesses {
+    function_memory_accesses_in(function, &[(start, end)])
+        .pop()
+        .unwrap_or_else(MemoryAccesses::new)
+}
+
+
+/// Accesses of memory by `function` in each `[start, end)` of `ranges`, in
+/// the same order. Values are only computed once for all of the ranges.
+pub fn function_memory_accesses_in(
+    function: &ir::Function<ir::Constant>,
+    ranges: &[(u64, u64)]
+) -> Vec<MemoryAccesses> {
+    let mut accesses: Vec<MemoryAccesses> =
+        ranges.iter().map(|_| MemoryAccesses::new()).collect();
+
+    let function_index = match function.index() {
+        Some(index) => index,
+        None => return accesses
+    };
+    let ranges: Vec<(usize, u64, u64)> =
+        ranges.iter()
+            .enumerate()
+            .filter(|&(_, &(start, end))| end > start)
+            .map(|(i, &(start, end))| (i, start, end))
+            .collect();
+    if ranges.is_empty() {
+        return accesses;
+    }
+
+    let values = match Values::new(function) {
+        Some(values) => values,
+        None => return accesses
+    };
+    // Indices into `accesses` of every range holding `address`.
+    let containing = |address: u64| -> Vec<usize> {
+        ranges.iter()
+            .filter(|&&(_, start, end)| address >= start && address < end)
+            .map(|&(i, _, _)| i)
+            .collect()
+    };
+
+    for block in function.blocks() {
+        for instruction in block.instructions() {
+            let location = InstructionLocation::new(
+                function_index, block.index(), instruction.index());
+            let access = |path: String, index: &ir::Expression<ir::Constant>| {
+                values.evaluate(&location, index)
+                    .map(|address| Access {
+                        location: location,
+                        path: path,
+                        address: address,
+                        index: index.clone()
+                    })
+            };
+
+            let operation = instruction.operation();
+            match operation {
+                ir::Operation::Load { index, .. } => {
+                    if let Some(access) = access("index".to_string(), index) {
+                        for i in containing(access.address) {
+                            accesses[i].readers.push(access.clone());
+                        }
+                    }
+                },
+                ir::Operation::Store { index, .. } => {
+                    if let Some(access) = access("index".to_string(), index) {
+                        for i in containing(access.address) {
+                            accesses[i].writers.push(access.clone());
+                        }
+                    }
+                },
+                _ => {}
+            }
+
+            for (field, e) in expression::operation_expressions(operation) {
+                expression::walk(&e, &mut |path, e| {
+                    if let Some(index) = dereference(e) {
+                        let mut full_path = vec![field.as_str()];
+                        full_path.extend(path.iter().cloned());
+                        full_path.push("expression");
+                        if let Some(access) = access(full_path.join("/"), index) {
+                            for i in containing(access.address) {
+                                accesses[i].readers.push(access.clone());
+                            }
+                        }
+                    }
+                });
+            }
+        }
+    }
+
+    // Search once over the span of every range, then sort the constants
+    // into the ranges they point into.
+    let low = ranges.iter().map(|&(_, start, _)| start).min().unwrap();
+    let high = ranges.iter().map(|&(_, _, end)| end - 1).max().unwrap();
+    let constant_uses = constants::function_search(function, &Matcher::Range(low, high));
+
+    // Constants anywhere within a dereferenced index are part of that
+    // access, not references of their own.
+    let within = |path: &str, index_path: &str|
+        path == index_path || path.starts_with(&format!("{}/", index_path));
+    for constant_use in constant_uses {
+        let value = match constant_use.constant().value_u64() {
+            Some(value) => value,
+            None => continue
+        };
+        for i in containing(value) {
+            let direct =
+                accesses[i].readers.iter()
+                    .chain(accesses[i].writers.iter())
+                    .any(|access|
+                        access.location == *constant_use.location()
+                            && within(constant_use.path(), &access.path));
+            if !direct {
+                accesses[i].references.push(constant_use.clone());
+            }
+        }
+    }
+
+    accesses
+}
+
+
+/// Accesses of memory in `[start, end)` across `program`.
+pub fn memory_accesses(program: &ir::Program<ir::Constant>, start: u64, end: u64)
+    -> MemoryAccesses {
+
+    memory_accesses_in(program, &[(start, end)])
+        .pop()
+        .unwrap_or_else(MemoryAccesses::new)
+}
+
+
+/// Accesses of memory in each `[start, end)` of `ranges` across `program`,
+/// in the same order.
+pub fn memory_accesses_in(program: &ir::Program<ir::Constant>, ranges: &[(u64, u64)])
+    -> Vec<MemoryAccesses> {
+
+    let mut accesses: Vec<MemoryAccesses> =
+        ranges.iter().map(|_| MemoryAccesses::new()).collect();
+    for function in program.functions() {
+        let function_accesses = function_memory_accesses_in(function, ranges);
+        for (accesses, mut function_accesses) in
+                accesses.iter_mut().zip(function_accesses.into_iter()) {
+            accesses.append(&mut function_accesses);
+        }
+    }
+    accesses
+}
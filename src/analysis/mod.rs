@@ -0,0 +1,54 @@
+//! Analyses run over the raptor IR held by a `Document`.
+
+pub mod audit;
+pub mod callgraph;
+pub mod cfg;
+pub mod constants;
+pub mod crypto;
+pub mod dataflow;
+pub mod dominators;
+pub mod expression;
+pub mod format_string;
+pub mod intrinsics;
+pub mod liveness;
+pub mod loops;
+pub mod memory_accesses;
+pub mod paths;
+pub mod query;
+pub mod reachability;
+pub mod slice;
+pub mod smt;
+pub mod ssa;
+pub mod symbolic;
+pub mod syscalls;
+pub mod taint;
+pub mod values;
+
+
+/// An instruction in a program, identified by the index of its function, the
+/// index of its block, and the index of the instruction.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct InstructionLocation {
+    function_index: usize,
+    block_index: usize,
+    instruction_index: usize
+}
+
+
+impl InstructionLocation {
+    pub fn new(
+        function_index: usize,
+        block_index: usize,
+        instruction_index: usize
+    ) -> InstructionLocation {
+        InstructionLocation {
+            function_index: function_index,
+            block_index: block_index,
+            instruction_index: instruction_index
+        }
+    }
+
+    pub fn function_index(&self) -> usize { self.function_index }
+    pub fn block_index(&self) -> usize { self.block_index }
+    pub fn instruction_index(&self) -> usize { self.instruction_index }
+}
@@ -0,0 +1,141 @@
+//! Path enumeration over any graph given by a successor function, such as a
+//! function's CFG or a program's call graph.
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+
+/// Paths found between two nodes.
+#[derive(Clone, Debug)]
+pub struct Paths {
+    paths: Vec<Vec<usize>>,
+    truncated: bool
+}
+
+
+impl Paths {
+    pub fn paths(&self) -> &[Vec<usize>] { &self.paths }
+
+    /// Whether enumeration stopped at the limit, so more paths may exist.
+    pub fn truncated(&self) -> bool { self.truncated }
+
+    pub fn into_paths(self) -> Vec<Vec<usize>> { self.paths }
+}
+
+
+/// Paths from `start` to `target`, each passing through any node at most
+/// `max_visits` times. A `max_visits` of 1 gives acyclic paths, and higher
+/// values unroll loops. Paths end the first time they reach `target`, and at
+/// most `limit` are returned.
+pub fn enumerate<F>(
+    successors: F,
+    start: usize,
+    target: usize,
+    max_visits: usize,
+    limit: usize
+) -> Paths where F: Fn(usize) -> BTreeSet<usize> {
+    if start == target {
+        return Paths { paths: vec![vec![start]], truncated: false };
+    }
+
+    let mut paths = Vec::new();
+    let mut visits: BTreeMap<usize, usize> = BTreeMap::new();
+    let mut path = vec![start];
+    let mut stack: Vec<Vec<usize>> = vec![successors(start).into_iter().collect()];
+    visits.insert(start, 1);
+
+    while let Some(next) = stack.last_mut() {
+        match next.pop() {
+            Some(successor) => {
+                if successor == target {
+                    // Only a path past the limit means some were left out.
+                    if paths.len() >= limit {
+                        return Paths { paths: paths, truncated: true };
+                    }
+                    let mut found = path.clone();
+                    found.push(successor);
+                    paths.push(found);
+                    continue;
+                }
+                let count = visits.entry(successor).or_insert(0);
+                if *count >= max_visits {
+                    continue;
+                }
+                *count += 1;
+                path.push(successor);
+                stack.push(successors(successor).into_iter().collect());
+            },
+            None => {
+                stack.pop();
+                if let Some(index) = path.pop() {
+                    *visits.get_mut(&index).unwrap() -= 1;
+                }
+            }
+        }
+    }
+
+    Paths { paths: paths, truncated: false }
+}
+
+
+/// A shortest path from `start` to `target`, by number of edges.
+pub fn shortest<F>(successors: F, start: usize, target: usize) -> Option<Vec<usize>>
+    where F: Fn(usize) -> BTreeSet<usize> {
+
+    let mut parents: BTreeMap<usize, usize> = BTreeMap::new();
+    let mut queue = VecDeque::new();
+    queue.push_back(start);
+    parents.insert(start, start);
+
+    while let Some(index) = queue.pop_front() {
+        if index == target {
+            let mut path = vec![index];
+            let mut current = index;
+            while current != start {
+                current = parents[&current];
+                path.push(current);
+            }
+            path.reverse();
+            return Some(path);
+        }
+        for successor in successors(index) {
+            if !parents.contains_key(&successor) {
+                parents.insert(successor, index);
+                queue.push_back(successor);
+            }
+        }
+    }
+
+    None
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 0 branches to 1 and 2, which both go to 3.
+    fn diamond(index: usize) -> BTreeSet<usize> {
+        match index {
+            0 => vec![1, 2].into_iter().collect(),
+            1 | 2 => vec![3].into_iter().collect(),
+            _ => BTreeSet::new()
+        }
+    }
+
+    #[test]
+    fn enumerate_limit() {
+        let paths = enumerate(diamond, 0, 3, 1, 2);
+        assert_eq!(paths.paths().len(), 2);
+        assert!(!paths.truncated());
+
+        let paths = enumerate(diamond, 0, 3, 1, 1);
+        assert_eq!(paths.paths().len(), 1);
+        assert!(paths.truncated());
+    }
+
+    #[test]
+    fn shortest_path() {
+        assert_eq!(shortest(diamond, 0, 3).map(|path| path.len()), Some(3));
+        assert_eq!(shortest(diamond, 3, 0), None);
+    }
+}
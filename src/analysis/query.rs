@@ -0,0 +1,734 @@
+//! A small language for finding shapes of operations and expressions.
+//!
+//! A pattern is a head with optional arguments, such as
+//! `store(index: add(stack_var, *), src: call_result)`. Arguments are given
+//! by position or by field name, and any left out match anything. Besides
+//! heads, a pattern may be `*`, an integer, a quoted name, or a capture
+//! `$name`, optionally followed by `=pattern`. A capture used twice must
+//! bind equal values.
+//!
+//! Operation heads are `assign(dst, src)`, `store(index, src)`,
+//! `load(dst, index)`, `branch(target)`, `call(target, arg0, ...)`,
+//! `intrinsic(mnemonic, arg0, ...)`, `return(result)` and `nop`. Queries
+//! with any other head are matched against every subexpression.
+//!
+//! Expression heads are the names used by `translate::expression_to_json`,
+//! `add(lhs, rhs)` through `ite(cond, then, else)`, along with
+//! `deref(expression)`, `ref(expression)` and `const`. Variables match
+//! `var`, `scalar(name)`, `stack_var(offset)`, and `call_result` when some
+//! call's write reaches them.
+
+use analysis::dataflow::{self, Location, ReachingDefinitions};
+use analysis::expression;
+use analysis::InstructionLocation;
+use error::*;
+use raptor::ir;
+use std::collections::BTreeMap;
+
+
+const OPERATION_HEADS: &[&str] = &[
+    "assign", "store", "load", "branch", "call", "intrinsic", "return", "nop"
+];
+
+const EXPRESSION_HEADS: &[&str] = &[
+    "add", "sub", "mul", "divu", "modu", "divs", "mods", "and", "or", "xor",
+    "shl", "shr", "cmpeq", "cmpneq", "cmplts", "cmpltu", "trun", "sext",
+    "zext", "ite", "deref", "ref", "const", "var", "scalar", "stack_var",
+    "call_result"
+];
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Pattern {
+    Any,
+    Integer(i64),
+    Name(String),
+    Capture(String, Box<Pattern>),
+    Node {
+        head: String,
+        arguments: Vec<(Option<String>, Pattern)>
+    }
+}
+
+
+impl Pattern {
+    fn is_operation(&self) -> bool {
+        match *self {
+            Pattern::Node { ref head, .. } => OPERATION_HEADS.contains(&head.as_str()),
+            _ => false
+        }
+    }
+
+    fn uses_head(&self, name: &str) -> bool {
+        match *self {
+            Pattern::Capture(_, ref pattern) => pattern.uses_head(name),
+            Pattern::Node { ref head, ref arguments } =>
+                head == name ||
+                arguments.iter().any(|&(_, ref pattern)| pattern.uses_head(name)),
+            _ => false
+        }
+    }
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Capture(String),
+    Integer(i64),
+    Name(String),
+    Star,
+    Open,
+    Close,
+    Comma,
+    Colon,
+    Equals
+}
+
+
+fn tokenize(text: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    let word = |i: &mut usize| -> String {
+        let start = *i;
+        while *i < chars.len() && (chars[*i].is_alphanumeric() || chars[*i] == '_') {
+            *i += 1;
+        }
+        chars[start..*i].iter().collect()
+    };
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            ' ' | '\t' | '\n' | '\r' => i += 1,
+            '*' => { tokens.push(Token::Star); i += 1; },
+            '(' => { tokens.push(Token::Open); i += 1; },
+            ')' => { tokens.push(Token::Close); i += 1; },
+            ',' => { tokens.push(Token::Comma); i += 1; },
+            ':' => { tokens.push(Token::Colon); i += 1; },
+            '=' => { tokens.push(Token::Equals); i += 1; },
+            '$' => {
+                i += 1;
+                let name = word(&mut i);
+                if name.is_empty() {
+                    bail!("Expected a capture name at {}", i);
+                }
+                tokens.push(Token::Capture(name));
+            },
+            '"' => {
+                let start = i + 1;
+                let end =
+                    chars[start..].iter()
+                        .position(|c| *c == '"')
+                        .map(|end| start + end)
+                        .ok_or("Unterminated name")?;
+                tokens.push(Token::Name(chars[start..end].iter().collect()));
+                i = end + 1;
+            },
+            '-' | '0'..='9' => {
+                let negative = c == '-';
+                if negative {
+                    i += 1;
+                }
+                let digits = word(&mut i);
+                let value =
+                    if digits.starts_with("0x") {
+                        u64::from_str_radix(&digits[2..], 16)
+                            .map(|value| value as i64)
+                    }
+                    else {
+                        digits.parse::<i64>()
+                    }
+                    .map_err(|_| format!("Invalid integer {}", digits))?;
+                tokens.push(Token::Integer(if negative { value.wrapping_neg() } else { value }));
+            },
+            c if c.is_alphabetic() || c == '_' => tokens.push(Token::Ident(word(&mut i))),
+            c => bail!("Unexpected character {} at {}", c, i)
+        }
+    }
+
+    Ok(tokens)
+}
+
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize
+}
+
+
+impl Parser {
+    fn peek(&self, offset: usize) -> Option<&Token> {
+        self.tokens.get(self.position + offset)
+    }
+
+    fn next(&mut self) -> Result<Token> {
+        let token = self.peek(0).cloned().ok_or("Unexpected end of query")?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<()> {
+        let token = self.next()?;
+        if token != expected {
+            bail!("Expected {:?}, found {:?}", expected, token);
+        }
+        Ok(())
+    }
+
+    fn pattern(&mut self) -> Result<Pattern> {
+        match self.next()? {
+            Token::Star => Ok(Pattern::Any),
+            Token::Integer(value) => Ok(Pattern::Integer(value)),
+            Token::Name(name) => Ok(Pattern::Name(name)),
+            Token::Capture(name) => {
+                if self.peek(0) == Some(&Token::Equals) {
+                    self.position += 1;
+                    Ok(Pattern::Capture(name, Box::new(self.pattern()?)))
+                }
+                else {
+                    Ok(Pattern::Capture(name, Box::new(Pattern::Any)))
+                }
+            },
+            Token::Ident(head) => {
+                if !OPERATION_HEADS.contains(&head.as_str()) &&
+                   !EXPRESSION_HEADS.contains(&head.as_str()) {
+                    bail!("Unknown head {}", head);
+                }
+                let mut arguments = Vec::new();
+                if self.peek(0) == Some(&Token::Open) {
+                    self.position += 1;
+                    while self.peek(0) != Some(&Token::Close) {
+                        if !arguments.is_empty() {
+                            self.expect(Token::Comma)?;
+                        }
+                        let named = self.peek(1) == Some(&Token::Colon);
+                        let field =
+                            match self.peek(0).cloned() {
+                                Some(Token::Ident(field)) if named => {
+                                    self.position += 2;
+                                    Some(field)
+                                },
+                                _ => None
+                            };
+                        arguments.push((field, self.pattern()?));
+                    }
+                    self.expect(Token::Close)?;
+                }
+                Ok(Pattern::Node { head: head, arguments: arguments })
+            },
+            token => bail!("Unexpected {:?}", token)
+        }
+    }
+}
+
+
+/// Parse a query. Operation heads are only allowed at the top.
+pub fn parse(text: &str) -> Result<Pattern> {
+    let mut parser = Parser {
+        tokens: tokenize(text)?,
+        position: 0
+    };
+    let pattern = parser.pattern()?;
+    if let Some(token) = parser.peek(0) {
+        bail!("Unexpected {:?} after query", token);
+    }
+
+    fn nested_operation(pattern: &Pattern) -> bool {
+        match *pattern {
+            Pattern::Capture(_, ref pattern) =>
+                pattern.is_operation() || nested_operation(pattern),
+            Pattern::Node { ref arguments, .. } =>
+                arguments.iter().any(|&(_, ref pattern)|
+                    pattern.is_operation() || nested_operation(pattern)),
+            _ => false
+        }
+    }
+    if nested_operation(&pattern) {
+        bail!("Operations may only appear at the top of a query");
+    }
+
+    Ok(pattern)
+}
+
+
+/// A value bound by a capture.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Captured {
+    Expression(ir::Expression<ir::Constant>),
+    Variable(ir::Variable),
+    Name(String),
+    Integer(i64)
+}
+
+
+#[derive(Clone, Debug)]
+pub struct Capture {
+    path: String,
+    value: Captured
+}
+
+
+impl Capture {
+    /// Fields leading from the operation to the captured value.
+    pub fn path(&self) -> &str { &self.path }
+    pub fn value(&self) -> &Captured { &self.value }
+}
+
+
+pub struct QueryMatch {
+    location: InstructionLocation,
+    path: Option<String>,
+    captures: BTreeMap<String, Capture>
+}
+
+
+impl QueryMatch {
+    pub fn location(&self) -> &InstructionLocation { &self.location }
+
+    /// Fields leading from the operation to the matched expression, or
+    /// `None` when the query matched the operation itself.
+    pub fn path(&self) -> Option<&str> { self.path.as_ref().map(|path| path.as_str()) }
+
+    pub fn captures(&self) -> &BTreeMap<String, Capture> { &self.captures }
+}
+
+
+#[derive(Clone, Copy)]
+enum Term<'t> {
+    Expression(&'t ir::Expression<ir::Constant>),
+    Variable(&'t ir::Variable),
+    Name(&'t str),
+    Integer(i64)
+}
+
+
+impl<'t> Term<'t> {
+    fn captured(&self) -> Captured {
+        match *self {
+            Term::Expression(expression) => Captured::Expression(expression.clone()),
+            Term::Variable(variable) => Captured::Variable(variable.clone()),
+            Term::Name(name) => Captured::Name(name.to_string()),
+            Term::Integer(value) => Captured::Integer(value)
+        }
+    }
+
+    fn variable(&self) -> Option<&'t ir::Variable> {
+        match *self {
+            Term::Expression(expression) => dataflow::expression_variable(expression),
+            Term::Variable(variable) => Some(variable),
+            _ => None
+        }
+    }
+}
+
+
+fn expression_head(expression: &ir::Expression<ir::Constant>) -> &'static str {
+    fn lvalue_head(lvalue: &ir::LValue<ir::Constant>) -> &'static str {
+        match lvalue {
+            ir::LValue::Variable(_) => "var",
+            ir::LValue::Dereference(_) => "deref"
+        }
+    }
+
+    fn rvalue_head(rvalue: &ir::RValue<ir::Constant>) -> &'static str {
+        match rvalue {
+            ir::RValue::Value(_) => "const",
+            ir::RValue::Reference(_) => "ref"
+        }
+    }
+
+    match expression {
+        ir::Expression::LValue(lvalue) => lvalue_head(lvalue),
+        ir::Expression::RValue(rvalue) => rvalue_head(rvalue),
+        ir::Expression::Add(..) => "add",
+        ir::Expression::Sub(..) => "sub",
+        ir::Expression::Mul(..) => "mul",
+        ir::Expression::Divu(..) => "divu",
+        ir::Expression::Modu(..) => "modu",
+        ir::Expression::Divs(..) => "divs",
+        ir::Expression::Mods(..) => "mods",
+        ir::Expression::And(..) => "and",
+        ir::Expression::Or(..) => "or",
+        ir::Expression::Xor(..) => "xor",
+        ir::Expression::Shl(..) => "shl",
+        ir::Expression::Shr(..) => "shr",
+        ir::Expression::Cmpeq(..) => "cmpeq",
+        ir::Expression::Cmpneq(..) => "cmpneq",
+        ir::Expression::Cmplts(..) => "cmplts",
+        ir::Expression::Cmpltu(..) => "cmpltu",
+        ir::Expression::Trun(..) => "trun",
+        ir::Expression::Sext(..) => "sext",
+        ir::Expression::Zext(..) => "zext",
+        ir::Expression::Ite(..) => "ite"
+    }
+}
+
+
+fn join(path: &str, field: &str) -> String {
+    if path.is_empty() { field.to_string() } else { format!("{}/{}", path, field) }
+}
+
+
+/// The terms an operation holds, as (field, path, term). `expressions` are
+/// the operation's expressions from `expression::operation_expressions`.
+fn operation_terms<'t>(
+    operation: &'t ir::Operation<ir::Constant>,
+    expressions: &'t [(String, ir::Expression<ir::Constant>)]
+) -> (&'static str, Vec<(String, String, Term<'t>)>) {
+
+    let mut terms: Vec<(String, String, Term<'t>)> = Vec::new();
+
+    let head = match operation {
+        ir::Operation::Assign { dst, .. } => {
+            terms.push(("dst".to_string(), "dst".to_string(), Term::Variable(dst)));
+            "assign"
+        },
+        ir::Operation::Load { dst, .. } => {
+            terms.push(("dst".to_string(), "dst".to_string(), Term::Variable(dst)));
+            "load"
+        },
+        ir::Operation::Store { .. } => "store",
+        ir::Operation::Branch { .. } => "branch",
+        ir::Operation::Call(call) => {
+            match call.target() {
+                ir::CallTarget::Symbol(symbol) =>
+                    terms.push(("target".to_string(), "target".to_string(),
+                                Term::Name(symbol))),
+                ir::CallTarget::FunctionId(index) =>
+                    terms.push(("target".to_string(), "target".to_string(),
+                                Term::Integer(*index as i64))),
+                ir::CallTarget::Expression(_) => {}
+            }
+            "call"
+        },
+        ir::Operation::Intrinsic(intrinsic) => {
+            terms.push(("mnemonic".to_string(), "mnemonic".to_string(),
+                        Term::Name(intrinsic.mnemonic())));
+            "intrinsic"
+        },
+        ir::Operation::Return(_) => "return",
+        ir::Operation::Nop => "nop"
+    };
+
+    for (path, expression) in expressions {
+        let field =
+            if path.starts_with("arguments/") {
+                format!("arg{}", &path["arguments/".len()..])
+            }
+            else {
+                path.clone()
+            };
+        terms.push((field, path.clone(), Term::Expression(expression)));
+    }
+
+    (head, terms)
+}
+
+
+struct Matcher<'q, 'f> {
+    query: &'q Pattern,
+    function: &'f ir::Function<ir::Constant>,
+    reaching_definitions: Option<ReachingDefinitions>
+}
+
+
+impl<'q, 'f> Matcher<'q, 'f> {
+    fn call_result(&self, location: &InstructionLocation, variable: &ir::Variable) -> bool {
+        let reaching_definitions = match self.reaching_definitions {
+            Some(ref reaching_definitions) => reaching_definitions,
+            None => return false
+        };
+        reaching_definitions
+            .reaching(location, &Location::Variable(variable.clone()))
+            .iter()
+            .any(|definition|
+                dataflow::instruction(self.function, definition)
+                    .map(|instruction| instruction.operation().is_call())
+                    .unwrap_or(false))
+    }
+
+    /// The fields of `term` under `head`, or `None` if `term` isn't a
+    /// `head`.
+    fn children<'t>(
+        &self,
+        location: &InstructionLocation,
+        head: &str,
+        term: Term<'t>,
+        path: &str
+    ) -> Option<Vec<(String, String, Term<'t>)>> {
+        match head {
+            "var" => term.variable().map(|_| Vec::new()),
+            "call_result" =>
+                term.variable()
+                    .filter(|variable| self.call_result(location, variable))
+                    .map(|_| Vec::new()),
+            "scalar" => match term.variable() {
+                Some(ir::Variable::Scalar(scalar)) =>
+                    Some(vec![("name".to_string(), join(path, "name"),
+                               Term::Name(scalar.name()))]),
+                _ => None
+            },
+            "stack_var" => match term.variable() {
+                Some(ir::Variable::StackVariable(stack_variable)) =>
+                    Some(vec![("offset".to_string(), join(path, "offset"),
+                               Term::Integer(stack_variable.offset() as i64))]),
+                _ => None
+            },
+            head => match term {
+                Term::Expression(expression) if expression_head(expression) == head =>
+                    Some(expression::children(expression)
+                        .into_iter()
+                        .map(|(field, child)|
+                            (field.to_string(), join(path, field), Term::Expression(child)))
+                        .collect()),
+                _ => None
+            }
+        }
+    }
+
+    /// Match `arguments` against `children`, positional arguments taking
+    /// children in order.
+    fn arguments<'t>(
+        &self,
+        location: &InstructionLocation,
+        arguments: &[(Option<String>, Pattern)],
+        children: &[(String, String, Term<'t>)],
+        captures: &mut BTreeMap<String, Capture>
+    ) -> bool {
+        let mut position = 0;
+        for &(ref field, ref pattern) in arguments {
+            let child =
+                match *field {
+                    Some(ref field) => children.iter().find(|child| &child.0 == field),
+                    None => {
+                        position += 1;
+                        children.get(position - 1)
+                    }
+                };
+            let matched = match child {
+                Some(&(_, ref path, term)) =>
+                    self.term(location, pattern, term, path, captures),
+                None => false
+            };
+            if !matched {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn term<'t>(
+        &self,
+        location: &InstructionLocation,
+        pattern: &Pattern,
+        term: Term<'t>,
+        path: &str,
+        captures: &mut BTreeMap<String, Capture>
+    ) -> bool {
+        match *pattern {
+            Pattern::Any => true,
+            Pattern::Integer(value) => match term {
+                Term::Integer(integer) => integer == value,
+                Term::Expression(expression) =>
+                    expression::constant(expression)
+                        .map(|constant| {
+                            let value =
+                                if constant.bits() >= 64 {
+                                    value as u64
+                                }
+                                else {
+                                    (value as u64) & ((1u64 << constant.bits()) - 1)
+                                };
+                            constant.value_u64() == Some(value)
+                        })
+                        .unwrap_or(false),
+                _ => false
+            },
+            Pattern::Name(ref name) => match term {
+                Term::Name(term_name) => term_name == name.as_str(),
+                term => match term.variable() {
+                    Some(ir::Variable::Scalar(scalar)) => scalar.name() == name.as_str(),
+                    _ => false
+                }
+            },
+            Pattern::Capture(ref name, ref pattern) => {
+                if !self.term(location, pattern, term, path, captures) {
+                    return false;
+                }
+                let value = term.captured();
+                if let Some(capture) = captures.get(name) {
+                    return capture.value == value;
+                }
+                captures.insert(name.to_string(), Capture {
+                    path: path.to_string(),
+                    value: value
+                });
+                true
+            },
+            Pattern::Node { ref head, ref arguments } =>
+                match self.children(location, head, term, path) {
+                    Some(children) =>
+                        self.arguments(location, arguments, &children, captures),
+                    None => false
+                }
+        }
+    }
+
+    fn instruction(
+        &self,
+        location: InstructionLocation,
+        operation: &ir::Operation<ir::Constant>,
+        matches: &mut Vec<QueryMatch>
+    ) {
+        let expressions = expression::operation_expressions(operation);
+        let (head, terms) = operation_terms(operation, &expressions);
+
+        if let Pattern::Node { head: ref query_head, ref arguments } = *self.query {
+            if self.query.is_operation() {
+                let mut captures = BTreeMap::new();
+                if query_head == head &&
+                   self.arguments(&location, arguments, &terms, &mut captures) {
+                    matches.push(QueryMatch {
+                        location: location,
+                        path: None,
+                        captures: captures
+                    });
+                }
+                return;
+            }
+        }
+
+        let mut try_match = |path: &str, term: Term| {
+            let mut captures = BTreeMap::new();
+            if self.term(&location, self.query, term, path, &mut captures) {
+                matches.push(QueryMatch {
+                    location: location,
+                    path: Some(path.to_string()),
+                    captures: captures
+                });
+            }
+        };
+
+        for &(_, ref path, term) in &terms {
+            match term {
+                Term::Expression(e) =>
+                    expression::walk(e, &mut |subpath, e| {
+                        let mut full_path = path.clone();
+                        for field in subpath {
+                            full_path = join(&full_path, field);
+                        }
+                        try_match(&full_path, Term::Expression(e));
+                    }),
+                Term::Variable(_) => try_match(path, term),
+                _ => {}
+            }
+        }
+    }
+}
+
+
+/// Every match of `query` in `function`.
+pub fn function_matches(function: &ir::Function<ir::Constant>, query: &Pattern)
+    -> Vec<QueryMatch> {
+
+    let function_index = match function.index() {
+        Some(index) => index,
+        None => return Vec::new()
+    };
+
+    let matcher = Matcher {
+        query: query,
+        function: function,
+        reaching_definitions:
+            if query.uses_head("call_result") {
+                ReachingDefinitions::new(function)
+            }
+            else {
+                None
+            }
+    };
+
+    let mut matches = Vec::new();
+    for block in function.blocks() {
+        for instruction in block.instructions() {
+            let location = InstructionLocation::new(
+                function_index, block.index(), instruction.index());
+            matcher.instruction(location, instruction.operation(), &mut matches);
+        }
+    }
+    matches
+}
+
+
+/// Every match of `query` in `program`.
+pub fn matches(program: &ir::Program<ir::Constant>, query: &Pattern) -> Vec<QueryMatch> {
+    program.functions()
+        .into_iter()
+        .flat_map(|function| function_matches(function, query))
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(head: &str, arguments: Vec<(Option<&str>, Pattern)>) -> Pattern {
+        Pattern::Node {
+            head: head.to_string(),
+            arguments:
+                arguments.into_iter()
+                    .map(|(field, pattern)| (field.map(|field| field.to_string()), pattern))
+                    .collect()
+        }
+    }
+
+    #[test]
+    fn tokens() {
+        assert_eq!(
+            tokenize("add($x=*, -0x10) \"f\"").unwrap(),
+            vec![Token::Ident("add".to_string()), Token::Open,
+                 Token::Capture("x".to_string()), Token::Equals, Token::Star,
+                 Token::Comma, Token::Integer(-16), Token::Close,
+                 Token::Name("f".to_string())]);
+        assert!(tokenize("$").is_err());
+        assert!(tokenize("\"f").is_err());
+        assert!(tokenize("0xg").is_err());
+        assert!(tokenize("add(#)").is_err());
+    }
+
+    #[test]
+    fn parse_queries() {
+        assert_eq!(
+            parse("store(index: add(stack_var, *), src: call_result)").unwrap(),
+            node("store", vec![
+                (Some("index"), node("add", vec![
+                    (None, node("stack_var", vec![])),
+                    (None, Pattern::Any)])),
+                (Some("src"), node("call_result", vec![]))]));
+
+        assert_eq!(
+            parse("call(\"memcpy\", $dst, $n=const)").unwrap(),
+            node("call", vec![
+                (None, Pattern::Name("memcpy".to_string())),
+                (None, Pattern::Capture("dst".to_string(), Box::new(Pattern::Any))),
+                (None, Pattern::Capture("n".to_string(), Box::new(node("const", vec![]))))]));
+
+        assert_eq!(parse("nop").unwrap(), node("nop", vec![]));
+        assert_eq!(parse("42").unwrap(), Pattern::Integer(42));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(parse("").is_err());
+        assert!(parse("frobnicate(*)").is_err());
+        assert!(parse("add(*, *").is_err());
+        assert!(parse("add(* *)").is_err());
+        assert!(parse("add(*) *").is_err());
+        assert!(parse("add(load(*, *), *)").is_err());
+        assert!(parse("sub($x=nop, *)").is_err());
+    }
+}
@@ -0,0 +1,226 @@
+//! Which functions can run at all, starting from the ways into a binary.
+
+use analysis::callgraph::CallGraph;
+use analysis::cfg::Cfg;
+use analysis::expression;
+use elf::Elf;
+use error::*;
+use falcon::loader::Loader;
+use raptor::ir;
+use std::collections::{BTreeMap, BTreeSet};
+
+
+/// Symbols which bring outside data into a program.
+pub const INPUT_SOURCES: &[&str] = &[
+    "read", "pread", "readv", "recv", "recvfrom", "recvmsg",
+    "fread", "fgets", "fgetc", "getc", "getchar", "gets", "getline",
+    "getdelim", "scanf", "fscanf", "sscanf", "getenv"
+];
+
+
+/// Where to start looking for reachable functions.
+#[derive(Clone, Debug, Default)]
+pub struct Roots {
+    pub entry: bool,
+    pub exports: bool,
+    pub init_array: bool,
+    /// Indices of additional functions to start from.
+    pub functions: Vec<usize>
+}
+
+
+impl Roots {
+    /// The entry point, exports and `.init_array`.
+    pub fn all() -> Roots {
+        Roots {
+            entry: true,
+            exports: true,
+            init_array: true,
+            functions: Vec::new()
+        }
+    }
+}
+
+
+pub struct Reachability {
+    roots: BTreeSet<usize>,
+    reachable: BTreeSet<usize>,
+    unreachable: BTreeSet<usize>,
+    input_callers: BTreeSet<usize>,
+    input_paths: BTreeSet<usize>,
+    unreachable_blocks: BTreeMap<usize, BTreeSet<usize>>
+}
+
+
+impl Reachability {
+    pub fn roots(&self) -> &BTreeSet<usize> { &self.roots }
+    pub fn reachable(&self) -> &BTreeSet<usize> { &self.reachable }
+    pub fn unreachable(&self) -> &BTreeSet<usize> { &self.unreachable }
+
+    /// Functions calling an input source directly.
+    pub fn input_callers(&self) -> &BTreeSet<usize> { &self.input_callers }
+
+    /// Reachable functions on some call path from a root to an input
+    /// caller, including the input callers themselves.
+    pub fn input_paths(&self) -> &BTreeSet<usize> { &self.input_paths }
+
+    /// Blocks of each function which can't be reached from its entry.
+    /// Functions without any are left out.
+    pub fn unreachable_blocks(&self) -> &BTreeMap<usize, BTreeSet<usize>> {
+        &self.unreachable_blocks
+    }
+}
+
+
+/// Functions whose address appears as a constant in each function. These
+/// are likely callbacks or entries in function pointer tables, so we treat
+/// them as called.
+fn address_taken(
+    program: &ir::Program<ir::Constant>,
+    addresses: &BTreeMap<u64, usize>
+) -> BTreeMap<usize, BTreeSet<usize>> {
+    let mut taken = BTreeMap::new();
+
+    for function in program.functions() {
+        let function_index = match function.index() {
+            Some(index) => index,
+            None => continue
+        };
+        let mut targets = BTreeSet::new();
+        for block in function.blocks() {
+            for instruction in block.instructions() {
+                for (_, e) in expression::operation_expressions(instruction.operation()) {
+                    expression::walk(&e, &mut |_, e| {
+                        let target =
+                            expression::constant(e)
+                                .and_then(|constant| constant.value_u64())
+                                .and_then(|value| addresses.get(&value));
+                        if let Some(target) = target {
+                            if *target != function_index {
+                                targets.insert(*target);
+                            }
+                        }
+                    });
+                }
+            }
+        }
+        taken.insert(function_index, targets);
+    }
+
+    taken
+}
+
+
+pub fn reachability(
+    program: &ir::Program<ir::Constant>,
+    loader: &Loader,
+    bytes: &[u8],
+    roots: &Roots,
+    input_sources: &[String]
+) -> Result<Reachability> {
+    let addresses: BTreeMap<u64, usize> =
+        program.functions()
+            .into_iter()
+            .filter_map(|function|
+                function.index().map(|index| (function.address(), index)))
+            .collect();
+
+    let mut root_addresses = Vec::new();
+    if roots.entry {
+        root_addresses.push(loader.program_entry());
+    }
+    if roots.exports {
+        for symbol in loader.symbols() {
+            root_addresses.push(symbol.address());
+        }
+    }
+    if roots.init_array {
+        let elf = Elf::parse(bytes)?;
+        root_addresses.append(&mut elf.pointers(".init_array")?);
+        root_addresses.append(&mut elf.pointers(".preinit_array")?);
+    }
+
+    let mut root_functions: BTreeSet<usize> =
+        root_addresses.into_iter()
+            .filter_map(|address| addresses.get(&address).cloned())
+            .collect();
+    root_functions.extend(roots.functions.iter().cloned());
+
+    let call_graph = CallGraph::new(program);
+    let taken = address_taken(program, &addresses);
+    let successors = |index: usize| -> BTreeSet<usize> {
+        let mut successors = call_graph.callees(index);
+        if let Some(taken) = taken.get(&index) {
+            successors.extend(taken.iter().cloned());
+        }
+        successors
+    };
+
+    let mut reachable = BTreeSet::new();
+    let mut queue: Vec<usize> = root_functions.iter().cloned().collect();
+    while let Some(index) = queue.pop() {
+        if reachable.insert(index) {
+            queue.extend(successors(index));
+        }
+    }
+
+    let unreachable: BTreeSet<usize> =
+        call_graph.functions()
+            .difference(&reachable)
+            .cloned()
+            .collect();
+
+    let mut input_callers = BTreeSet::new();
+    for source in input_sources {
+        input_callers.extend(call_graph.symbol_callers(source));
+    }
+
+    // Walk callers back from the input callers, staying within what's
+    // reachable.
+    let mut predecessors: BTreeMap<usize, BTreeSet<usize>> = BTreeMap::new();
+    for index in &reachable {
+        for successor in successors(*index) {
+            predecessors.entry(successor)
+                .or_insert_with(BTreeSet::new)
+                .insert(*index);
+        }
+    }
+    let mut input_paths = BTreeSet::new();
+    let mut queue: Vec<usize> =
+        input_callers.intersection(&reachable).cloned().collect();
+    while let Some(index) = queue.pop() {
+        if !input_paths.insert(index) {
+            continue;
+        }
+        if let Some(predecessors) = predecessors.get(&index) {
+            queue.extend(predecessors.iter().cloned());
+        }
+    }
+
+    let mut unreachable_blocks = BTreeMap::new();
+    for function in program.functions() {
+        let function_index = match function.index() {
+            Some(index) => index,
+            None => continue
+        };
+        let cfg = Cfg::new(function);
+        let entry = match cfg.entry() {
+            Some(entry) => entry,
+            None => continue
+        };
+        let live = cfg.reachable_from(entry);
+        let dead: BTreeSet<usize> = cfg.blocks().difference(&live).cloned().collect();
+        if !dead.is_empty() {
+            unreachable_blocks.insert(function_index, dead);
+        }
+    }
+
+    Ok(Reachability {
+        roots: root_functions,
+        reachable: reachable,
+        unreachable: unreachable,
+        input_callers: input_callers,
+        input_paths: input_paths,
+        unreachable_blocks: unreachable_blocks
+    })
+}
@@ -0,0 +1,288 @@
+use analysis::cfg::Cfg;
+use analysis::dataflow::{self, Location, ReachingDefinitions};
+use analysis::InstructionLocation;
+use error::*;
+use raptor::ir;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Direction {
+    Backward,
+    Forward
+}
+
+
+/// The result of slicing: the instructions in the slice, and for each
+/// function touched, the CFG pruned down to the blocks holding those
+/// instructions.
+pub struct Slice {
+    locations: BTreeSet<InstructionLocation>,
+    edges: BTreeMap<usize, BTreeSet<(usize, usize)>>
+}
+
+
+impl Slice {
+    pub fn locations(&self) -> &BTreeSet<InstructionLocation> { &self.locations }
+
+    /// Function indices touched by this slice.
+    pub fn functions(&self) -> Vec<usize> {
+        self.edges.keys().cloned().collect()
+    }
+
+    /// Blocks of `function_index` kept in the pruned CFG, with the indices of
+    /// the sliced instructions in each.
+    pub fn blocks(&self, function_index: usize) -> BTreeMap<usize, Vec<usize>> {
+        let mut blocks = BTreeMap::new();
+        for location in &self.locations {
+            if location.function_index() == function_index {
+                blocks.entry(location.block_index())
+                    .or_insert_with(Vec::new)
+                    .push(location.instruction_index());
+            }
+        }
+        blocks
+    }
+
+    /// Edges of the pruned CFG of `function_index`.
+    pub fn edges(&self, function_index: usize) -> Option<&BTreeSet<(usize, usize)>> {
+        self.edges.get(&function_index)
+    }
+}
+
+
+/// Computes slices over a program, caching per-function reaching definitions
+/// as it goes.
+pub struct Slicer<'p> {
+    program: &'p ir::Program<ir::Constant>,
+    interprocedural: bool,
+    reaching_definitions: HashMap<usize, ReachingDefinitions>
+}
+
+
+impl<'p> Slicer<'p> {
+    pub fn new(program: &'p ir::Program<ir::Constant>, interprocedural: bool)
+        -> Slicer<'p> {
+
+        Slicer {
+            program: program,
+            interprocedural: interprocedural,
+            reaching_definitions: HashMap::new()
+        }
+    }
+
+    fn function(&self, index: usize) -> Result<&'p ir::Function<ir::Constant>> {
+        self.program
+            .function(index)
+            .ok_or(format!("Could not find function-index: {}", index).into())
+    }
+
+    fn reaching_definitions(&mut self, function_index: usize)
+        -> Result<&ReachingDefinitions> {
+
+        if !self.reaching_definitions.contains_key(&function_index) {
+            let function = self.function(function_index)?;
+            let reaching_definitions =
+                ReachingDefinitions::new(function)
+                    .ok_or(format!("Function {} has no index", function_index))?;
+            self.reaching_definitions.insert(function_index, reaching_definitions);
+        }
+        Ok(&self.reaching_definitions[&function_index])
+    }
+
+    /// If the instruction at `location` calls another function by id, return
+    /// the callee's index.
+    fn callee(&self, location: &InstructionLocation) -> Result<Option<usize>> {
+        let function = self.function(location.function_index())?;
+        Ok(dataflow::instruction(function, location)
+            .and_then(|instruction| instruction.operation().call())
+            .and_then(|call| match call.target() {
+                ir::CallTarget::FunctionId(function_id) => Some(*function_id),
+                _ => None
+            }))
+    }
+
+    /// Slice from the value of `variables` at the instruction `criterion`.
+    pub fn slice(
+        &mut self,
+        criterion: InstructionLocation,
+        variables: &[ir::Variable],
+        direction: Direction
+    ) -> Result<Slice> {
+        let function = self.function(criterion.function_index())?;
+
+        if dataflow::instruction(function, &criterion).is_none() {
+            bail!("Could not find instruction {}:{} in function {}",
+                criterion.block_index(),
+                criterion.instruction_index(),
+                criterion.function_index());
+        }
+
+        let locations: Vec<Location> =
+            variables.iter()
+                .map(|variable| Location::Variable(variable.clone()))
+                .collect();
+
+        let mut slice = BTreeSet::new();
+        slice.insert(criterion);
+
+        match direction {
+            Direction::Backward => {
+                let mut seeds = BTreeSet::new();
+                {
+                    let rd = self.reaching_definitions(criterion.function_index())?;
+                    for location in &locations {
+                        seeds.append(&mut rd.reaching(&criterion, location));
+                    }
+                }
+                self.backward(seeds, &mut slice)?;
+            },
+            Direction::Forward => {
+                let mut seeds = BTreeSet::new();
+                {
+                    let rd = self.reaching_definitions(criterion.function_index())?;
+                    let defs = rd.defs(&criterion);
+                    for location in &locations {
+                        if defs.contains(location) {
+                            seeds.insert((criterion, location.clone()));
+                        }
+                        else {
+                            for definition in rd.reaching(&criterion, location) {
+                                seeds.insert((definition, location.clone()));
+                            }
+                        }
+                    }
+                }
+                self.forward(seeds, &mut slice)?;
+            }
+        }
+
+        let mut edges = BTreeMap::new();
+        let function_indices: BTreeSet<usize> =
+            slice.iter().map(|location| location.function_index()).collect();
+        for function_index in function_indices {
+            let keep: BTreeSet<usize> =
+                slice.iter()
+                    .filter(|location| location.function_index() == function_index)
+                    .map(|location| location.block_index())
+                    .collect();
+            let cfg = Cfg::new(self.function(function_index)?);
+            edges.insert(function_index, cfg.collapse(&keep));
+        }
+
+        Ok(Slice {
+            locations: slice,
+            edges: edges
+        })
+    }
+
+    fn backward(
+        &mut self,
+        seeds: BTreeSet<InstructionLocation>,
+        slice: &mut BTreeSet<InstructionLocation>
+    ) -> Result<()> {
+        let mut queue: Vec<InstructionLocation> = seeds.into_iter().collect();
+
+        while let Some(instruction) = queue.pop() {
+            if !slice.insert(instruction) {
+                continue;
+            }
+
+            let mut next = Vec::new();
+            {
+                let rd = self.reaching_definitions(instruction.function_index())?;
+                for location in rd.uses(&instruction) {
+                    next.extend(rd.reaching(&instruction, &location));
+                }
+            }
+
+            // A call to another function defines its results in the callee's
+            // return instructions, so keep slicing from there.
+            if self.interprocedural {
+                if let Some(callee) = self.callee(&instruction)? {
+                    let written = self.reaching_definitions(
+                        instruction.function_index())?.defs(&instruction);
+                    let returns = self.returns(callee)?;
+                    let rd = self.reaching_definitions(callee)?;
+                    for ret in returns {
+                        next.push(ret);
+                        for location in &written {
+                            next.extend(rd.reaching(&ret, location));
+                        }
+                    }
+                }
+            }
+
+            queue.extend(next);
+        }
+
+        Ok(())
+    }
+
+    fn forward(
+        &mut self,
+        seeds: BTreeSet<(InstructionLocation, Location)>,
+        slice: &mut BTreeSet<InstructionLocation>
+    ) -> Result<()> {
+        let mut queue: Vec<(InstructionLocation, Location)> =
+            seeds.into_iter().collect();
+        let mut visited = BTreeSet::new();
+
+        while let Some((definition, location)) = queue.pop() {
+            if !visited.insert((definition, location.clone())) {
+                continue;
+            }
+            slice.insert(definition);
+
+            let uses = self.reaching_definitions(definition.function_index())?
+                .uses_of(&definition, &location);
+
+            for instruction in uses {
+                slice.insert(instruction);
+                let defs = self.reaching_definitions(
+                    instruction.function_index())?.defs(&instruction);
+                for def in defs {
+                    queue.push((instruction, def));
+                }
+
+                // Follow the value into the callee. Arguments are passed in
+                // registers and stack slots we can't name on the callee's
+                // side, so we seed from every input of the callee that
+                // shares a variable with the affected location.
+                if self.interprocedural {
+                    if let Some(callee) = self.callee(&instruction)? {
+                        let exposed = self.reaching_definitions(callee)?
+                            .upward_exposed_uses();
+                        for (use_, used) in exposed {
+                            if used == location {
+                                slice.insert(use_);
+                                let defs = self.reaching_definitions(callee)?
+                                    .defs(&use_);
+                                for def in defs {
+                                    queue.push((use_, def));
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn returns(&mut self, function_index: usize) -> Result<Vec<InstructionLocation>> {
+        let function = self.function(function_index)?;
+        Ok(function.blocks()
+            .into_iter()
+            .flat_map(|block| dataflow::block_locations(function_index, block))
+            .filter(|location|
+                dataflow::instruction(function, location)
+                    .map(|instruction| match instruction.operation() {
+                        ir::Operation::Return(_) => true,
+                        _ => false
+                    })
+                    .unwrap_or(false))
+            .collect())
+    }
+}
@@ -0,0 +1,520 @@
+//! SMT-LIB 2 encoding of raptor IR, and a way to ask a local solver about
+//! it.
+//!
+//! Variables are encoded in SSA style, a fresh constant each time they are
+//! written, and memory as an array from addresses to bytes.
+
+use analysis::dataflow;
+use analysis::values;
+use error::*;
+use raptor::ir;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+
+fn variable_bits(variable: &ir::Variable) -> usize {
+    match variable {
+        ir::Variable::Scalar(scalar) => scalar.bits(),
+        ir::Variable::StackVariable(stack_variable) => stack_variable.bits()
+    }
+}
+
+
+fn variable_name(variable: &ir::Variable) -> String {
+    match variable {
+        ir::Variable::Scalar(scalar) =>
+            format!("{}:{}", scalar.name(), scalar.bits()),
+        ir::Variable::StackVariable(stack_variable) =>
+            format!("stack{:+}:{}", stack_variable.offset(), stack_variable.bits())
+    }
+}
+
+
+fn sort(bits: usize) -> String {
+    format!("(_ BitVec {})", bits)
+}
+
+
+fn constant(value: u64, bits: usize) -> String {
+    format!("(_ bv{} {})", value, bits)
+}
+
+
+fn boolean(term: &str) -> String {
+    format!("(= {} #b1)", term)
+}
+
+
+/// Builds up the SMT-LIB encoding of a sequence of operations.
+pub struct Encoder {
+    address_bits: usize,
+    big_endian: bool,
+    versions: BTreeMap<ir::Variable, usize>,
+    memory_version: usize,
+    references: BTreeMap<ir::Variable, String>,
+    fresh: usize,
+    declarations: Vec<String>,
+    assertions: Vec<String>,
+    inputs: BTreeMap<String, ir::Variable>,
+    constant_reads: BTreeSet<(u64, usize)>
+}
+
+
+impl Encoder {
+    pub fn new(address_bits: usize, big_endian: bool) -> Encoder {
+        let mut encoder = Encoder {
+            address_bits: address_bits,
+            big_endian: big_endian,
+            versions: BTreeMap::new(),
+            memory_version: 0,
+            references: BTreeMap::new(),
+            fresh: 0,
+            declarations: Vec::new(),
+            assertions: Vec::new(),
+            inputs: BTreeMap::new(),
+            constant_reads: BTreeSet::new()
+        };
+        let memory = encoder.memory();
+        encoder.declare(&memory,
+            &format!("(Array {} (_ BitVec 8))", sort(address_bits)));
+        encoder
+    }
+
+    fn declare(&mut self, symbol: &str, sort: &str) {
+        self.declarations.push(format!("(declare-const {} {})", symbol, sort));
+    }
+
+    fn fresh(&mut self, prefix: &str, bits: usize) -> String {
+        self.fresh += 1;
+        let symbol = format!("|{}#{}|", prefix, self.fresh);
+        self.declare(&symbol, &sort(bits));
+        symbol
+    }
+
+    /// The symbol holding the current contents of memory.
+    pub fn memory(&self) -> String {
+        format!("|memory@{}|", self.memory_version)
+    }
+
+    /// The symbol for memory on entry, which the caller may constrain.
+    pub fn initial_memory(&self) -> String {
+        "|memory@0|".to_string()
+    }
+
+    /// The symbol holding the current value of `variable`. Variables read
+    /// before being written are inputs.
+    pub fn variable(&mut self, variable: &ir::Variable) -> String {
+        if let Some(version) = self.versions.get(variable) {
+            return format!("|{}@{}|", variable_name(variable), version);
+        }
+        let symbol = format!("|{}@0|", variable_name(variable));
+        self.declare(&symbol, &sort(variable_bits(variable)));
+        self.versions.insert(variable.clone(), 0);
+        self.inputs.insert(symbol.clone(), variable.clone());
+        symbol
+    }
+
+    /// Start a new version of `variable`, returning its symbol.
+    pub fn define(&mut self, variable: &ir::Variable) -> String {
+        // Make sure version 0 exists, so a later read of an input isn't
+        // mistaken for this definition.
+        self.variable(variable);
+        let version = {
+            let version = self.versions.get_mut(variable).unwrap();
+            *version += 1;
+            *version
+        };
+        let symbol = format!("|{}@{}|", variable_name(variable), version);
+        self.declare(&symbol, &sort(variable_bits(variable)));
+        symbol
+    }
+
+    fn havoc_memory(&mut self) {
+        self.memory_version += 1;
+        let memory = self.memory();
+        self.declare(&memory,
+            &format!("(Array {} (_ BitVec 8))", sort(self.address_bits)));
+    }
+
+    fn note_constant_read(&mut self, index: &ir::Expression<ir::Constant>, bits: usize) {
+        if let Some((address, _)) = values::fold(index) {
+            self.constant_reads.insert((address, (bits + 7) / 8));
+        }
+    }
+
+    fn read(&self, address: &str, bits: usize) -> String {
+        let bytes = (bits + 7) / 8;
+        let mut selects: Vec<String> =
+            (0..bytes)
+                .map(|i| format!("(select {} (bvadd {} {}))",
+                                 self.memory(), address,
+                                 constant(i as u64, self.address_bits)))
+                .collect();
+        // Concatenation puts its first argument in the most significant
+        // bits.
+        if !self.big_endian {
+            selects.reverse();
+        }
+        let value =
+            if selects.len() == 1 {
+                selects.pop().unwrap()
+            }
+            else {
+                format!("(concat {})", selects.join(" "))
+            };
+        if bytes * 8 == bits {
+            value
+        }
+        else {
+            format!("((_ extract {} 0) {})", bits - 1, value)
+        }
+    }
+
+    fn write(&mut self, address: &str, value: &str, bits: usize) {
+        let bytes = (bits + 7) / 8;
+        let value =
+            if bytes * 8 == bits {
+                value.to_string()
+            }
+            else {
+                format!("((_ zero_extend {}) {})", bytes * 8 - bits, value)
+            };
+        let mut memory = self.memory();
+        for i in 0..bytes {
+            let shift = if self.big_endian { bytes - 1 - i } else { i };
+            memory = format!(
+                "(store {} (bvadd {} {}) ((_ extract {} {}) {}))",
+                memory, address, constant(i as u64, self.address_bits),
+                shift * 8 + 7, shift * 8, value);
+        }
+        self.memory_version += 1;
+        let symbol = self.memory();
+        self.declare(&symbol,
+            &format!("(Array {} (_ BitVec 8))", sort(self.address_bits)));
+        self.assertions.push(format!("(= {} {})", symbol, memory));
+    }
+
+    fn lvalue(
+        &mut self,
+        expression: &ir::Expression<ir::Constant>,
+        lvalue: &ir::LValue<ir::Constant>
+    ) -> String {
+        match lvalue {
+            ir::LValue::Variable(variable) => self.variable(variable),
+            ir::LValue::Dereference(dereference) => {
+                let bits = expression.bits();
+                self.note_constant_read(dereference.expression(), bits);
+                let address = self.expression(dereference.expression());
+                self.read(&address, bits)
+            }
+        }
+    }
+
+    fn rvalue(&mut self, rvalue: &ir::RValue<ir::Constant>) -> String {
+        match rvalue {
+            ir::RValue::Value(value) =>
+                format!("(_ bv{} {})", value.value().to_str_radix(10), value.bits()),
+            ir::RValue::Reference(reference) =>
+                self.reference(reference.expression())
+        }
+    }
+
+    /// The address of `expression`. Taking the address of a dereference
+    /// gives back the dereferenced address. Anything else gets a symbol of
+    /// its own, as we don't know where variables live.
+    fn reference(&mut self, expression: &ir::Expression<ir::Constant>) -> String {
+        fn dereferenced(expression: &ir::Expression<ir::Constant>)
+            -> Option<&ir::Expression<ir::Constant>> {
+
+            fn lvalue_dereferenced(lvalue: &ir::LValue<ir::Constant>)
+                -> Option<&ir::Expression<ir::Constant>> {
+
+                match lvalue {
+                    ir::LValue::Dereference(dereference) => Some(dereference.expression()),
+                    ir::LValue::Variable(_) => None
+                }
+            }
+
+            match expression {
+                ir::Expression::LValue(lvalue) => lvalue_dereferenced(lvalue),
+                _ => None
+            }
+        }
+
+        if let Some(address) = dereferenced(expression) {
+            return self.expression(address);
+        }
+
+        match dataflow::expression_variable(expression).cloned() {
+            Some(variable) => {
+                if let Some(symbol) = self.references.get(&variable) {
+                    return symbol.clone();
+                }
+                let symbol = format!("|&{}|", variable_name(&variable));
+                self.declare(&symbol, &sort(self.address_bits));
+                self.references.insert(variable, symbol.clone());
+                symbol
+            },
+            None => {
+                let bits = self.address_bits;
+                self.fresh("reference", bits)
+            }
+        }
+    }
+
+    /// The term for `expression`, in terms of the current variable versions
+    /// and memory.
+    pub fn expression(&mut self, expression: &ir::Expression<ir::Constant>) -> String {
+        let binop = |encoder: &mut Encoder,
+                     op: &str,
+                     lhs: &ir::Expression<ir::Constant>,
+                     rhs: &ir::Expression<ir::Constant>| {
+            let lhs = encoder.expression(lhs);
+            let rhs = encoder.expression(rhs);
+            format!("({} {} {})", op, lhs, rhs)
+        };
+        let cmp = |encoder: &mut Encoder,
+                   op: &str,
+                   lhs: &ir::Expression<ir::Constant>,
+                   rhs: &ir::Expression<ir::Constant>| {
+            let lhs = encoder.expression(lhs);
+            let rhs = encoder.expression(rhs);
+            format!("(ite ({} {} {}) #b1 #b0)", op, lhs, rhs)
+        };
+
+        match expression {
+            ir::Expression::LValue(lvalue) => self.lvalue(expression, lvalue),
+            ir::Expression::RValue(rvalue) => self.rvalue(rvalue),
+            ir::Expression::Add(lhs, rhs) => binop(self, "bvadd", lhs, rhs),
+            ir::Expression::Sub(lhs, rhs) => binop(self, "bvsub", lhs, rhs),
+            ir::Expression::Mul(lhs, rhs) => binop(self, "bvmul", lhs, rhs),
+            ir::Expression::Divu(lhs, rhs) => binop(self, "bvudiv", lhs, rhs),
+            ir::Expression::Modu(lhs, rhs) => binop(self, "bvurem", lhs, rhs),
+            ir::Expression::Divs(lhs, rhs) => binop(self, "bvsdiv", lhs, rhs),
+            ir::Expression::Mods(lhs, rhs) => binop(self, "bvsrem", lhs, rhs),
+            ir::Expression::And(lhs, rhs) => binop(self, "bvand", lhs, rhs),
+            ir::Expression::Or(lhs, rhs) => binop(self, "bvor", lhs, rhs),
+            ir::Expression::Xor(lhs, rhs) => binop(self, "bvxor", lhs, rhs),
+            ir::Expression::Shl(lhs, rhs) => binop(self, "bvshl", lhs, rhs),
+            ir::Expression::Shr(lhs, rhs) => binop(self, "bvlshr", lhs, rhs),
+            ir::Expression::Cmpeq(lhs, rhs) => cmp(self, "=", lhs, rhs),
+            ir::Expression::Cmpneq(lhs, rhs) => cmp(self, "distinct", lhs, rhs),
+            ir::Expression::Cmplts(lhs, rhs) => cmp(self, "bvslt", lhs, rhs),
+            ir::Expression::Cmpltu(lhs, rhs) => cmp(self, "bvult", lhs, rhs),
+            ir::Expression::Trun(bits, rhs) => {
+                let rhs = self.expression(rhs);
+                format!("((_ extract {} 0) {})", bits - 1, rhs)
+            },
+            ir::Expression::Zext(bits, rhs) => {
+                let extension = bits - rhs.bits();
+                let rhs = self.expression(rhs);
+                format!("((_ zero_extend {}) {})", extension, rhs)
+            },
+            ir::Expression::Sext(bits, rhs) => {
+                let extension = bits - rhs.bits();
+                let rhs = self.expression(rhs);
+                format!("((_ sign_extend {}) {})", extension, rhs)
+            },
+            ir::Expression::Ite(cond, then, else_) => {
+                let cond = self.expression(cond);
+                let then = self.expression(then);
+                let else_ = self.expression(else_);
+                format!("(ite {} {} {})", boolean(&cond), then, else_)
+            }
+        }
+    }
+
+    /// Encode the effect of `operation`.
+    ///
+    /// Calls and intrinsics are not followed. Whatever they write, and all
+    /// of memory, becomes unconstrained.
+    pub fn operation(&mut self, operation: &ir::Operation<ir::Constant>) {
+        match operation {
+            ir::Operation::Assign { dst, src } => {
+                let src = self.expression(src);
+                let dst = self.define(dst);
+                self.assertions.push(format!("(= {} {})", dst, src));
+            },
+            ir::Operation::Load { dst, index } => {
+                let bits = variable_bits(dst);
+                self.note_constant_read(index, bits);
+                let index = self.expression(index);
+                let value = self.read(&index, bits);
+                let dst = self.define(dst);
+                self.assertions.push(format!("(= {} {})", dst, value));
+            },
+            ir::Operation::Store { index, src } => {
+                let bits = src.bits();
+                let index = self.expression(index);
+                let src = self.expression(src);
+                self.write(&index, &src, bits);
+            },
+            ir::Operation::Call(call) => {
+                if let Some(variables_written) = call.variables_written() {
+                    for variable in variables_written {
+                        self.define(variable);
+                    }
+                }
+                self.havoc_memory();
+            },
+            ir::Operation::Intrinsic(intrinsic) => {
+                if let Some(written_expressions) = intrinsic.written_expressions() {
+                    for expression in written_expressions {
+                        let expression = ir::Expression::from_il(expression);
+                        if let Some(variable) =
+                                dataflow::expression_variable(&expression) {
+                            self.define(variable);
+                        }
+                    }
+                }
+                self.havoc_memory();
+            },
+            ir::Operation::Branch { .. } |
+            ir::Operation::Return(_) |
+            ir::Operation::Nop => {}
+        }
+    }
+
+    /// Require the one bit `condition` to hold.
+    pub fn condition(&mut self, condition: &ir::Expression<ir::Constant>) {
+        let condition = self.expression(condition);
+        self.assertions.push(boolean(&condition));
+    }
+
+    /// Add a raw SMT-LIB assertion.
+    pub fn assert<S: Into<String>>(&mut self, term: S) {
+        self.assertions.push(term.into());
+    }
+
+    /// Symbols of the variables read before being written, and the
+    /// variables they stand for.
+    pub fn inputs(&self) -> &BTreeMap<String, ir::Variable> { &self.inputs }
+
+    /// Memory read at constant addresses, as (address, bytes) pairs.
+    pub fn constant_reads(&self) -> &BTreeSet<(u64, usize)> { &self.constant_reads }
+
+    /// The declarations and assertions built so far, without any commands.
+    pub fn smtlib(&self) -> String {
+        let mut script = String::new();
+        script.push_str("(set-logic QF_ABV)\n");
+        for declaration in &self.declarations {
+            script.push_str(declaration);
+            script.push('\n');
+        }
+        for assertion in &self.assertions {
+            script.push_str(&format!("(assert {})\n", assertion));
+        }
+        script
+    }
+}
+
+
+/// What the solver made of a query.
+#[derive(Clone, Debug)]
+pub enum Answer {
+    /// Satisfiable, with the values of the requested symbols.
+    Sat(BTreeMap<String, u64>),
+    Unsat,
+    /// The solver gave up, usually because it ran out of time.
+    Unknown
+}
+
+
+/// Pull `(|symbol| #x...)` pairs out of the solver's answer to `get-value`.
+fn parse_values(output: &str) -> BTreeMap<String, u64> {
+    let mut values = BTreeMap::new();
+    let mut rest = output;
+    while let Some(start) = rest.find('|') {
+        let end = match rest[start + 1..].find('|') {
+            Some(end) => start + 1 + end,
+            None => break
+        };
+        let symbol = &rest[start..end + 1];
+        rest = &rest[end + 1..];
+        let value = rest.trim_start();
+        let token: String =
+            value.chars()
+                .take_while(|c| c.is_ascii_alphanumeric() || *c == '#')
+                .collect();
+        let parsed =
+            if token.starts_with("#x") {
+                u64::from_str_radix(&token[2..], 16).ok()
+            }
+            else if token.starts_with("#b") {
+                u64::from_str_radix(&token[2..], 2).ok()
+            }
+            else {
+                None
+            };
+        if let Some(parsed) = parsed {
+            values.insert(symbol.to_string(), parsed);
+        }
+    }
+    values
+}
+
+
+/// Run a locally installed z3 over `script`, asking for the values of
+/// `symbols` when it is satisfiable.
+pub fn solve(script: &str, symbols: &[String], timeout: Duration) -> Result<Answer> {
+    let mut child =
+        Command::new("z3")
+            .arg("-in")
+            .arg("-smt2")
+            .arg(format!("-t:{}", timeout.as_millis().max(1)))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("Could not run z3: {}", e))?;
+
+    {
+        let stdin = child.stdin.as_mut().ok_or("Could not write to z3")?;
+        stdin.write_all(b"(set-option :produce-models true)\n")?;
+        stdin.write_all(script.as_bytes())?;
+        stdin.write_all(b"(check-sat)\n")?;
+        if !symbols.is_empty() {
+            stdin.write_all(format!("(get-value ({}))\n", symbols.join(" ")).as_bytes())?;
+        }
+    }
+
+    let output = child.wait_with_output()?;
+    let output = String::from_utf8_lossy(&output.stdout);
+    let mut lines = output.lines();
+
+    match lines.next().map(|line| line.trim()) {
+        Some("sat") => {
+            let rest: Vec<&str> = lines.collect();
+            Ok(Answer::Sat(parse_values(&rest.join("\n"))))
+        },
+        Some("unsat") => Ok(Answer::Unsat),
+        Some("unknown") | Some("timeout") => Ok(Answer::Unknown),
+        Some(line) => bail!("Unexpected output from z3: {}", line),
+        None => bail!("No output from z3")
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values() {
+        let values = parse_values("((|rax_0| #x000000000000002a)\n (|a b| #b101)\n (|c| (_ bv3 8)))");
+        assert_eq!(values.len(), 2);
+        assert_eq!(values.get("|rax_0|"), Some(&42));
+        assert_eq!(values.get("|a b|"), Some(&5));
+        assert_eq!(values.get("|c|"), None);
+    }
+
+    #[test]
+    fn no_values() {
+        assert!(parse_values("").is_empty());
+        assert!(parse_values("unsat").is_empty());
+        assert!(parse_values("((|x| #x").is_empty());
+        assert!(parse_values("((|x").is_empty());
+    }
+}
@@ -0,0 +1,252 @@
+use analysis::cfg::Cfg;
+use analysis::dataflow::{self, Location};
+use analysis::dominators::Dominators;
+use analysis::InstructionLocation;
+use raptor::ir;
+use std::collections::{BTreeMap, BTreeSet};
+
+
+/// A phi node at the head of a block. `arguments` holds, for each
+/// predecessor block, the version of the variable flowing in from it.
+#[derive(Clone, Debug)]
+pub struct Phi {
+    variable: ir::Variable,
+    version: usize,
+    arguments: BTreeMap<usize, usize>
+}
+
+
+impl Phi {
+    pub fn variable(&self) -> &ir::Variable { &self.variable }
+    pub fn version(&self) -> usize { self.version }
+    pub fn arguments(&self) -> &BTreeMap<usize, usize> { &self.arguments }
+}
+
+
+type Versions = BTreeMap<ir::Variable, usize>;
+
+
+/// A function in SSA form.
+///
+/// Rather than rewriting the IR, this records which version of each scalar
+/// and stack variable every instruction reads and writes. Version 0 is the
+/// value a variable holds on entry to the function. Memory is not
+/// versioned.
+pub struct Ssa {
+    function_index: usize,
+    phis: BTreeMap<usize, Vec<Phi>>,
+    uses: BTreeMap<InstructionLocation, Versions>,
+    defs: BTreeMap<InstructionLocation, Versions>
+}
+
+
+impl Ssa {
+    /// `None` when `function` has no index, as its instructions then have
+    /// no location.
+    pub fn new(function: &ir::Function<ir::Constant>) -> Option<Ssa> {
+        let function_index = function.index()?;
+        let cfg = Cfg::new(function);
+        let dominators = Dominators::new(&cfg);
+
+        let variables = |locations: BTreeSet<Location>| -> BTreeSet<ir::Variable> {
+            locations.into_iter()
+                .filter_map(|location| location.variable().cloned())
+                .collect()
+        };
+
+        let mut block_instructions: BTreeMap<usize, Vec<InstructionLocation>> =
+            BTreeMap::new();
+        let mut instruction_uses = BTreeMap::new();
+        let mut instruction_defs = BTreeMap::new();
+
+        // Blocks defining each variable, and the variables read in some
+        // block before being written there. Only those need phi nodes,
+        // which gives semi-pruned SSA.
+        let mut def_blocks: BTreeMap<ir::Variable, BTreeSet<usize>> = BTreeMap::new();
+        let mut globals: BTreeSet<ir::Variable> = BTreeSet::new();
+
+        for block in function.blocks() {
+            let mut killed = BTreeSet::new();
+            let mut locations = Vec::new();
+            for instruction in block.instructions() {
+                let location = InstructionLocation::new(
+                    function_index, block.index(), instruction.index());
+                let uses = variables(dataflow::operation_uses(instruction.operation()));
+                let defs = variables(dataflow::operation_defs(instruction.operation()));
+                for variable in &uses {
+                    if !killed.contains(variable) {
+                        globals.insert(variable.clone());
+                    }
+                }
+                for variable in &defs {
+                    killed.insert(variable.clone());
+                    def_blocks.entry(variable.clone())
+                        .or_insert_with(BTreeSet::new)
+                        .insert(block.index());
+                }
+                instruction_uses.insert(location, uses);
+                instruction_defs.insert(location, defs);
+                locations.push(location);
+            }
+            block_instructions.insert(block.index(), locations);
+        }
+
+        // Place phi nodes on the iterated dominance frontier of each
+        // variable's definitions.
+        let mut phi_variables: BTreeMap<usize, BTreeSet<ir::Variable>> = BTreeMap::new();
+        for (variable, blocks) in &def_blocks {
+            if !globals.contains(variable) {
+                continue;
+            }
+            let mut worklist: Vec<usize> = blocks.iter().cloned().collect();
+            let mut placed = BTreeSet::new();
+            while let Some(index) = worklist.pop() {
+                let frontier = match dominators.frontier(index) {
+                    Some(frontier) => frontier,
+                    None => continue
+                };
+                for join in frontier {
+                    if placed.insert(*join) {
+                        phi_variables.entry(*join)
+                            .or_insert_with(BTreeSet::new)
+                            .insert(variable.clone());
+                        if !blocks.contains(join) {
+                            worklist.push(*join);
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut phis: BTreeMap<usize, Vec<Phi>> =
+            phi_variables.into_iter()
+                .map(|(index, variables)|
+                    (index, variables.into_iter()
+                        .map(|variable| Phi {
+                            variable: variable,
+                            version: 0,
+                            arguments: BTreeMap::new()
+                        })
+                        .collect()))
+                .collect();
+
+        // Rename by walking the dominator tree, keeping a stack of versions
+        // for each variable.
+        let mut counters: BTreeMap<ir::Variable, usize> = BTreeMap::new();
+        let mut stacks: BTreeMap<ir::Variable, Vec<usize>> = BTreeMap::new();
+        let mut uses = BTreeMap::new();
+        let mut defs = BTreeMap::new();
+
+        let tree = dominators.tree();
+        let mut visited = BTreeSet::new();
+
+        enum Step { Enter(usize), Exit(Vec<ir::Variable>) }
+
+        let mut roots: Vec<usize> = cfg.entry().into_iter().collect();
+        roots.extend(cfg.reverse_post_order());
+
+        for root in roots {
+            if visited.contains(&root) {
+                continue;
+            }
+            let mut steps = vec![Step::Enter(root)];
+            while let Some(step) = steps.pop() {
+                let index = match step {
+                    Step::Enter(index) => index,
+                    Step::Exit(pushed) => {
+                        for variable in pushed {
+                            stacks.get_mut(&variable).unwrap().pop();
+                        }
+                        continue;
+                    }
+                };
+                if !visited.insert(index) {
+                    continue;
+                }
+
+                let mut pushed = Vec::new();
+                let mut new_version = |variable: &ir::Variable,
+                                       stacks: &mut BTreeMap<ir::Variable, Vec<usize>>| {
+                    let counter = counters.entry(variable.clone()).or_insert(0);
+                    *counter += 1;
+                    stacks.entry(variable.clone())
+                        .or_insert_with(Vec::new)
+                        .push(*counter);
+                    pushed.push(variable.clone());
+                    *counter
+                };
+
+                if let Some(block_phis) = phis.get_mut(&index) {
+                    for phi in block_phis.iter_mut() {
+                        phi.version = new_version(&phi.variable, &mut stacks);
+                    }
+                }
+
+                if let Some(locations) = block_instructions.get(&index) {
+                    for location in locations {
+                        let current = |variable: &ir::Variable,
+                                       stacks: &BTreeMap<ir::Variable, Vec<usize>>|
+                            stacks.get(variable)
+                                .and_then(|stack| stack.last().cloned())
+                                .unwrap_or(0);
+                        let used: Versions =
+                            instruction_uses[location].iter()
+                                .map(|variable|
+                                    (variable.clone(), current(variable, &stacks)))
+                                .collect();
+                        let defined: Versions =
+                            instruction_defs[location].iter()
+                                .map(|variable|
+                                    (variable.clone(), new_version(variable, &mut stacks)))
+                                .collect();
+                        uses.insert(*location, used);
+                        defs.insert(*location, defined);
+                    }
+                }
+
+                for successor in cfg.successors(index) {
+                    if let Some(successor_phis) = phis.get_mut(successor) {
+                        for phi in successor_phis.iter_mut() {
+                            let version =
+                                stacks.get(&phi.variable)
+                                    .and_then(|stack| stack.last().cloned())
+                                    .unwrap_or(0);
+                            phi.arguments.insert(index, version);
+                        }
+                    }
+                }
+
+                steps.push(Step::Exit(pushed));
+                if let Some(children) = tree.get(&index) {
+                    for child in children.iter().rev() {
+                        steps.push(Step::Enter(*child));
+                    }
+                }
+            }
+        }
+
+        Some(Ssa {
+            function_index: function_index,
+            phis: phis,
+            uses: uses,
+            defs: defs
+        })
+    }
+
+    pub fn function_index(&self) -> usize { self.function_index }
+
+    pub fn phis(&self, block_index: usize) -> Option<&Vec<Phi>> {
+        self.phis.get(&block_index)
+    }
+
+    /// The version of each variable read by the instruction at `location`.
+    pub fn uses(&self, location: &InstructionLocation) -> Option<&Versions> {
+        self.uses.get(location)
+    }
+
+    /// The version of each variable written by the instruction at
+    /// `location`.
+    pub fn defs(&self, location: &InstructionLocation) -> Option<&Versions> {
+        self.defs.get(location)
+    }
+}
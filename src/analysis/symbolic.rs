@@ -0,0 +1,302 @@
+//! Search for inputs which drive a function to a target address.
+//!
+//! Paths through the function's CFG are enumerated from the entry, encoded
+//! with `analysis::smt`, and handed to z3 one at a time. Calls are not
+//! followed, so this answers questions within a single function.
+
+use analysis::cfg::Cfg;
+use analysis::paths;
+use analysis::smt::{self, Answer, Encoder};
+use analysis::InstructionLocation;
+use error::*;
+use falcon::architecture::Endian;
+use falcon::loader::Loader;
+use falcon::memory::MemoryPermissions;
+use raptor::ir;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+
+/// Bounds on an exploration.
+#[derive(Clone, Debug)]
+pub struct Limits {
+    /// The most paths handed to the solver.
+    pub max_paths: usize,
+    /// How many times a path may pass through any one block, which bounds
+    /// loop unrolling.
+    pub max_visits: usize,
+    /// Time allowed for the whole exploration.
+    pub timeout: Duration
+}
+
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_paths: 64,
+            max_visits: 2,
+            timeout: Duration::from_secs(30)
+        }
+    }
+}
+
+
+#[derive(Clone, Debug)]
+pub enum Outcome {
+    /// The target is reached along `path` when the inputs hold these
+    /// values.
+    Reached {
+        path: Vec<usize>,
+        assignments: BTreeMap<ir::Variable, u64>
+    },
+    /// Every path within the bounds was shown infeasible.
+    Unreachable,
+    /// We ran out of paths or time, or the solver gave up, before finding
+    /// an answer.
+    Unknown
+}
+
+
+pub struct Exploration {
+    outcome: Outcome,
+    paths_explored: usize
+}
+
+
+impl Exploration {
+    pub fn outcome(&self) -> &Outcome { &self.outcome }
+    pub fn paths_explored(&self) -> usize { self.paths_explored }
+}
+
+
+/// The block and instruction index holding `address`.
+fn find_address(function: &ir::Function<ir::Constant>, address: u64)
+    -> Option<(usize, usize)> {
+
+    for block in function.blocks() {
+        for instruction in block.instructions() {
+            if instruction.address() == Some(address) {
+                return Some((block.index(), instruction.index()));
+            }
+        }
+    }
+    None
+}
+
+
+/// Encode the instructions along `path`, and the conditions on the edges
+/// between its blocks. Encoding of the last block stops before
+/// `last_instruction`, when given.
+pub fn encode_path(
+    encoder: &mut Encoder,
+    function: &ir::Function<ir::Constant>,
+    path: &[usize],
+    first_instruction: Option<usize>,
+    last_instruction: Option<usize>
+) -> Result<()> {
+    for (i, index) in path.iter().enumerate() {
+        let block =
+            function.blocks()
+                .into_iter()
+                .find(|block| block.index() == *index)
+                .ok_or(format!("Could not find block {}", index))?;
+
+        let mut started = i > 0 || first_instruction.is_none();
+        for instruction in block.instructions() {
+            if i == 0 && Some(instruction.index()) == first_instruction {
+                started = true;
+            }
+            if i == path.len() - 1 && Some(instruction.index()) == last_instruction {
+                break;
+            }
+            if started {
+                encoder.operation(instruction.operation());
+            }
+        }
+
+        if let Some(next) = path.get(i + 1) {
+            let edge =
+                function.edges()
+                    .into_iter()
+                    .find(|edge| edge.head() == *index && edge.tail() == *next)
+                    .ok_or(format!("No edge from block {} to {}", index, next))?;
+            if let Some(condition) = edge.condition() {
+                encoder.condition(condition);
+            }
+        }
+    }
+    Ok(())
+}
+
+
+/// Pin memory read at constant, non-writable addresses to the contents of
+/// the loaded image.
+fn constrain_image(encoder: &mut Encoder, loader: &Loader) -> Result<()> {
+    let memory = loader.memory()?;
+    let address_bits = loader.architecture().word_size();
+    let initial_memory = encoder.initial_memory();
+    let reads: Vec<(u64, usize)> = encoder.constant_reads().iter().cloned().collect();
+    for (address, bytes) in reads {
+        // Reads running off the end of the address space pin what they can.
+        for address in address..address.saturating_add(bytes as u64) {
+            let writable =
+                memory.permissions(address)
+                    .map(|permissions| permissions.contains(MemoryPermissions::WRITE))
+                    .unwrap_or(true);
+            if writable {
+                continue;
+            }
+            if let Some(byte) = memory.get8(address) {
+                encoder.assert(format!(
+                    "(= (select {} (_ bv{} {})) (_ bv{} 8))",
+                    initial_memory, address, address_bits, byte));
+            }
+        }
+    }
+    Ok(())
+}
+
+
+fn encoder(loader: &Loader) -> Encoder {
+    let architecture = loader.architecture();
+    let big_endian = match architecture.endian() {
+        Endian::Big => true,
+        Endian::Little => false
+    };
+    Encoder::new(architecture.word_size(), big_endian)
+}
+
+
+/// Encode the way from `start` to `end`, both in `function`, along `path`.
+/// The instruction at `start` is included and the one at `end` is not.
+/// Without a path, a shortest one is used. Returns the path taken and the
+/// encoder holding the constraints.
+pub fn path_constraints(
+    loader: &Loader,
+    function: &ir::Function<ir::Constant>,
+    start: &InstructionLocation,
+    end: &InstructionLocation,
+    path: Option<Vec<usize>>
+) -> Result<(Vec<usize>, Encoder)> {
+    let path = match path {
+        Some(path) => {
+            if path.first() != Some(&start.block_index()) ||
+               path.last() != Some(&end.block_index()) {
+                bail!("Path must run from block {} to block {}",
+                      start.block_index(), end.block_index());
+            }
+            if path.len() == 1 &&
+               start.instruction_index() > end.instruction_index() {
+                bail!("A single block path can't run backwards");
+            }
+            path
+        },
+        None => {
+            let same_block_forward =
+                start.block_index() == end.block_index() &&
+                start.instruction_index() <= end.instruction_index();
+            if same_block_forward {
+                vec![start.block_index()]
+            }
+            else {
+                // Leave the start block before coming back to it, so a
+                // loop can carry us to an earlier instruction.
+                let cfg = Cfg::new(function);
+                cfg.successors(start.block_index())
+                    .iter()
+                    .filter_map(|successor|
+                        paths::shortest(
+                            |index| cfg.successors(index).clone(),
+                            *successor,
+                            end.block_index()))
+                    .next()
+                    .map(|rest| {
+                        let mut path = vec![start.block_index()];
+                        path.extend(rest);
+                        path
+                    })
+                    .ok_or("No path between the locations")?
+            }
+        }
+    };
+
+    let mut encoder = encoder(loader);
+    encode_path(
+        &mut encoder,
+        function,
+        &path,
+        Some(start.instruction_index()),
+        Some(end.instruction_index()))?;
+    constrain_image(&mut encoder, loader)?;
+
+    Ok((path, encoder))
+}
+
+
+/// Look for inputs to `function` which reach the instruction at `target`.
+pub fn explore(
+    loader: &Loader,
+    function: &ir::Function<ir::Constant>,
+    target: u64,
+    limits: &Limits
+) -> Result<Exploration> {
+    let started = Instant::now();
+
+    let (target_block, target_instruction) =
+        find_address(function, target)
+            .ok_or(format!("0x{:x} is not in function {}", target, function.name()))?;
+
+    let cfg = Cfg::new(function);
+    let entry = cfg.entry().ok_or("Function has no entry block")?;
+
+    let paths = paths::enumerate(
+        |index| cfg.successors(index).clone(),
+        entry,
+        target_block,
+        limits.max_visits,
+        limits.max_paths);
+
+    let mut gave_up = paths.truncated();
+    let mut paths_explored = 0;
+
+    for path in paths.into_paths() {
+        let elapsed = started.elapsed();
+        if elapsed >= limits.timeout {
+            gave_up = true;
+            break;
+        }
+
+        let mut encoder = encoder(loader);
+        encode_path(&mut encoder, function, &path, None, Some(target_instruction))?;
+        constrain_image(&mut encoder, loader)?;
+
+        let symbols: Vec<String> = encoder.inputs().keys().cloned().collect();
+        paths_explored += 1;
+
+        match smt::solve(&encoder.smtlib(), &symbols, limits.timeout - elapsed)? {
+            Answer::Sat(values) => {
+                let assignments =
+                    values.into_iter()
+                        .filter_map(|(symbol, value)|
+                            encoder.inputs()
+                                .get(&symbol)
+                                .map(|variable| (variable.clone(), value)))
+                        .collect();
+                return Ok(Exploration {
+                    outcome: Outcome::Reached {
+                        path: path,
+                        assignments: assignments
+                    },
+                    paths_explored: paths_explored
+                });
+            },
+            Answer::Unsat => {},
+            Answer::Unknown => gave_up = true
+        }
+    }
+
+    Ok(Exploration {
+        outcome: if gave_up { Outcome::Unknown } else { Outcome::Unreachable },
+        paths_explored: paths_explored
+    })
+}
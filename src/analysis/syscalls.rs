@@ -0,0 +1,284 @@
+//! Finding direct system calls, and what they are called with.
+
+use analysis::values::Values;
+use analysis::InstructionLocation;
+use error::*;
+use falcon::il;
+use falcon::loader::Loader;
+use raptor::ir;
+
+
+const AMD64_ARGUMENTS: &[&str] = &["rdi", "rsi", "rdx", "r10", "r8", "r9"];
+const X86_ARGUMENTS: &[&str] = &["ebx", "ecx", "edx", "esi", "edi", "ebp"];
+// Arguments past the fourth are passed on the stack under o32.
+const MIPS_ARGUMENTS: &[&str] = &["$a0", "$a1", "$a2", "$a3"];
+const MIPS64_ARGUMENTS: &[&str] = &["$a0", "$a1", "$a2", "$a3", "$t0", "$t1"];
+const PPC_ARGUMENTS: &[&str] = &["r3", "r4", "r5", "r6", "r7", "r8"];
+
+
+/// The register holding the syscall number, and those holding arguments in
+/// order, under the Linux ABI for each architecture.
+fn abi(architecture: &str) -> Option<(&'static str, &'static [&'static str])> {
+    match architecture {
+        "amd64" => Some(("rax", AMD64_ARGUMENTS)),
+        "x86" => Some(("eax", X86_ARGUMENTS)),
+        "mips" | "mipsel" => Some(("$v0", MIPS_ARGUMENTS)),
+        "mips64" | "mips64el" => Some(("$v0", MIPS64_ARGUMENTS)),
+        "ppc" => Some(("r0", PPC_ARGUMENTS)),
+        _ => None
+    }
+}
+
+
+const AMD64_SYSCALLS: &[(u64, &str)] = &[
+    (0, "read"), (1, "write"), (2, "open"), (3, "close"), (4, "stat"),
+    (5, "fstat"), (6, "lstat"), (7, "poll"), (8, "lseek"), (9, "mmap"),
+    (10, "mprotect"), (11, "munmap"), (12, "brk"), (13, "rt_sigaction"),
+    (14, "rt_sigprocmask"), (15, "rt_sigreturn"), (16, "ioctl"),
+    (17, "pread64"), (18, "pwrite64"), (19, "readv"), (20, "writev"),
+    (21, "access"), (22, "pipe"), (23, "select"), (24, "sched_yield"),
+    (25, "mremap"), (26, "msync"), (27, "mincore"), (28, "madvise"),
+    (29, "shmget"), (30, "shmat"), (31, "shmctl"), (32, "dup"), (33, "dup2"),
+    (34, "pause"), (35, "nanosleep"), (36, "getitimer"), (37, "alarm"),
+    (38, "setitimer"), (39, "getpid"), (40, "sendfile"), (41, "socket"),
+    (42, "connect"), (43, "accept"), (44, "sendto"), (45, "recvfrom"),
+    (46, "sendmsg"), (47, "recvmsg"), (48, "shutdown"), (49, "bind"),
+    (50, "listen"), (51, "getsockname"), (52, "getpeername"),
+    (53, "socketpair"), (54, "setsockopt"), (55, "getsockopt"), (56, "clone"),
+    (57, "fork"), (58, "vfork"), (59, "execve"), (60, "exit"), (61, "wait4"),
+    (62, "kill"), (63, "uname"), (72, "fcntl"), (73, "flock"), (74, "fsync"),
+    (76, "truncate"), (77, "ftruncate"), (78, "getdents"), (79, "getcwd"),
+    (80, "chdir"), (81, "fchdir"), (82, "rename"), (83, "mkdir"),
+    (84, "rmdir"), (85, "creat"), (86, "link"), (87, "unlink"),
+    (88, "symlink"), (89, "readlink"), (90, "chmod"), (91, "fchmod"),
+    (92, "chown"), (93, "fchown"), (95, "umask"), (96, "gettimeofday"),
+    (101, "ptrace"), (102, "getuid"), (104, "getgid"), (105, "setuid"),
+    (106, "setgid"), (107, "geteuid"), (108, "getegid"), (109, "setpgid"),
+    (110, "getppid"), (112, "setsid"), (157, "prctl"), (158, "arch_prctl"),
+    (186, "gettid"), (202, "futex"), (217, "getdents64"),
+    (218, "set_tid_address"), (228, "clock_gettime"), (231, "exit_group"),
+    (257, "openat"), (288, "accept4"), (293, "pipe2"), (318, "getrandom"),
+    (319, "memfd_create"), (322, "execveat")
+];
+
+
+const X86_SYSCALLS: &[(u64, &str)] = &[
+    (1, "exit"), (2, "fork"), (3, "read"), (4, "write"), (5, "open"),
+    (6, "close"), (7, "waitpid"), (8, "creat"), (9, "link"), (10, "unlink"),
+    (11, "execve"), (12, "chdir"), (13, "time"), (14, "mknod"), (15, "chmod"),
+    (19, "lseek"), (20, "getpid"), (21, "mount"), (23, "setuid"),
+    (24, "getuid"), (26, "ptrace"), (27, "alarm"), (29, "pause"),
+    (33, "access"), (37, "kill"), (38, "rename"), (39, "mkdir"),
+    (40, "rmdir"), (41, "dup"), (42, "pipe"), (45, "brk"), (46, "setgid"),
+    (47, "getgid"), (49, "geteuid"), (50, "getegid"), (54, "ioctl"),
+    (55, "fcntl"), (57, "setpgid"), (60, "umask"), (61, "chroot"),
+    (63, "dup2"), (64, "getppid"), (66, "setsid"), (67, "sigaction"),
+    (78, "gettimeofday"), (83, "symlink"), (85, "readlink"), (90, "mmap"),
+    (91, "munmap"), (92, "truncate"), (93, "ftruncate"), (102, "socketcall"),
+    (106, "stat"), (107, "lstat"), (108, "fstat"), (114, "wait4"),
+    (117, "ipc"), (118, "fsync"), (119, "sigreturn"), (120, "clone"),
+    (122, "uname"), (125, "mprotect"), (141, "getdents"), (142, "_newselect"),
+    (145, "readv"), (146, "writev"), (162, "nanosleep"), (163, "mremap"),
+    (168, "poll"), (172, "prctl"), (173, "rt_sigreturn"),
+    (174, "rt_sigaction"), (175, "rt_sigprocmask"), (180, "pread64"),
+    (181, "pwrite64"), (183, "getcwd"), (187, "sendfile"), (190, "vfork"),
+    (192, "mmap2"), (197, "fstat64"), (220, "getdents64"), (221, "fcntl64"),
+    (224, "gettid"), (240, "futex"), (243, "set_thread_area"),
+    (252, "exit_group"), (258, "set_tid_address"), (265, "clock_gettime"),
+    (295, "openat"), (331, "pipe2"), (355, "getrandom"),
+    (356, "memfd_create"), (358, "execveat"), (359, "socket"),
+    (360, "socketpair"), (361, "bind"), (362, "connect"), (363, "listen"),
+    (364, "accept4")
+];
+
+
+const MIPS_SYSCALLS: &[(u64, &str)] = &[
+    (4001, "exit"), (4002, "fork"), (4003, "read"), (4004, "write"),
+    (4005, "open"), (4006, "close"), (4007, "waitpid"), (4008, "creat"),
+    (4009, "link"), (4010, "unlink"), (4011, "execve"), (4012, "chdir"),
+    (4013, "time"), (4019, "lseek"), (4020, "getpid"), (4023, "setuid"),
+    (4024, "getuid"), (4033, "access"), (4037, "kill"), (4038, "rename"),
+    (4039, "mkdir"), (4040, "rmdir"), (4041, "dup"), (4042, "pipe"),
+    (4045, "brk"), (4054, "ioctl"), (4055, "fcntl"), (4063, "dup2"),
+    (4064, "getppid"), (4066, "setsid"), (4090, "mmap"), (4091, "munmap"),
+    (4102, "socketcall"), (4120, "clone"), (4122, "uname"),
+    (4125, "mprotect"), (4166, "nanosleep"), (4168, "accept"),
+    (4169, "bind"), (4170, "connect"), (4174, "listen"), (4175, "recv"),
+    (4176, "recvfrom"), (4178, "send"), (4179, "sendmsg"), (4180, "sendto"),
+    (4181, "setsockopt"), (4182, "shutdown"), (4183, "socket"),
+    (4184, "socketpair"), (4203, "getcwd"), (4210, "mmap2"),
+    (4219, "getdents64"), (4222, "gettid"), (4238, "futex"),
+    (4246, "exit_group"), (4288, "openat")
+];
+
+
+// The n64 ABI.
+const MIPS64_SYSCALLS: &[(u64, &str)] = &[
+    (5000, "read"), (5001, "write"), (5002, "open"), (5003, "close"),
+    (5004, "stat"), (5005, "fstat"), (5006, "lstat"), (5007, "poll"),
+    (5008, "lseek"), (5009, "mmap"), (5010, "mprotect"), (5011, "munmap"),
+    (5012, "brk"), (5013, "rt_sigaction"), (5014, "rt_sigprocmask"),
+    (5015, "ioctl"), (5016, "pread64"), (5017, "pwrite64"), (5018, "readv"),
+    (5019, "writev"), (5020, "access"), (5021, "pipe"), (5022, "select"),
+    (5023, "sched_yield"), (5024, "mremap"), (5025, "msync"),
+    (5026, "mincore"), (5027, "madvise"), (5028, "shmget"), (5029, "shmat"),
+    (5030, "shmctl"), (5031, "dup"), (5032, "dup2"), (5033, "pause"),
+    (5034, "nanosleep"), (5035, "getitimer"), (5036, "setitimer"),
+    (5037, "alarm"), (5038, "getpid"), (5039, "sendfile"), (5040, "socket"),
+    (5041, "connect"), (5042, "accept"), (5043, "sendto"),
+    (5044, "recvfrom"), (5045, "sendmsg"), (5046, "recvmsg"),
+    (5047, "shutdown"), (5048, "bind"), (5049, "listen"),
+    (5050, "getsockname"), (5051, "getpeername"), (5052, "socketpair"),
+    (5053, "setsockopt"), (5054, "getsockopt"), (5055, "clone"),
+    (5056, "fork"), (5057, "execve"), (5058, "exit"), (5059, "wait4"),
+    (5060, "kill"), (5061, "uname"), (5070, "fcntl"), (5071, "flock"),
+    (5072, "fsync"), (5074, "truncate"), (5075, "ftruncate"),
+    (5076, "getdents"), (5077, "getcwd"), (5078, "chdir"), (5079, "fchdir"),
+    (5080, "rename"), (5081, "mkdir"), (5082, "rmdir"), (5083, "creat"),
+    (5084, "link"), (5085, "unlink"), (5086, "symlink"), (5087, "readlink"),
+    (5088, "chmod"), (5089, "fchmod"), (5090, "chown"), (5091, "fchown"),
+    (5093, "umask"), (5094, "gettimeofday"), (5099, "ptrace"),
+    (5100, "getuid"), (5102, "getgid"), (5103, "setuid"), (5104, "setgid"),
+    (5105, "geteuid"), (5106, "getegid"), (5107, "setpgid"),
+    (5108, "getppid"), (5110, "setsid"), (5178, "gettid"), (5194, "futex"),
+    (5205, "exit_group"), (5211, "rt_sigreturn"), (5212, "set_tid_address"),
+    (5222, "clock_gettime"), (5247, "openat"), (5293, "accept4"),
+    (5308, "getdents64"), (5313, "getrandom")
+];
+
+
+const PPC_SYSCALLS: &[(u64, &str)] = &[
+    (1, "exit"), (2, "fork"), (3, "read"), (4, "write"), (5, "open"),
+    (6, "close"), (7, "waitpid"), (8, "creat"), (9, "link"), (10, "unlink"),
+    (11, "execve"), (12, "chdir"), (19, "lseek"), (20, "getpid"),
+    (37, "kill"), (38, "rename"), (39, "mkdir"), (40, "rmdir"), (41, "dup"),
+    (42, "pipe"), (45, "brk"), (54, "ioctl"), (55, "fcntl"), (63, "dup2"),
+    (90, "mmap"), (91, "munmap"), (102, "socketcall"), (120, "clone"),
+    (122, "uname"), (125, "mprotect"), (207, "gettid"), (221, "futex"),
+    (234, "exit_group"), (286, "openat"), (326, "socket"), (327, "bind"),
+    (328, "connect"), (329, "listen"), (330, "accept")
+];
+
+
+/// The name of syscall `number` on `architecture`, for the common ones.
+pub fn syscall_name(architecture: &str, number: u64) -> Option<&'static str> {
+    let table = match architecture {
+        "amd64" => AMD64_SYSCALLS,
+        "x86" => X86_SYSCALLS,
+        "mips" | "mipsel" => MIPS_SYSCALLS,
+        "mips64" | "mips64el" => MIPS64_SYSCALLS,
+        "ppc" => PPC_SYSCALLS,
+        _ => return None
+    };
+    table.iter()
+        .find(|&&(n, _)| n == number)
+        .map(|&(_, name)| name)
+}
+
+
+fn is_syscall(intrinsic: &il::Intrinsic) -> bool {
+    match intrinsic.mnemonic() {
+        "syscall" | "sysenter" | "sc" => true,
+        "int" => intrinsic.instruction_str().contains("0x80"),
+        _ => false
+    }
+}
+
+
+/// A register at a syscall, and what we could recover of its value.
+pub struct Argument {
+    register: &'static str,
+    value: Option<u64>,
+    expression: ir::Expression<ir::Constant>
+}
+
+
+impl Argument {
+    pub fn register(&self) -> &str { self.register }
+    pub fn value(&self) -> Option<u64> { self.value }
+
+    /// The register's value, resolved back as far as we could.
+    pub fn expression(&self) -> &ir::Expression<ir::Constant> { &self.expression }
+}
+
+
+pub struct Syscall {
+    location: InstructionLocation,
+    instruction_str: String,
+    number: Argument,
+    name: Option<&'static str>,
+    arguments: Vec<Argument>
+}
+
+
+impl Syscall {
+    pub fn location(&self) -> &InstructionLocation { &self.location }
+    pub fn instruction_str(&self) -> &str { &self.instruction_str }
+    pub fn number(&self) -> &Argument { &self.number }
+    pub fn name(&self) -> Option<&str> { self.name }
+    pub fn arguments(&self) -> &[Argument] { &self.arguments }
+}
+
+
+/// Every direct system call in `program`.
+pub fn syscalls(program: &ir::Program<ir::Constant>, loader: &Loader) -> Result<Vec<Syscall>> {
+    let architecture = loader.architecture();
+    let (number_register, argument_registers) =
+        abi(architecture.name())
+            .ok_or(format!("No syscall ABI for architecture {}", architecture.name()))?;
+    let bits = architecture.word_size();
+
+    let mut syscalls = Vec::new();
+
+    for function in program.functions() {
+        let function_index = match function.index() {
+            Some(index) => index,
+            None => continue
+        };
+        let values = match Values::new(function) {
+            Some(values) => values,
+            None => continue
+        };
+
+        for block in function.blocks() {
+            for instruction in block.instructions() {
+                let intrinsic = match instruction.operation() {
+                    ir::Operation::Intrinsic(intrinsic) => intrinsic,
+                    _ => continue
+                };
+                if !is_syscall(intrinsic) {
+                    continue;
+                }
+
+                let location = InstructionLocation::new(
+                    function_index, block.index(), instruction.index());
+                let argument = |register: &'static str| {
+                    let expression =
+                        ir::Expression::from_il(&il::expr_scalar(register, bits));
+                    Argument {
+                        register: register,
+                        value: values.evaluate(&location, &expression),
+                        expression: values.resolve(&location, &expression)
+                    }
+                };
+
+                let number = argument(number_register);
+                let name =
+                    number.value()
+                        .and_then(|number| syscall_name(architecture.name(), number));
+
+                syscalls.push(Syscall {
+                    location: location,
+                    instruction_str: intrinsic.instruction_str().to_string(),
+                    number: number,
+                    name: name,
+                    arguments: argument_registers.iter()
+                        .map(|register| argument(*register))
+                        .collect()
+                });
+            }
+        }
+    }
+
+    Ok(syscalls)
+}
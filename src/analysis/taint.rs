@@ -0,0 +1,416 @@
+use analysis::cfg::Cfg;
+use analysis::dataflow::{self, Location, ReachingDefinitions};
+use analysis::InstructionLocation;
+use error::*;
+use raptor::ir;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+
+
+/// A symbol whose call introduces tainted data.
+#[derive(Clone, Debug)]
+pub struct Source {
+    symbol: String,
+    return_value: bool,
+    arguments: Vec<usize>
+}
+
+
+impl Source {
+    /// `return_value` taints whatever the call writes. Each of `arguments`
+    /// is an argument position whose pointed-to data becomes tainted, such as
+    /// the buffer passed to `read`.
+    pub fn new<S: Into<String>>(symbol: S, return_value: bool, arguments: Vec<usize>)
+        -> Source {
+
+        Source {
+            symbol: symbol.into(),
+            return_value: return_value,
+            arguments: arguments
+        }
+    }
+
+    pub fn symbol(&self) -> &str { &self.symbol }
+    pub fn return_value(&self) -> bool { self.return_value }
+    pub fn arguments(&self) -> &[usize] { &self.arguments }
+}
+
+
+/// A symbol which must not receive tainted data in `arguments`.
+#[derive(Clone, Debug)]
+pub struct Sink {
+    symbol: String,
+    arguments: Vec<usize>
+}
+
+
+impl Sink {
+    pub fn new<S: Into<String>>(symbol: S, arguments: Vec<usize>) -> Sink {
+        Sink {
+            symbol: symbol.into(),
+            arguments: arguments
+        }
+    }
+
+    pub fn symbol(&self) -> &str { &self.symbol }
+    pub fn arguments(&self) -> &[usize] { &self.arguments }
+}
+
+
+/// Tainted data flowing from a source call to a sensitive argument of a sink
+/// call.
+#[derive(Clone, Debug)]
+pub struct Flow {
+    source: String,
+    source_location: InstructionLocation,
+    sink: String,
+    sink_location: InstructionLocation,
+    argument: usize,
+    path: Vec<InstructionLocation>
+}
+
+
+impl Flow {
+    pub fn source(&self) -> &str { &self.source }
+    pub fn source_location(&self) -> &InstructionLocation { &self.source_location }
+    pub fn sink(&self) -> &str { &self.sink }
+    pub fn sink_location(&self) -> &InstructionLocation { &self.sink_location }
+    pub fn argument(&self) -> usize { self.argument }
+
+    /// Every instruction the tainted data passes through, from the source
+    /// call to the sink call.
+    pub fn path(&self) -> &[InstructionLocation] { &self.path }
+}
+
+
+fn call_symbol(instruction: &ir::Instruction<ir::Constant>) -> Option<&str> {
+    instruction.operation()
+        .call()
+        .and_then(|call| call.target().symbol())
+        .map(|symbol| &symbol[..])
+}
+
+
+/// Locations made tainted by the expression passed as a pointer argument.
+///
+/// When the argument is the address of a variable, that variable is
+/// tainted. Otherwise we taint memory, and the pointer itself, so passing
+/// the pointer along still counts as passing the tainted data.
+fn pointed_to(argument: &ir::Expression<ir::Constant>) -> BTreeSet<Location> {
+    if let Some(variable) =
+        dataflow::expression_reference(argument)
+            .and_then(|reference| dataflow::expression_variable(reference)) {
+        let mut locations = BTreeSet::new();
+        locations.insert(Location::Variable(variable.clone()));
+        return locations;
+    }
+
+    let mut locations = dataflow::expression_uses(argument);
+    locations.insert(Location::Memory);
+    locations
+}
+
+
+type Node = (InstructionLocation, Location);
+
+
+/// Propagates taint from source calls to sink calls over a program.
+pub struct TaintAnalysis<'p> {
+    program: &'p ir::Program<ir::Constant>,
+    sources: Vec<Source>,
+    sinks: Vec<Sink>,
+    reaching_definitions: HashMap<usize, ReachingDefinitions>,
+    cfgs: HashMap<usize, Cfg>,
+    /// Blocks which may execute after each block, by function and block
+    /// index.
+    blocks_after: HashMap<(usize, usize), BTreeSet<usize>>
+}
+
+
+impl<'p> TaintAnalysis<'p> {
+    pub fn new(
+        program: &'p ir::Program<ir::Constant>,
+        sources: Vec<Source>,
+        sinks: Vec<Sink>
+    ) -> TaintAnalysis<'p> {
+        TaintAnalysis {
+            program: program,
+            sources: sources,
+            sinks: sinks,
+            reaching_definitions: HashMap::new(),
+            cfgs: HashMap::new(),
+            blocks_after: HashMap::new()
+        }
+    }
+
+    fn function(&self, index: usize) -> Result<&'p ir::Function<ir::Constant>> {
+        self.program
+            .function(index)
+            .ok_or(format!("Could not find function-index: {}", index).into())
+    }
+
+    fn reaching_definitions(&mut self, function_index: usize)
+        -> Result<&ReachingDefinitions> {
+
+        if !self.reaching_definitions.contains_key(&function_index) {
+            let function = self.function(function_index)?;
+            let reaching_definitions =
+                ReachingDefinitions::new(function)
+                    .ok_or(format!("Function {} has no index", function_index))?;
+            self.reaching_definitions.insert(function_index, reaching_definitions);
+        }
+        Ok(&self.reaching_definitions[&function_index])
+    }
+
+    /// Whether `reader` may execute after the instruction at `location`.
+    fn may_follow(&mut self, location: &InstructionLocation, reader: &InstructionLocation)
+        -> Result<bool> {
+
+        let function_index = location.function_index();
+        let key = (function_index, location.block_index());
+        if !self.blocks_after.contains_key(&key) {
+            if !self.cfgs.contains_key(&function_index) {
+                let cfg = Cfg::new(self.function(function_index)?);
+                self.cfgs.insert(function_index, cfg);
+            }
+            let cfg = &self.cfgs[&function_index];
+            let mut blocks = BTreeSet::new();
+            for successor in cfg.successors(location.block_index()) {
+                blocks.append(&mut cfg.reachable_from(*successor));
+            }
+            self.blocks_after.insert(key, blocks);
+        }
+
+        if self.blocks_after[&key].contains(&reader.block_index()) {
+            return Ok(true);
+        }
+        if reader.block_index() != location.block_index() {
+            return Ok(false);
+        }
+
+        // Instruction indices need not follow the order within the block.
+        let function = self.function(function_index)?;
+        Ok(function.blocks()
+            .into_iter()
+            .find(|block| block.index() == location.block_index())
+            .map(|block|
+                dataflow::block_locations(function_index, block)
+                    .into_iter()
+                    .skip_while(|l| l != location)
+                    .skip(1)
+                    .any(|l| l == *reader))
+            .unwrap_or(false))
+    }
+
+    fn instruction(&self, location: &InstructionLocation)
+        -> Result<&'p ir::Instruction<ir::Constant>> {
+
+        let function = self.function(location.function_index())?;
+        dataflow::instruction(function, location)
+            .ok_or(format!("Could not find instruction {}:{} in function {}",
+                location.block_index(),
+                location.instruction_index(),
+                location.function_index()).into())
+    }
+
+    /// Run the analysis over every function, returning every flow found.
+    pub fn flows(&mut self) -> Result<Vec<Flow>> {
+        let mut flows = Vec::new();
+
+        let program = self.program;
+        for function in program.functions() {
+            let function_index = match function.index() {
+                Some(index) => index,
+                None => continue
+            };
+            for block in function.blocks() {
+                for location in dataflow::block_locations(function_index, block) {
+                    let symbol =
+                        match call_symbol(self.instruction(&location)?) {
+                            Some(symbol) => symbol.to_string(),
+                            None => continue
+                        };
+                    let sources: Vec<Source> =
+                        self.sources.iter()
+                            .filter(|source| source.symbol() == symbol)
+                            .cloned()
+                            .collect();
+                    for source in sources {
+                        flows.append(&mut self.flows_from(&source, location)?);
+                    }
+                }
+            }
+        }
+
+        Ok(flows)
+    }
+
+    /// Seeds for a call to `source` at `location`.
+    fn seeds(&mut self, source: &Source, location: InstructionLocation)
+        -> Result<Vec<Node>> {
+
+        let mut seeds = Vec::new();
+
+        if source.return_value() {
+            for def in self.reaching_definitions(location.function_index())?
+                           .defs(&location) {
+                seeds.push((location, def));
+            }
+        }
+
+        if let Some(arguments) =
+            self.instruction(&location)?
+                .operation()
+                .call()
+                .and_then(|call| call.arguments()) {
+            for position in source.arguments() {
+                if let Some(argument) = arguments.get(*position) {
+                    for tainted in pointed_to(argument) {
+                        seeds.push((location, tainted));
+                    }
+                }
+            }
+        }
+
+        Ok(seeds)
+    }
+
+    /// Instructions which read `location` with the value it holds just
+    /// after `node`'s instruction executes.
+    fn readers(&mut self, node: &Node) -> Result<BTreeSet<InstructionLocation>> {
+        let (instruction, ref location) = *node;
+
+        let rd = self.reaching_definitions(instruction.function_index())?;
+        if rd.defs(&instruction).contains(location) {
+            return Ok(rd.uses_of(&instruction, location));
+        }
+
+        // The instruction taints this location without the IR recording a
+        // write, such as a buffer filled by a call. Find reads after the
+        // instruction which see the same definitions it did.
+        let before = rd.reaching(&instruction, location);
+        let candidates: BTreeSet<InstructionLocation> =
+            if before.is_empty() {
+                rd.exposed_uses_of(location)
+            }
+            else {
+                before.iter()
+                    .flat_map(|definition| rd.uses_of(definition, location))
+                    .collect()
+            };
+
+        let mut readers = BTreeSet::new();
+        for reader in candidates {
+            if self.may_follow(&instruction, &reader)? {
+                readers.insert(reader);
+            }
+        }
+        Ok(readers)
+    }
+
+    fn flows_from(&mut self, source: &Source, location: InstructionLocation)
+        -> Result<Vec<Flow>> {
+
+        let mut flows = Vec::new();
+        let mut parents: BTreeMap<Node, Node> = BTreeMap::new();
+        let mut visited: BTreeSet<Node> = BTreeSet::new();
+        let mut queue: VecDeque<Node> = self.seeds(source, location)?.into();
+        let mut reported = BTreeSet::new();
+
+        while let Some(node) = queue.pop_front() {
+            if !visited.insert(node.clone()) {
+                continue;
+            }
+
+            for reader in self.readers(&node)? {
+                let instruction = self.instruction(&reader)?;
+
+                if let Some(symbol) = call_symbol(instruction) {
+                    let sinks: Vec<Sink> =
+                        self.sinks.iter()
+                            .filter(|sink| sink.symbol() == symbol)
+                            .cloned()
+                            .collect();
+                    let arguments =
+                        match instruction.operation()
+                                         .call()
+                                         .and_then(|call| call.arguments()) {
+                            Some(arguments) => arguments,
+                            None => Vec::new()
+                        };
+                    for sink in sinks {
+                        for position in sink.arguments() {
+                            let tainted =
+                                arguments.get(*position)
+                                    .map(|argument|
+                                        dataflow::expression_uses(argument)
+                                            .contains(&node.1))
+                                    .unwrap_or(false);
+                            if tainted && reported.insert((reader, *position)) {
+                                flows.push(Flow {
+                                    source: source.symbol().to_string(),
+                                    source_location: location,
+                                    sink: sink.symbol().to_string(),
+                                    sink_location: reader,
+                                    argument: *position,
+                                    path: path(&parents, &node, reader)
+                                });
+                            }
+                        }
+                    }
+                }
+
+                let mut next: Vec<Node> = Vec::new();
+                for def in self.reaching_definitions(reader.function_index())?
+                               .defs(&reader) {
+                    next.push((reader, def));
+                }
+
+                // Follow tainted arguments into functions we have IR for.
+                if let Some(ir::CallTarget::FunctionId(callee)) =
+                    instruction.operation().call().map(|call| call.target()) {
+                    for (exposed, used) in
+                        self.reaching_definitions(*callee)?.upward_exposed_uses() {
+                        if used == node.1 {
+                            for def in self.reaching_definitions(*callee)?
+                                           .defs(&exposed) {
+                                next.push((exposed, def));
+                            }
+                        }
+                    }
+                }
+
+                for child in next {
+                    if !visited.contains(&child) && !parents.contains_key(&child) {
+                        parents.insert(child.clone(), node.clone());
+                    }
+                    queue.push_back(child);
+                }
+            }
+        }
+
+        Ok(flows)
+    }
+}
+
+
+/// Walk `parents` back from `node` to build the path ending at `last`.
+fn path(
+    parents: &BTreeMap<Node, Node>,
+    node: &Node,
+    last: InstructionLocation
+) -> Vec<InstructionLocation> {
+    let mut path = vec![last];
+    let mut node = node.clone();
+    loop {
+        if path.last() != Some(&node.0) {
+            path.push(node.0);
+        }
+        match parents.get(&node) {
+            Some(parent) => node = parent.clone(),
+            None => break
+        }
+    }
+    path.reverse();
+    path
+}
+
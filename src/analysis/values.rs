@@ -0,0 +1,221 @@
+use analysis::dataflow::{self, Location, ReachingDefinitions};
+use analysis::expression;
+use analysis::InstructionLocation;
+use raptor::ir;
+
+
+/// How many definitions we follow back when resolving an expression.
+const MAX_DEPTH: usize = 16;
+
+
+/// Resolves expressions to what they hold at an instruction, by following
+/// single reaching definitions backwards through a function.
+///
+/// This is a cheap local substitute for a full value analysis. It is
+/// enough to recover constants and stack buffer addresses which were
+/// loaded into a register just before a call.
+pub struct Values<'f> {
+    function: &'f ir::Function<ir::Constant>,
+    reaching_definitions: ReachingDefinitions
+}
+
+
+impl<'f> Values<'f> {
+    /// `None` when `function` has no index.
+    pub fn new(function: &'f ir::Function<ir::Constant>) -> Option<Values<'f>> {
+        Some(Values {
+            function: function,
+            reaching_definitions: ReachingDefinitions::new(function)?
+        })
+    }
+
+    pub fn function(&self) -> &'f ir::Function<ir::Constant> { self.function }
+
+    pub fn reaching_definitions(&self) -> &ReachingDefinitions {
+        &self.reaching_definitions
+    }
+
+    /// Replace variables in `expression` with the expression assigned to
+    /// them, when exactly one assignment reaches `location`.
+    pub fn resolve(
+        &self,
+        location: &InstructionLocation,
+        expression: &ir::Expression<ir::Constant>
+    ) -> ir::Expression<ir::Constant> {
+        self.resolve_depth(location, expression, 0)
+    }
+
+    fn resolve_depth(
+        &self,
+        location: &InstructionLocation,
+        expression: &ir::Expression<ir::Constant>,
+        depth: usize
+    ) -> ir::Expression<ir::Constant> {
+        if depth >= MAX_DEPTH {
+            return expression.clone();
+        }
+
+        let r = |e: &ir::Expression<ir::Constant>|
+            Box::new(self.resolve_depth(location, e, depth + 1));
+
+        match expression {
+            ir::Expression::LValue(_) => {
+                let variable = match dataflow::expression_variable(expression) {
+                    Some(variable) => variable,
+                    None => return expression.clone()
+                };
+                let definitions =
+                    self.reaching_definitions.reaching(
+                        location, &Location::Variable(variable.clone()));
+                if definitions.len() != 1 {
+                    return expression.clone();
+                }
+                let definition = definitions.into_iter().next().unwrap();
+                match dataflow::instruction(self.function, &definition)
+                        .map(|instruction| instruction.operation()) {
+                    Some(ir::Operation::Assign { src, .. }) =>
+                        self.resolve_depth(&definition, src, depth + 1),
+                    _ => expression.clone()
+                }
+            },
+            ir::Expression::RValue(_) => expression.clone(),
+            ir::Expression::Add(lhs, rhs) => ir::Expression::Add(r(lhs), r(rhs)),
+            ir::Expression::Sub(lhs, rhs) => ir::Expression::Sub(r(lhs), r(rhs)),
+            ir::Expression::Mul(lhs, rhs) => ir::Expression::Mul(r(lhs), r(rhs)),
+            ir::Expression::Divu(lhs, rhs) => ir::Expression::Divu(r(lhs), r(rhs)),
+            ir::Expression::Modu(lhs, rhs) => ir::Expression::Modu(r(lhs), r(rhs)),
+            ir::Expression::Divs(lhs, rhs) => ir::Expression::Divs(r(lhs), r(rhs)),
+            ir::Expression::Mods(lhs, rhs) => ir::Expression::Mods(r(lhs), r(rhs)),
+            ir::Expression::And(lhs, rhs) => ir::Expression::And(r(lhs), r(rhs)),
+            ir::Expression::Or(lhs, rhs) => ir::Expression::Or(r(lhs), r(rhs)),
+            ir::Expression::Xor(lhs, rhs) => ir::Expression::Xor(r(lhs), r(rhs)),
+            ir::Expression::Shl(lhs, rhs) => ir::Expression::Shl(r(lhs), r(rhs)),
+            ir::Expression::Shr(lhs, rhs) => ir::Expression::Shr(r(lhs), r(rhs)),
+            ir::Expression::Cmpeq(lhs, rhs) => ir::Expression::Cmpeq(r(lhs), r(rhs)),
+            ir::Expression::Cmpneq(lhs, rhs) => ir::Expression::Cmpneq(r(lhs), r(rhs)),
+            ir::Expression::Cmplts(lhs, rhs) => ir::Expression::Cmplts(r(lhs), r(rhs)),
+            ir::Expression::Cmpltu(lhs, rhs) => ir::Expression::Cmpltu(r(lhs), r(rhs)),
+            ir::Expression::Trun(bits, rhs) => ir::Expression::Trun(*bits, r(rhs)),
+            ir::Expression::Sext(bits, rhs) => ir::Expression::Sext(*bits, r(rhs)),
+            ir::Expression::Zext(bits, rhs) => ir::Expression::Zext(*bits, r(rhs)),
+            ir::Expression::Ite(cond, then, else_) =>
+                ir::Expression::Ite(r(cond), r(then), r(else_))
+        }
+    }
+
+    /// The constant value of `expression` at `location`, if it has one.
+    pub fn evaluate(
+        &self,
+        location: &InstructionLocation,
+        expression: &ir::Expression<ir::Constant>
+    ) -> Option<u64> {
+        fold(&self.resolve(location, expression)).map(|(value, _)| value)
+    }
+
+    /// If `expression` holds the address of a stack variable at `location`,
+    /// return that variable.
+    pub fn stack_buffer(
+        &self,
+        location: &InstructionLocation,
+        expression: &ir::Expression<ir::Constant>
+    ) -> Option<ir::StackVariable> {
+        let resolved = self.resolve(location, expression);
+        dataflow::expression_reference(&resolved)
+            .and_then(|reference| expression::stack_variable(reference))
+            .cloned()
+    }
+}
+
+
+fn mask(value: u64, bits: usize) -> u64 {
+    if bits >= 64 { value } else { value & ((1u64 << bits) - 1) }
+}
+
+
+fn sign_extend(value: u64, bits: usize) -> i64 {
+    if bits == 0 || bits >= 64 {
+        value as i64
+    }
+    else {
+        let shift = 64 - bits;
+        ((value << shift) as i64) >> shift
+    }
+}
+
+
+/// Fold a fully resolved expression into a value and its width in bits.
+/// Values wider than 64 bits are not handled.
+pub fn fold(expression: &ir::Expression<ir::Constant>) -> Option<(u64, usize)> {
+    if let Some(constant) = expression::constant(expression) {
+        return constant.value_u64().map(|value| (value, constant.bits()));
+    }
+
+    let binop = |lhs: &ir::Expression<ir::Constant>,
+                 rhs: &ir::Expression<ir::Constant>,
+                 f: &dyn Fn(u64, u64, usize) -> Option<u64>| {
+        let (l, bits) = fold(lhs)?;
+        let (r, _) = fold(rhs)?;
+        f(l, r, bits).map(|value| (mask(value, bits), bits))
+    };
+
+    let cmp = |lhs: &ir::Expression<ir::Constant>,
+               rhs: &ir::Expression<ir::Constant>,
+               f: &dyn Fn(u64, u64, usize) -> bool| {
+        let (l, bits) = fold(lhs)?;
+        let (r, _) = fold(rhs)?;
+        Some((if f(l, r, bits) { 1 } else { 0 }, 1))
+    };
+
+    match expression {
+        ir::Expression::Add(lhs, rhs) =>
+            binop(lhs, rhs, &|l, r, _| Some(l.wrapping_add(r))),
+        ir::Expression::Sub(lhs, rhs) =>
+            binop(lhs, rhs, &|l, r, _| Some(l.wrapping_sub(r))),
+        ir::Expression::Mul(lhs, rhs) =>
+            binop(lhs, rhs, &|l, r, _| Some(l.wrapping_mul(r))),
+        ir::Expression::Divu(lhs, rhs) =>
+            binop(lhs, rhs, &|l, r, _| l.checked_div(r)),
+        ir::Expression::Modu(lhs, rhs) =>
+            binop(lhs, rhs, &|l, r, _| l.checked_rem(r)),
+        ir::Expression::Divs(lhs, rhs) =>
+            binop(lhs, rhs, &|l, r, bits|
+                sign_extend(l, bits).checked_div(sign_extend(r, bits))
+                    .map(|v| v as u64)),
+        ir::Expression::Mods(lhs, rhs) =>
+            binop(lhs, rhs, &|l, r, bits|
+                sign_extend(l, bits).checked_rem(sign_extend(r, bits))
+                    .map(|v| v as u64)),
+        ir::Expression::And(lhs, rhs) => binop(lhs, rhs, &|l, r, _| Some(l & r)),
+        ir::Expression::Or(lhs, rhs) => binop(lhs, rhs, &|l, r, _| Some(l | r)),
+        ir::Expression::Xor(lhs, rhs) => binop(lhs, rhs, &|l, r, _| Some(l ^ r)),
+        ir::Expression::Shl(lhs, rhs) =>
+            binop(lhs, rhs, &|l, r, _|
+                Some(if r >= 64 { 0 } else { l << r })),
+        ir::Expression::Shr(lhs, rhs) =>
+            binop(lhs, rhs, &|l, r, _|
+                Some(if r >= 64 { 0 } else { l >> r })),
+        ir::Expression::Cmpeq(lhs, rhs) => cmp(lhs, rhs, &|l, r, _| l == r),
+        ir::Expression::Cmpneq(lhs, rhs) => cmp(lhs, rhs, &|l, r, _| l != r),
+        ir::Expression::Cmplts(lhs, rhs) =>
+            cmp(lhs, rhs, &|l, r, bits| sign_extend(l, bits) < sign_extend(r, bits)),
+        ir::Expression::Cmpltu(lhs, rhs) => cmp(lhs, rhs, &|l, r, _| l < r),
+        ir::Expression::Trun(bits, rhs) => {
+            let (value, _) = fold(rhs)?;
+            Some((mask(value, *bits), *bits))
+        },
+        ir::Expression::Zext(bits, rhs) => {
+            let (value, _) = fold(rhs)?;
+            Some((value, *bits))
+        },
+        ir::Expression::Sext(bits, rhs) => {
+            let (value, from) = fold(rhs)?;
+            Some((mask(sign_extend(value, from) as u64, *bits), *bits))
+        },
+        ir::Expression::Ite(cond, then, else_) => {
+            let (cond, _) = fold(cond)?;
+            if cond != 0 { fold(then) } else { fold(else_) }
+        },
+        ir::Expression::LValue(_) |
+        ir::Expression::RValue(_) => None
+    }
+}
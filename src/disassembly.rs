@@ -0,0 +1,283 @@
+//! Decoding the machine instructions IR was lifted from.
+
+use error::*;
+use falcon::architecture::Endian;
+use falcon::il;
+use falcon::loader::Loader;
+use falcon_capstone::capstone;
+use raptor::ir;
+use std::collections::BTreeMap;
+
+
+/// The longest instruction of any architecture we decode.
+pub const MAX_INSTRUCTION_LENGTH: u64 = 16;
+
+
+/// An IR instruction, by function, block and instruction index.
+pub type IrLocation = (usize, usize, usize);
+
+
+/// A decoded machine instruction.
+#[derive(Clone, Debug)]
+pub struct Instruction {
+    address: u64,
+    bytes: Vec<u8>,
+    mnemonic: String,
+    operands: String,
+    ir: Vec<IrLocation>
+}
+
+
+impl Instruction {
+    pub fn address(&self) -> u64 { self.address }
+    pub fn bytes(&self) -> &[u8] { &self.bytes }
+    pub fn length(&self) -> usize { self.bytes.len() }
+    pub fn mnemonic(&self) -> &str { &self.mnemonic }
+    pub fn operands(&self) -> &str { &self.operands }
+
+    /// IR instructions lifted from this instruction.
+    pub fn ir(&self) -> &[IrLocation] { &self.ir }
+
+    /// False for bytes the decoder couldn't make sense of.
+    pub fn valid(&self) -> bool { self.mnemonic != "(bad)" }
+}
+
+
+/// The lowest and highest instruction addresses of a lifted block.
+fn block_bounds(block: &il::Block) -> Option<(u64, u64)> {
+    let addresses =
+        block.instructions()
+            .iter()
+            .filter_map(|instruction| instruction.address());
+    match (addresses.clone().min(), addresses.max()) {
+        (Some(first), Some(last)) => Some((first, last)),
+        _ => None
+    }
+}
+
+
+/// The lifted function with a block covering `address`.
+pub fn il_function_at(il_program: &il::Program, address: u64) -> Option<&il::Function> {
+    il_program.functions()
+        .into_iter()
+        .find(|function|
+            function.blocks()
+                .iter()
+                .filter_map(|block| block_bounds(block))
+                .any(|(first, last)|
+                    first <= address && address < last.saturating_add(MAX_INSTRUCTION_LENGTH)))
+}
+
+
+pub struct Disassembler<'l> {
+    loader: &'l Loader,
+    capstone: capstone::Capstone,
+    /// Instructions are aligned to, and invalid bytes skipped by, this much.
+    alignment: u64,
+    ir: BTreeMap<u64, Vec<IrLocation>>,
+    /// The bounds of the lifted block each lifted address belongs to.
+    blocks: BTreeMap<u64, (u64, u64)>
+}
+
+
+impl<'l> Disassembler<'l> {
+    pub fn new(
+        loader: &'l Loader,
+        il_program: &il::Program,
+        program: &ir::Program<ir::Constant>
+    ) -> Result<Disassembler<'l>> {
+
+        let mut disassembler = Disassembler::without_ir(loader)?;
+        for function in il_program.functions() {
+            disassembler.add_blocks(function);
+        }
+        for function in program.functions() {
+            disassembler.add_ir(function);
+        }
+        Ok(disassembler)
+    }
+
+    /// A disassembler which only knows the blocks and IR of one function.
+    pub fn for_function(
+        loader: &'l Loader,
+        il_function: &il::Function,
+        function: &ir::Function<ir::Constant>
+    ) -> Result<Disassembler<'l>> {
+
+        let mut disassembler = Disassembler::without_ir(loader)?;
+        disassembler.add_blocks(il_function);
+        disassembler.add_ir(function);
+        Ok(disassembler)
+    }
+
+    /// A disassembler which attaches no IR to the instructions it decodes.
+    pub fn without_ir(loader: &'l Loader) -> Result<Disassembler<'l>> {
+        let architecture = loader.architecture();
+        let endian = match architecture.endian() {
+            Endian::Big => capstone::cs_mode::CS_MODE_BIG_ENDIAN,
+            Endian::Little => capstone::cs_mode::CS_MODE_LITTLE_ENDIAN
+        };
+        let (arch, mode, alignment) = match architecture.name() {
+            "amd64" => (capstone::cs_arch::CS_ARCH_X86, capstone::cs_mode::CS_MODE_64, 1),
+            "x86" => (capstone::cs_arch::CS_ARCH_X86, capstone::cs_mode::CS_MODE_32, 1),
+            "mips" | "mipsel" =>
+                (capstone::cs_arch::CS_ARCH_MIPS, capstone::cs_mode::CS_MODE_32 | endian, 4),
+            "mips64" | "mips64el" =>
+                (capstone::cs_arch::CS_ARCH_MIPS, capstone::cs_mode::CS_MODE_64 | endian, 4),
+            "ppc" => (capstone::cs_arch::CS_ARCH_PPC, capstone::cs_mode::CS_MODE_32 | endian, 4),
+            name => bail!("No disassembler for architecture {}", name)
+        };
+
+        let capstone =
+            capstone::Capstone::new(arch, mode)
+                .map_err(|e| format!("Could not create disassembler: {:?}", e))?;
+
+        Ok(Disassembler {
+            loader: loader,
+            capstone: capstone,
+            alignment: alignment,
+            ir: BTreeMap::new(),
+            blocks: BTreeMap::new()
+        })
+    }
+
+    fn add_blocks(&mut self, function: &il::Function) {
+        for block in function.blocks() {
+            let bounds = match block_bounds(block) {
+                Some(bounds) => bounds,
+                None => continue
+            };
+            for instruction in block.instructions() {
+                if let Some(address) = instruction.address() {
+                    self.blocks.insert(address, bounds);
+                }
+            }
+        }
+    }
+
+    fn add_ir(&mut self, function: &ir::Function<ir::Constant>) {
+        let function_index = match function.index() {
+            Some(index) => index,
+            None => return
+        };
+        for block in function.blocks() {
+            for instruction in block.instructions() {
+                if let Some(address) = instruction.address() {
+                    self.ir.entry(address)
+                        .or_insert_with(Vec::new)
+                        .push((function_index, block.index(), instruction.index()));
+                }
+            }
+        }
+    }
+
+    /// Bytes of memory from `address`, up to `end` or the first unmapped
+    /// byte.
+    fn read(&self, address: u64, end: u64) -> Result<Vec<u8>> {
+        let memory = self.loader.memory()?;
+        let mut bytes = Vec::new();
+        let mut address = address;
+        while address < end {
+            match memory.get8(address) {
+                Some(byte) => bytes.push(byte),
+                None => break
+            }
+            address += 1;
+        }
+        Ok(bytes)
+    }
+
+    fn ir_at(&self, address: u64) -> Vec<IrLocation> {
+        self.ir.get(&address).cloned().unwrap_or_default()
+    }
+
+    /// Decode the instruction at `address`, or `None` when there are no
+    /// bytes there.
+    pub fn instruction(&self, address: u64) -> Result<Option<Instruction>> {
+        let bytes = self.read(address, address + MAX_INSTRUCTION_LENGTH)?;
+        if bytes.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(self.decode(address, &bytes)))
+    }
+
+    /// Decode the first instruction of `bytes`, found at `address`. Bytes
+    /// which don't decode make an invalid instruction `alignment` long.
+    fn decode(&self, address: u64, bytes: &[u8]) -> Instruction {
+        let decoded =
+            self.capstone.disasm(bytes, address, 1)
+                .ok()
+                .and_then(|instructions| instructions.iter().next());
+
+        match decoded {
+            Some(decoded) => Instruction {
+                address: address,
+                bytes: bytes[..decoded.size as usize].to_vec(),
+                mnemonic: decoded.mnemonic.to_string(),
+                operands: decoded.op_str.to_string(),
+                ir: self.ir_at(address)
+            },
+            None => {
+                let length = (self.alignment as usize).min(bytes.len());
+                Instruction {
+                    address: address,
+                    bytes: bytes[..length].to_vec(),
+                    mnemonic: "(bad)".to_string(),
+                    operands: String::new(),
+                    ir: self.ir_at(address)
+                }
+            }
+        }
+    }
+
+    /// The instruction of a lifted block whose bytes cover `address`, even
+    /// when it starts before it. The block is decoded from its start, so
+    /// this finds instructions whose IR was optimized away too.
+    pub fn containing(&self, address: u64) -> Result<Option<Instruction>> {
+        let (first, last) =
+            match self.blocks.range(..=address).next_back() {
+                Some((_, bounds)) => *bounds,
+                None => return Ok(None)
+            };
+        if address >= last.saturating_add(MAX_INSTRUCTION_LENGTH) {
+            return Ok(None);
+        }
+        Ok(self.range(first, address.saturating_add(1))?
+            .into_iter()
+            .find(|instruction|
+                address < instruction.address() + instruction.length() as u64))
+    }
+
+    /// Linearly decode the instructions starting in `[start, end)`. Stops
+    /// early at unmapped memory.
+    pub fn range(&self, start: u64, end: u64) -> Result<Vec<Instruction>> {
+        let bytes = self.read(start, end.saturating_add(MAX_INSTRUCTION_LENGTH))?;
+        let mut instructions = Vec::new();
+        let mut offset = 0usize;
+        while start + (offset as u64) < end && offset < bytes.len() {
+            let instruction = self.decode(start + offset as u64, &bytes[offset..]);
+            offset += instruction.length();
+            instructions.push(instruction);
+        }
+        Ok(instructions)
+    }
+
+    /// Decode every instruction in the blocks of `function` as lifted, in
+    /// address order. Instructions whose IR was optimized away are included,
+    /// with no IR.
+    pub fn function(&self, function: &il::Function) -> Result<Vec<Instruction>> {
+        let function_index = function.index();
+        let mut instructions = BTreeMap::new();
+        for block in function.blocks() {
+            let (first, last) = match block_bounds(block) {
+                Some(bounds) => bounds,
+                None => continue
+            };
+            for mut instruction in self.range(first, last.saturating_add(1))? {
+                instruction.ir.retain(|location| Some(location.0) == function_index);
+                instructions.insert(instruction.address(), instruction);
+            }
+        }
+        Ok(instructions.into_iter().map(|(_, instruction)| instruction).collect())
+    }
+}
@@ -0,0 +1,238 @@
+//! Finding functions recursive descent from the loader's entries misses,
+//! such as those only reached through function pointers.
+
+use disassembly::{self, Disassembler};
+use elf::Elf;
+use error::*;
+use falcon::architecture::Endian;
+use falcon::il;
+use falcon::loader::Loader;
+use falcon::memory::MemoryPermissions;
+use log::info;
+use std::collections::{BTreeMap, BTreeSet};
+
+
+/// How a function was found.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Discovery {
+    /// Recursive descent from the loader's function entries.
+    Recursive,
+    /// The start of an FDE in `.eh_frame`.
+    EhFrame,
+    /// A pointer into executable memory found in data.
+    Pointer,
+    /// A known function prologue in executable memory.
+    Prologue,
+    /// Created on request with `Document::add_function`.
+    User
+}
+
+
+impl Discovery {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Discovery::Recursive => "recursive",
+            Discovery::EhFrame => "eh-frame",
+            Discovery::Pointer => "pointer",
+            Discovery::Prologue => "prologue",
+            Discovery::User => "user"
+        }
+    }
+}
+
+
+/// Byte patterns starting a function, with `None` matching any byte, and
+/// the alignment functions start at.
+fn prologues(architecture: &str) -> (Vec<Vec<Option<u8>>>, u64) {
+    let bytes = |bytes: &[u8]| bytes.iter().map(|b| Some(*b)).collect::<Vec<Option<u8>>>();
+    match architecture {
+        "amd64" => (vec![
+            // push rbp; mov rbp, rsp
+            bytes(&[0x55, 0x48, 0x89, 0xe5]),
+            bytes(&[0x55, 0x48, 0x8b, 0xec]),
+            // endbr64
+            bytes(&[0xf3, 0x0f, 0x1e, 0xfa])
+        ], 1),
+        "x86" => (vec![
+            // push ebp; mov ebp, esp
+            bytes(&[0x55, 0x89, 0xe5]),
+            bytes(&[0x55, 0x8b, 0xec]),
+            // endbr32
+            bytes(&[0xf3, 0x0f, 0x1e, 0xfb])
+        ], 1),
+        // addiu $sp, $sp, -n
+        "mips" => (vec![vec![Some(0x27), Some(0xbd), Some(0xff), None]], 4),
+        "mipsel" => (vec![vec![None, Some(0xff), Some(0xbd), Some(0x27)]], 4),
+        // daddiu $sp, $sp, -n under n64, and addiu as before
+        "mips64" => (vec![
+            vec![Some(0x67), Some(0xbd), Some(0xff), None],
+            vec![Some(0x27), Some(0xbd), Some(0xff), None]
+        ], 4),
+        "mips64el" => (vec![
+            vec![None, Some(0xff), Some(0xbd), Some(0x67)],
+            vec![None, Some(0xff), Some(0xbd), Some(0x27)]
+        ], 4),
+        // stwu r1, -n(r1)
+        "ppc" => (vec![vec![Some(0x94), Some(0x21), Some(0xff), None]], 4),
+        _ => (Vec::new(), 1)
+    }
+}
+
+
+/// Byte ranges of lifted instructions, as the end of the instruction
+/// starting at each address.
+struct Covered<'l> {
+    disassembler: Option<Disassembler<'l>>,
+    ranges: BTreeMap<u64, u64>
+}
+
+
+impl<'l> Covered<'l> {
+    fn new(loader: &'l Loader) -> Covered<'l> {
+        Covered {
+            // Without a disassembler we only know where instructions start.
+            disassembler: Disassembler::without_ir(loader).ok(),
+            ranges: BTreeMap::new()
+        }
+    }
+
+    fn insert(&mut self, address: u64) {
+        if self.ranges.contains_key(&address) {
+            return;
+        }
+        let length =
+            self.disassembler.as_ref()
+                .and_then(|disassembler| disassembler.instruction(address).ok())
+                .and_then(|instruction| instruction)
+                .map(|instruction| instruction.length() as u64)
+                .unwrap_or(1)
+                .max(1);
+        self.ranges.insert(address, address.saturating_add(length));
+    }
+
+    fn insert_function(&mut self, function: &il::Function) {
+        self.insert(function.address());
+        for block in function.blocks() {
+            for instruction in block.instructions() {
+                if let Some(address) = instruction.address() {
+                    self.insert(address);
+                }
+            }
+        }
+    }
+
+    /// Whether `address` falls within any lifted instruction.
+    fn contains(&self, address: u64) -> bool {
+        let first = address.saturating_sub(disassembly::MAX_INSTRUCTION_LENGTH);
+        self.ranges.range(first..=address).any(|(_, end)| address < *end)
+    }
+}
+
+
+/// Candidate function starts, in the order we trust them.
+fn candidates(loader: &Loader, bytes: &[u8]) -> Result<Vec<(u64, Discovery)>> {
+    let architecture = loader.architecture();
+    let memory = loader.memory()?;
+
+    let is_executable = |address: u64|
+        memory.permissions(address)
+            .map(|permissions| permissions.contains(MemoryPermissions::EXECUTE))
+            .unwrap_or(false);
+
+    let mut candidates = Vec::new();
+
+    // The ELF may not have section headers, in which case we just go
+    // without.
+    if let Ok(elf) = Elf::parse(bytes) {
+        for address in elf.eh_frame_functions().unwrap_or_default() {
+            candidates.push((address, Discovery::EhFrame));
+        }
+    }
+
+    let word = architecture.word_size() / 8;
+    let big_endian = match architecture.endian() {
+        Endian::Big => true,
+        Endian::Little => false
+    };
+    let (patterns, alignment) = prologues(architecture.name());
+
+    for (base, section) in memory.sections() {
+        let data = section.data();
+        if section.permissions().contains(MemoryPermissions::EXECUTE) {
+            let mut offset = 0usize;
+            while offset < data.len() {
+                let matched = patterns.iter().any(|pattern|
+                    data.len() - offset >= pattern.len() &&
+                    pattern.iter()
+                        .zip(data[offset..].iter())
+                        .all(|(p, b)| p.map(|p| p == *b).unwrap_or(true)));
+                if matched {
+                    candidates.push((base + offset as u64, Discovery::Prologue));
+                }
+                offset += alignment as usize;
+            }
+        }
+        else {
+            // Pointers are stored aligned to their size.
+            let skip = ((word as u64 - base % word as u64) % word as u64) as usize;
+            for chunk in data.get(skip..).unwrap_or_default().chunks(word) {
+                if chunk.len() != word {
+                    continue;
+                }
+                let value =
+                    if big_endian {
+                        chunk.iter().fold(0u64, |value, b| (value << 8) | *b as u64)
+                    }
+                    else {
+                        chunk.iter().rev().fold(0u64, |value, b| (value << 8) | *b as u64)
+                    };
+                if value % alignment == 0 && is_executable(value) {
+                    candidates.push((value, Discovery::Pointer));
+                }
+            }
+        }
+    }
+
+    // Pointers come ahead of prologues, as they are less likely to be
+    // chance matches.
+    candidates.sort_by_key(|&(_, discovery)| match discovery {
+        Discovery::EhFrame => 0,
+        Discovery::Pointer => 1,
+        _ => 2
+    });
+
+    Ok(candidates)
+}
+
+
+/// Lift functions `program` is missing, adding them to it. Returns how each
+/// new function was found, by address.
+pub fn discover(loader: &Loader, bytes: &[u8], program: &mut il::Program)
+    -> Result<BTreeMap<u64, Discovery>> {
+
+    let mut covered = Covered::new(loader);
+    for function in program.functions() {
+        covered.insert_function(function);
+    }
+    let mut tried = BTreeSet::new();
+    let mut discovered = BTreeMap::new();
+
+    for (address, discovery) in candidates(loader, bytes)? {
+        // Prologue bytes in the middle of an instruction are a chance match,
+        // as is anything else landing inside code we already lifted.
+        if covered.contains(address) || !tried.insert(address) {
+            continue;
+        }
+
+        let function = match loader.function(address) {
+            Ok(function) => function,
+            Err(_) => continue
+        };
+        covered.insert_function(&function);
+        info!("Discovered function at 0x{:x} by {}", address, discovery.as_str());
+        program.add_function(function);
+        discovered.insert(address, discovery);
+    }
+
+    Ok(discovered)
+}
@@ -1,4 +1,6 @@
+use discovery::{self, Discovery};
 use error::*;
+use falcon::il;
 use falcon::loader::Loader;
 use log::info;
 use raptor::analysis;
@@ -6,28 +8,123 @@ use raptor::features::XRefs;
 use raptor::ir;
 use raptor::translator::ProgramTranslator;
 use rayon::prelude::*;
+use std::collections::BTreeMap;
 use std::ops::Deref;
-use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+
+/// What `Document::translate` does to each function after lifting.
+#[derive(Clone, Debug)]
+pub struct Pipeline {
+    /// Run raptor's function optimizer.
+    pub optimize: bool,
+    /// Run dead code elimination until it stops changing the function.
+    pub dead_code_elimination: bool,
+    /// The most dead code elimination passes over a single function.
+    pub max_iterations: usize,
+    /// Keep a copy of the IR from before translation.
+    pub keep_unoptimized: bool,
+    /// Look for functions recursive descent missed, with
+    /// `discovery::discover`.
+    pub discover_functions: bool
+}
+
+
+impl Default for Pipeline {
+    fn default() -> Pipeline {
+        Pipeline {
+            optimize: true,
+            dead_code_elimination: true,
+            max_iterations: 64,
+            keep_unoptimized: false,
+            discover_functions: false
+        }
+    }
+}
+
+
+/// A function lifted and translated, but not yet part of its document.
+pub struct Lifted {
+    index: usize,
+    il_function: il::Function,
+    unoptimized: ir::Function<ir::Constant>,
+    function: ir::Function<ir::Constant>
+}
+
+
+/// `program` with the function at `index` swapped for `function`.
+fn replace_il_function(program: &il::Program, index: usize, function: il::Function)
+    -> Result<il::Program> {
+
+    let mut function = Some(function);
+    let mut replaced = il::Program::new();
+    for existing in program.functions() {
+        if existing.index() == Some(index) {
+            replaced.add_function(function.take().ok_or("Duplicate function index")?);
+        }
+        else {
+            replaced.add_function(existing.clone());
+        }
+    }
+    if function.is_some() {
+        bail!("Could not find IL function {}", index);
+    }
+
+    // add_function numbers functions as they come, which only keeps their
+    // indices when there are no gaps.
+    let indices = |program: &il::Program| -> Vec<Option<usize>> {
+        program.functions().into_iter().map(|function| function.index()).collect()
+    };
+    if indices(&replaced) != indices(program) {
+        bail!("Could not keep function indices replacing IL function {}", index);
+    }
+
+    Ok(replaced)
+}
 
 
 pub struct Document {
     loader: Box<Loader>,
+    bytes: Vec<u8>,
+    il_program: Arc<il::Program>,
     program: RwLock<ir::Program<ir::Constant>>,
+    unoptimized: Option<ir::Program<ir::Constant>>,
+    pipeline: Pipeline,
+    discovered: BTreeMap<u64, Discovery>,
     xrefs: XRefs
 }
 
 
 impl Document {
-    pub fn new(loader: Box<Loader>) -> Result<Document> {
-        let program = loader.program_recursive()?;
+    pub fn new(loader: Box<Loader>, bytes: Vec<u8>, pipeline: Pipeline)
+        -> Result<Document> {
+
+        let mut il_program = loader.program_recursive()?;
+        let discovered =
+            if pipeline.discover_functions {
+                discovery::discover(loader.as_ref(), &bytes, &mut il_program)?
+            }
+            else {
+                BTreeMap::new()
+            };
+        let program = ir::Program::<ir::Constant>::from_il(&il_program)?;
         Ok(Document {
             loader: loader,
-            program: RwLock::new(ir::Program::<ir::Constant>::from_il(&program)?),
+            bytes: bytes,
+            il_program: Arc::new(il_program),
+            program: RwLock::new(program),
+            unoptimized: None,
+            pipeline: pipeline,
+            discovered: discovered,
             xrefs: XRefs::new()
         })
     }
 
     pub fn loader(&self) -> &Loader { self.loader.as_ref() }
+
+    /// The file the document was loaded from.
+    pub fn bytes(&self) -> &[u8] { &self.bytes }
+    pub fn il_program(&self) -> Arc<il::Program> { self.il_program.clone() }
     pub fn program(&self) -> Result<RwLockReadGuard<ir::Program<ir::Constant>>> {
         self.program
             .read()
@@ -39,58 +136,184 @@ impl Document {
             .map_err(|_| "Lock poisoned for document program".into())
     }
 
+    /// The IR as lifted, before translation. Only kept when the pipeline
+    /// asks for it.
+    pub fn unoptimized(&self) -> Option<&ir::Program<ir::Constant>> {
+        self.unoptimized.as_ref()
+    }
+
+    pub fn pipeline(&self) -> &Pipeline { &self.pipeline }
+
     pub fn xrefs(&self) -> &XRefs { &self.xrefs }
 
+    /// How the function at `address` was found.
+    pub fn discovery(&self, address: u64) -> Discovery {
+        self.discovered.get(&address)
+            .cloned()
+            .unwrap_or(Discovery::Recursive)
+    }
+
+    /// Run the pipeline over a single function. Returns `None` when raptor's
+    /// optimizer fails on it, and an error when dead code elimination does.
+    fn optimize(
+        &self,
+        program_translator: &ProgramTranslator,
+        function: ir::Function<ir::Constant>
+    ) -> Result<Option<ir::Function<ir::Constant>>> {
+        let mut function =
+            if self.pipeline.optimize {
+                match program_translator.function_translator().optimize_function(function) {
+                    Ok(function) => function,
+                    Err(_) => return Ok(None)
+                }
+            }
+            else {
+                function
+            };
+
+        if self.pipeline.dead_code_elimination {
+            for _ in 0..self.pipeline.max_iterations {
+                let new_function = analysis::dead_code_elimination(&function)?;
+                if new_function == function {
+                    break;
+                }
+                function = new_function;
+            }
+        }
+
+        Ok(Some(function))
+    }
+
+    fn program_translator(&self) -> Result<ProgramTranslator> {
+        Ok(ProgramTranslator::new(
+            self.loader().architecture().box_clone(),
+            self.loader().architecture().calling_convention(),
+            self.loader()
+        )?)
+    }
+
+    fn refresh_xrefs(&mut self) -> Result<()> {
+        self.xrefs = XRefs::from_program(self.program()?.deref());
+        Ok(())
+    }
+
+    /// The index `il_program` gives the next function added to it.
+    fn next_index(&self) -> usize {
+        self.il_program.functions()
+            .into_iter()
+            .filter_map(|function| function.index())
+            .max()
+            .map(|index| index + 1)
+            .unwrap_or(0)
+    }
+
+    fn lift(&self, address: u64, index: usize) -> Result<Lifted> {
+        let mut il_function = self.loader().function(address)?;
+        il_function.set_index(Some(index));
+
+        // Like `translate`, leave the function as lifted when the optimizer
+        // fails on it.
+        let unoptimized = ir::Function::<ir::Constant>::from_il(&il_function)?;
+        let function =
+            self.optimize(&self.program_translator()?, unoptimized.clone())?
+                .unwrap_or_else(|| unoptimized.clone());
+
+        Ok(Lifted {
+            index: index,
+            il_function: il_function,
+            unoptimized: unoptimized,
+            function: function
+        })
+    }
+
+    /// Lift and translate a function at `address`, which must not already
+    /// be a function, without changing the document. This only needs a
+    /// shared borrow, so it can run without holding the store's write lock.
+    /// Add the result with `add_function`.
+    pub fn lift_function(&self, address: u64) -> Result<Lifted> {
+        if self.program()?.functions().into_iter().any(|f| f.address() == address) {
+            bail!("There is already a function at 0x{:x}", address);
+        }
+        self.lift(address, self.next_index())
+    }
+
+    /// Add a function from `lift_function`. Returns the new function's index.
+    pub fn add_function(&mut self, lifted: Lifted) -> Result<usize> {
+        let address = lifted.il_function.address();
+        if self.program()?.functions().into_iter().any(|f| f.address() == address) {
+            bail!("There is already a function at 0x{:x}", address);
+        }
+        if lifted.index != self.next_index() {
+            bail!("Functions were added while lifting 0x{:x}", address);
+        }
+
+        Arc::make_mut(&mut self.il_program).add_function(lifted.il_function);
+        if let Some(ref mut unoptimized) = self.unoptimized {
+            unoptimized.add_function(lifted.unoptimized);
+        }
+        self.program_mut()?.add_function(lifted.function);
+        self.discovered.insert(address, Discovery::User);
+
+        self.refresh_xrefs()?;
+
+        // Report the index the program gave the function, as that is what
+        // every other request takes.
+        Ok(self.program()?
+            .functions()
+            .into_iter()
+            .find(|function| function.address() == address)
+            .and_then(|function| function.index())
+            .ok_or("Lost the translated function")?)
+    }
+
+    /// Lift and translate the function at `index` again, without changing
+    /// the document. Put the result in place with `replace_function`.
+    pub fn relift_function(&self, index: usize) -> Result<Lifted> {
+        let address =
+            self.program()?
+                .function(index)
+                .ok_or(format!("Could not find function {}", index))?
+                .address();
+        self.lift(address, index)
+    }
+
+    /// Replace a function with its translation from `relift_function`.
+    pub fn replace_function(&mut self, lifted: Lifted) -> Result<()> {
+        let index = lifted.index;
+        let address = lifted.il_function.address();
+        if self.program()?.function(index).map(|f| f.address()) != Some(address) {
+            bail!("Function {} changed while lifting 0x{:x}", index, address);
+        }
+
+        self.il_program =
+            Arc::new(replace_il_function(&self.il_program, index, lifted.il_function)?);
+        if let Some(ref mut unoptimized) = self.unoptimized {
+            unoptimized.replace_function(index, lifted.unoptimized);
+        }
+        self.program_mut()?.replace_function(index, lifted.function);
+
+        self.refresh_xrefs()
+    }
+
     pub fn translate(&mut self) -> Result<()> {
+        if self.pipeline.keep_unoptimized {
+            self.unoptimized = Some(self.program()?.clone());
+        }
+
         info!("Translating functions");
         let functions = {
-            let program_translator = ProgramTranslator::new(
-                self.loader().architecture().box_clone(),
-                self.loader().architecture().calling_convention(),
-                self.loader()
-            )?;
-
-            let function_translator = program_translator.function_translator();
-
-            let functions:
-                ::std::result::Result<
-                    Vec<ir::Function<ir::Constant>>,
-                    Error
-                > =
-                self.program()?
-                    .functions()
-                    // .into_par_iter()
-                    // .try_fold(|| Vec::new(), |mut v, function| {
-                    .into_iter()
-                    .try_fold(Vec::new(), |mut v, function| {
-                        println!("{}", function.name());
-                        let mut function = match function_translator.optimize_function(function.clone()) {
-                            Ok(f) => f,
-                            Err(_) => return Ok(v)
-                        };
-                        // let mut function =
-                        //     function_translator.optimize_function(function.clone())?;
-                        loop {
-                            let new_function =
-                                analysis::dead_code_elimination(&function)?;
-                            if new_function == function {
-                                function = new_function;
-                                break;
-                            }
-                            else {
-                                function = new_function;
-                            }
-                        }
-                        info!("Done with {}", function.name());
-                        v.push(function);
-                        Ok(v)
-                    // })  
-                    // .try_reduce(|| Vec::new(), |mut v, mut functions| {
-                    //     v.append(&mut functions);
-                    //     Ok(v)
-                    });
-
-            functions?
+            let program_translator = self.program_translator()?;
+
+            let mut functions = Vec::new();
+            for function in self.program()?.functions() {
+                // Functions the optimizer chokes on are left as lifted.
+                if let Some(function) = self.optimize(&program_translator, function.clone())? {
+                    info!("Done with {}", function.name());
+                    functions.push(function);
+                }
+            }
+
+            functions
         };
 
         for function in functions {
@@ -99,14 +322,9 @@ impl Document {
         }
 
         info!("Computing xrefs");
-
-        let xrefs = {
-            XRefs::from_program(self.program()?.deref())
-        };
-
-        self.xrefs = xrefs;
+        self.refresh_xrefs()?;
         info!("Done");
 
         Ok(())
     }
-}
\ No newline at end of file
+}
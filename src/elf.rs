@@ -0,0 +1,498 @@
+//! Just enough ELF parsing to find sections falcon's loader doesn't expose.
+
+use error::*;
+
+
+/// A section header.
+#[derive(Clone, Debug)]
+pub struct Section {
+    name: String,
+    address: u64,
+    offset: u64,
+    size: u64
+}
+
+
+impl Section {
+    pub fn name(&self) -> &str { &self.name }
+    pub fn address(&self) -> u64 { self.address }
+    pub fn offset(&self) -> u64 { self.offset }
+    pub fn size(&self) -> u64 { self.size }
+}
+
+
+/// The section headers of an ELF file, and what we need to read its data.
+pub struct Elf<'a> {
+    bytes: &'a [u8],
+    is_64: bool,
+    big_endian: bool,
+    sections: Vec<Section>
+}
+
+
+/// `bytes[start..end]`, when that lies within `bytes`.
+fn slice(bytes: &[u8], start: u64, end: u64) -> Option<&[u8]> {
+    if start > end || end > bytes.len() as u64 {
+        return None;
+    }
+    Some(&bytes[start as usize..end as usize])
+}
+
+
+/// `offset + field`, for offsets read from the file.
+fn add(offset: u64, field: u64) -> Result<u64> {
+    offset.checked_add(field)
+        .ok_or(format!("Offset 0x{:x} + 0x{:x} overflows", offset, field).into())
+}
+
+
+fn read(bytes: &[u8], offset: u64, size: usize, big_endian: bool) -> Result<u64> {
+    let data =
+        offset.checked_add(size as u64)
+            .and_then(|end| slice(bytes, offset, end))
+            .ok_or(format!("Read past the end of the ELF at 0x{:x}", offset))?;
+    let mut value = 0u64;
+    for i in 0..size {
+        let byte = if big_endian { data[i] } else { data[size - 1 - i] };
+        value = (value << 8) | byte as u64;
+    }
+    Ok(value)
+}
+
+
+impl<'a> Elf<'a> {
+    pub fn parse(bytes: &'a [u8]) -> Result<Elf<'a>> {
+        if bytes.len() < 0x34 || &bytes[0..4] != b"\x7fELF" {
+            bail!("Not an ELF file");
+        }
+        let is_64 = match bytes[4] {
+            1 => false,
+            2 => true,
+            class => bail!("Unknown ELF class {}", class)
+        };
+        let big_endian = match bytes[5] {
+            1 => false,
+            2 => true,
+            data => bail!("Unknown ELF data encoding {}", data)
+        };
+
+        let word = if is_64 { 8 } else { 4 };
+        let (shoff, shentsize, shnum, shstrndx) =
+            if is_64 {
+                (read(bytes, 0x28, 8, big_endian)?,
+                 read(bytes, 0x3a, 2, big_endian)?,
+                 read(bytes, 0x3c, 2, big_endian)?,
+                 read(bytes, 0x3e, 2, big_endian)?)
+            }
+            else {
+                (read(bytes, 0x20, 4, big_endian)?,
+                 read(bytes, 0x2e, 2, big_endian)?,
+                 read(bytes, 0x30, 2, big_endian)?,
+                 read(bytes, 0x32, 2, big_endian)?)
+            };
+
+        // (name offset, address, offset, size) for each header.
+        let mut headers = Vec::new();
+        for i in 0..shnum {
+            let header =
+                i.checked_mul(shentsize)
+                    .and_then(|offset| offset.checked_add(shoff))
+                    .ok_or(format!("Section header {} is out of range", i))?;
+            let field = |offset: u64, size: usize| -> Result<u64> {
+                read(bytes, add(header, offset)?, size, big_endian)
+            };
+            let name = field(0, 4)?;
+            let (address, offset, size) =
+                if is_64 {
+                    (field(0x10, word)?, field(0x18, word)?, field(0x20, word)?)
+                }
+                else {
+                    (field(0x0c, word)?, field(0x10, word)?, field(0x14, word)?)
+                };
+            headers.push((name, address, offset, size));
+        }
+
+        let strings_offset =
+            headers.get(shstrndx as usize)
+                .map(|&(_, _, offset, _)| offset)
+                .unwrap_or(0);
+
+        let mut sections = Vec::new();
+        for (name, address, offset, size) in headers {
+            let start = add(strings_offset, name)?;
+            let name =
+                slice(bytes, start, bytes.len() as u64)
+                    .map(|rest| {
+                        let end = rest.iter()
+                            .position(|b| *b == 0)
+                            .unwrap_or(rest.len());
+                        String::from_utf8_lossy(&rest[..end]).to_string()
+                    })
+                    .unwrap_or_default();
+            sections.push(Section {
+                name: name,
+                address: address,
+                offset: offset,
+                size: size
+            });
+        }
+
+        Ok(Elf {
+            bytes: bytes,
+            is_64: is_64,
+            big_endian: big_endian,
+            sections: sections
+        })
+    }
+
+    pub fn is_64(&self) -> bool { self.is_64 }
+    pub fn big_endian(&self) -> bool { self.big_endian }
+    pub fn sections(&self) -> &[Section] { &self.sections }
+
+    pub fn section(&self, name: &str) -> Option<&Section> {
+        self.sections.iter().find(|section| section.name() == name)
+    }
+
+    /// The file contents of `section`.
+    pub fn data(&self, section: &Section) -> Result<&'a [u8]> {
+        let end = add(section.offset(), section.size())?;
+        slice(self.bytes, section.offset(), end)
+            .ok_or(format!("Section {} runs past the end of the ELF", section.name()).into())
+    }
+
+    /// Pointers held in the section named `name`, such as `.init_array`.
+    /// Empty when there is no such section.
+    pub fn pointers(&self, name: &str) -> Result<Vec<u64>> {
+        let section = match self.section(name) {
+            Some(section) => section,
+            None => return Ok(Vec::new())
+        };
+        let data = self.data(section)?;
+        let word = if self.is_64 { 8 } else { 4 };
+        (0..data.len() / word)
+            .map(|i| read(data, (i * word) as u64, word, self.big_endian))
+            .collect()
+    }
+}
+
+
+fn uleb128(data: &[u8], offset: &mut usize) -> Result<u64> {
+    let mut value = 0u64;
+    let mut shift = 0u32;
+    loop {
+        let byte = *data.get(*offset).ok_or("Truncated LEB128")?;
+        *offset += 1;
+        // Bits past the 64th are dropped.
+        if shift < 64 {
+            value |= ((byte & 0x7f) as u64) << shift;
+            shift += 7;
+        }
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+}
+
+
+fn sleb128(data: &[u8], offset: &mut usize) -> Result<i64> {
+    let mut value = 0i64;
+    let mut shift = 0u32;
+    loop {
+        let byte = *data.get(*offset).ok_or("Truncated LEB128")?;
+        *offset += 1;
+        if shift < 64 {
+            value |= ((byte & 0x7f) as i64) << shift;
+            shift += 7;
+        }
+        if byte & 0x80 == 0 {
+            if shift < 64 && byte & 0x40 != 0 {
+                value |= -1i64 << shift;
+            }
+            return Ok(value);
+        }
+    }
+}
+
+
+impl<'a> Elf<'a> {
+    /// Read a pointer encoded with a `DW_EH_PE_*` encoding at `offset` into
+    /// `data`, which is loaded at `address`.
+    fn encoded_pointer(
+        &self,
+        data: &[u8],
+        address: u64,
+        offset: &mut usize,
+        encoding: u8
+    ) -> Result<u64> {
+        let word = if self.is_64 { 8 } else { 4 };
+        let field = *offset;
+        let fixed = |offset: &mut usize, size: usize| -> Result<u64> {
+            let value = read(data, *offset as u64, size, self.big_endian)?;
+            *offset += size;
+            Ok(value)
+        };
+        let sign_extend = |value: u64, bits: u32| -> u64 {
+            let shift = 64 - bits;
+            (((value << shift) as i64) >> shift) as u64
+        };
+
+        let value = match encoding & 0x0f {
+            0x00 => fixed(offset, word)?,
+            0x01 => uleb128(data, offset)?,
+            0x02 => fixed(offset, 2)?,
+            0x03 => fixed(offset, 4)?,
+            0x04 => fixed(offset, 8)?,
+            0x09 => sleb128(data, offset)? as u64,
+            0x0a => sign_extend(fixed(offset, 2)?, 16),
+            0x0b => sign_extend(fixed(offset, 4)?, 32),
+            0x0c => fixed(offset, 8)?,
+            format => bail!("Unknown pointer encoding format 0x{:x}", format)
+        };
+
+        Ok(match encoding & 0x70 {
+            // pc relative
+            0x10 => value.wrapping_add(address).wrapping_add(field as u64),
+            _ => value
+        })
+    }
+
+    /// The start address of every function described by an FDE in
+    /// `.eh_frame`.
+    pub fn eh_frame_functions(&self) -> Result<Vec<u64>> {
+        let section = match self.section(".eh_frame") {
+            Some(section) => section,
+            None => return Ok(Vec::new())
+        };
+        let data = self.data(section)?;
+        let word = if self.is_64 { 8 } else { 4 };
+
+        // FDE pointer encoding of each CIE, by offset.
+        let mut cie_encodings = ::std::collections::BTreeMap::new();
+        let mut functions = Vec::new();
+        let mut offset = 0usize;
+
+        while offset + 4 <= data.len() {
+            let start = offset;
+            let mut length = read(data, offset as u64, 4, self.big_endian)?;
+            offset += 4;
+            if length == 0 {
+                break;
+            }
+            if length == 0xffff_ffff {
+                length = read(data, offset as u64, 8, self.big_endian)?;
+                offset += 8;
+            }
+            let end = add(offset as u64, length)?;
+            if end > data.len() as u64 {
+                break;
+            }
+            let end = end as usize;
+
+            let id_offset = offset;
+            let id = read(data, offset as u64, 4, self.big_endian)?;
+            offset += 4;
+
+            if id == 0 {
+                // CIE
+                let version = *data.get(offset).ok_or("Truncated CIE")?;
+                offset += 1;
+                let augmentation_end =
+                    data.get(offset..end)
+                        .ok_or("Truncated CIE")?
+                        .iter()
+                        .position(|b| *b == 0)
+                        .ok_or("Unterminated CIE augmentation")?;
+                let augmentation = data[offset..offset + augmentation_end].to_vec();
+                offset += augmentation_end + 1;
+                if augmentation.starts_with(b"eh") {
+                    offset += word;
+                }
+                uleb128(data, &mut offset)?;
+                sleb128(data, &mut offset)?;
+                if version == 1 {
+                    offset += 1;
+                }
+                else {
+                    uleb128(data, &mut offset)?;
+                }
+
+                let mut encoding = 0u8;
+                if augmentation.first() == Some(&b'z') {
+                    uleb128(data, &mut offset)?;
+                    for c in &augmentation[1..] {
+                        match *c {
+                            b'R' => {
+                                encoding = *data.get(offset).ok_or("Truncated CIE")?;
+                                offset += 1;
+                            },
+                            b'L' => offset += 1,
+                            b'P' => {
+                                let personality = *data.get(offset).ok_or("Truncated CIE")?;
+                                offset += 1;
+                                self.encoded_pointer(
+                                    data, section.address(), &mut offset,
+                                    personality & 0x7f)?;
+                            },
+                            _ => {}
+                        }
+                    }
+                }
+                cie_encodings.insert(start, encoding);
+            }
+            else {
+                // FDE, whose id is the distance back to its CIE.
+                let cie = (id_offset as u64).checked_sub(id).map(|cie| cie as usize);
+                if let Some(encoding) = cie.and_then(|cie| cie_encodings.get(&cie)) {
+                    if *encoding != 0xff {
+                        let pc_begin = self.encoded_pointer(
+                            data, section.address(), &mut offset, *encoding)?;
+                        functions.push(pc_begin);
+                    }
+                }
+            }
+
+            offset = end;
+        }
+
+        functions.sort();
+        functions.dedup();
+        Ok(functions)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push(bytes: &mut Vec<u8>, value: u64, size: usize) {
+        for i in 0..size {
+            bytes.push((value >> (i * 8)) as u8);
+        }
+    }
+
+    /// A little endian ELF64 holding `.eh_frame`, loaded at `address`, and
+    /// the section name table.
+    fn elf(eh_frame: &[u8], address: u64) -> Vec<u8> {
+        let names = b"\0.shstrtab\0.eh_frame\0";
+        let mut bytes = b"\x7fELF\x02\x01\x01".to_vec();
+        bytes.resize(0x40, 0);
+        let names_offset = bytes.len() as u64;
+        bytes.extend_from_slice(names);
+        let eh_frame_offset = bytes.len() as u64;
+        bytes.extend_from_slice(eh_frame);
+
+        let shoff = bytes.len() as u64;
+        let sections = [
+            (0, 0, 0, 0),
+            (1, 0, names_offset, names.len() as u64),
+            (11, address, eh_frame_offset, eh_frame.len() as u64)
+        ];
+        for &(name, address, offset, size) in &sections {
+            let start = bytes.len();
+            push(&mut bytes, name, 4);
+            bytes.resize(start + 0x10, 0);
+            push(&mut bytes, address, 8);
+            push(&mut bytes, offset, 8);
+            push(&mut bytes, size, 8);
+            bytes.resize(start + 0x40, 0);
+        }
+
+        bytes[0x28..0x30].copy_from_slice(&shoff.to_le_bytes());
+        bytes[0x3a..0x3c].copy_from_slice(&0x40u16.to_le_bytes());
+        bytes[0x3c..0x3e].copy_from_slice(&(sections.len() as u16).to_le_bytes());
+        bytes[0x3e..0x40].copy_from_slice(&1u16.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn sections() {
+        let bytes = elf(&[1, 2, 3], 0x1000);
+        let elf = Elf::parse(&bytes).unwrap();
+        assert!(elf.is_64());
+        assert!(!elf.big_endian());
+        assert_eq!(elf.sections().len(), 3);
+        let section = elf.section(".eh_frame").unwrap();
+        assert_eq!(section.address(), 0x1000);
+        assert_eq!(elf.data(section).unwrap(), &[1, 2, 3]);
+        assert!(elf.section(".text").is_none());
+    }
+
+    #[test]
+    fn uleb128_values() {
+        let data = [0x02, 0xe5, 0x8e, 0x26, 0x80, 0x01];
+        let mut offset = 0;
+        assert_eq!(uleb128(&data, &mut offset).unwrap(), 2);
+        assert_eq!(uleb128(&data, &mut offset).unwrap(), 624485);
+        assert_eq!(uleb128(&data, &mut offset).unwrap(), 128);
+        assert_eq!(offset, data.len());
+        assert!(uleb128(&[0x80], &mut 0).is_err());
+    }
+
+    #[test]
+    fn uleb128_overlong() {
+        let mut data = vec![0xff; 20];
+        data.push(0x01);
+        let mut offset = 0;
+        assert_eq!(uleb128(&data, &mut offset).unwrap(), ::std::u64::MAX);
+        assert_eq!(offset, data.len());
+    }
+
+    #[test]
+    fn sleb128_values() {
+        let data = [0x02, 0x7e, 0xff, 0x00, 0x80, 0x7f, 0xc0, 0xbb, 0x78];
+        let mut offset = 0;
+        assert_eq!(sleb128(&data, &mut offset).unwrap(), 2);
+        assert_eq!(sleb128(&data, &mut offset).unwrap(), -2);
+        assert_eq!(sleb128(&data, &mut offset).unwrap(), 127);
+        assert_eq!(sleb128(&data, &mut offset).unwrap(), -128);
+        assert_eq!(sleb128(&data, &mut offset).unwrap(), -123456);
+        assert_eq!(offset, data.len());
+    }
+
+    #[test]
+    fn eh_frame_functions() {
+        let address = 0x1000;
+        let mut eh_frame = Vec::new();
+
+        // A CIE giving FDEs pc relative, signed 4 byte pointers.
+        push(&mut eh_frame, 16, 4);
+        push(&mut eh_frame, 0, 4);
+        eh_frame.extend_from_slice(&[1, b'z', b'R', 0, 1, 0x78, 16, 1, 0x1b, 0, 0, 0]);
+
+        // An FDE for 0x400.
+        push(&mut eh_frame, 16, 4);
+        let id_offset = eh_frame.len() as u64;
+        push(&mut eh_frame, id_offset, 4);
+        let field = eh_frame.len() as u64;
+        push(&mut eh_frame, 0x400u64.wrapping_sub(address + field), 4);
+        push(&mut eh_frame, 0x20, 4);
+        eh_frame.extend_from_slice(&[0, 0, 0, 0]);
+
+        push(&mut eh_frame, 0, 4);
+
+        let bytes = elf(&eh_frame, address);
+        let elf = Elf::parse(&bytes).unwrap();
+        assert!(elf.is_64());
+        assert!(!elf.big_endian());
+        assert_eq!(elf.section(".eh_frame").unwrap().address(), address);
+        assert_eq!(elf.eh_frame_functions().unwrap(), vec![0x400]);
+    }
+
+    #[test]
+    fn truncated_eh_frame() {
+        // The length runs past the end of the section.
+        let mut eh_frame = Vec::new();
+        push(&mut eh_frame, 0xffff_fff0, 4);
+        push(&mut eh_frame, 0, 4);
+        let bytes = elf(&eh_frame, 0x1000);
+        let elf = Elf::parse(&bytes).unwrap();
+        assert!(elf.eh_frame_functions().unwrap().is_empty());
+    }
+
+    #[test]
+    fn not_elf() {
+        assert!(Elf::parse(&[0; 0x40]).is_err());
+        assert!(Elf::parse(b"\x7fELF").is_err());
+    }
+}
@@ -0,0 +1,315 @@
+//! Concrete emulation of a document's functions with falcon's executor.
+
+use error::*;
+use falcon::architecture::Architecture;
+use falcon::executor;
+use falcon::il;
+use falcon::loader::Loader;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+
+/// Where the emulated function returns to. When execution branches here,
+/// the function has returned.
+pub const RETURN_ADDRESS: u64 = 0xdead_c0de;
+
+/// Stack pointer used when the caller doesn't supply one.
+pub const DEFAULT_STACK_POINTER: u64 = 0x7fff_0000;
+
+
+/// The state to start emulating from.
+#[derive(Clone, Debug, Default)]
+pub struct Setup {
+    registers: BTreeMap<String, il::Constant>,
+    memory: Vec<(u64, Vec<u8>)>,
+    max_steps: usize
+}
+
+
+impl Setup {
+    pub fn new(max_steps: usize) -> Setup {
+        Setup {
+            registers: BTreeMap::new(),
+            memory: Vec::new(),
+            max_steps: max_steps
+        }
+    }
+
+    pub fn set_register<S: Into<String>>(&mut self, name: S, value: il::Constant) {
+        self.registers.insert(name.into(), value);
+    }
+
+    /// Overwrite the loaded image at `address` with `bytes`.
+    pub fn set_memory(&mut self, address: u64, bytes: Vec<u8>) {
+        self.memory.push((address, bytes));
+    }
+}
+
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Stop {
+    /// The function branched to `RETURN_ADDRESS`.
+    Returned,
+    /// The driver stepped `max_steps` times, through IL instructions or
+    /// empty blocks.
+    StepLimit,
+    /// Execution branched to an address we have no IL for, usually a call
+    /// to an import.
+    UnresolvedCall(u64),
+    /// The executor failed, for example on an intrinsic or a read of
+    /// uninitialized memory.
+    Error(String)
+}
+
+
+impl Stop {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Stop::Returned => "returned",
+            Stop::StepLimit => "step-limit",
+            Stop::UnresolvedCall(_) => "unresolved-call",
+            Stop::Error(_) => "error"
+        }
+    }
+}
+
+
+/// One executed IL instruction.
+#[derive(Clone, Debug)]
+pub struct Step {
+    address: Option<u64>,
+    instruction: String
+}
+
+
+impl Step {
+    pub fn address(&self) -> Option<u64> { self.address }
+    pub fn instruction(&self) -> &str { &self.instruction }
+}
+
+
+/// A store performed during emulation.
+#[derive(Clone, Debug)]
+pub struct MemoryWrite {
+    address: u64,
+    value: il::Constant
+}
+
+
+impl MemoryWrite {
+    pub fn address(&self) -> u64 { self.address }
+    pub fn value(&self) -> &il::Constant { &self.value }
+}
+
+
+pub struct Emulation {
+    trace: Vec<Step>,
+    registers: BTreeMap<String, il::Constant>,
+    memory_writes: Vec<MemoryWrite>,
+    stop: Stop
+}
+
+
+impl Emulation {
+    pub fn trace(&self) -> &[Step] { &self.trace }
+
+    /// Final values of every register set up front or written during
+    /// emulation.
+    pub fn registers(&self) -> &BTreeMap<String, il::Constant> { &self.registers }
+    pub fn memory_writes(&self) -> &[MemoryWrite] { &self.memory_writes }
+    pub fn stop(&self) -> &Stop { &self.stop }
+}
+
+
+fn evaluate(state: &executor::State, expression: &il::Expression) -> Result<u64> {
+    let constant = executor::eval(&state.symbolize_expression(expression)?)?;
+    constant.value_u64()
+        .ok_or("Expression evaluated to more than 64 bits".into())
+}
+
+
+/// Put `RETURN_ADDRESS` where the architecture's return instruction will
+/// find it.
+fn set_return_address(
+    architecture: &Architecture,
+    state: &mut executor::State
+) -> Result<()> {
+    let word_size = architecture.word_size();
+    match architecture.name() {
+        "amd64" | "x86" => {
+            let stack_pointer = architecture.stack_pointer();
+            let sp = state.get_scalar(stack_pointer.name())
+                .and_then(|sp| sp.value_u64())
+                .ok_or("Stack pointer is not set")?;
+            state.memory_mut()
+                .store(sp, &il::Constant::new(RETURN_ADDRESS, word_size))?;
+        },
+        "mips" | "mipsel" | "mips64" | "mips64el" => {
+            state.set_scalar("$ra", il::Constant::new(RETURN_ADDRESS, word_size));
+        },
+        "ppc" => {
+            state.set_scalar("lr", il::Constant::new(RETURN_ADDRESS, word_size));
+        },
+        name => bail!("Emulation is not supported for {}", name)
+    }
+    Ok(())
+}
+
+
+fn final_registers(state: &executor::State, mut names: Vec<String>)
+    -> BTreeMap<String, il::Constant> {
+
+    names.sort();
+    names.dedup();
+
+    names.into_iter()
+        .filter_map(|name| {
+            let value = state.get_scalar(&name).cloned()?;
+            Some((name, value))
+        })
+        .collect()
+}
+
+
+/// Emulate the function starting at `address`.
+pub fn emulate(
+    loader: &Loader,
+    program: Arc<il::Program>,
+    address: u64,
+    setup: &Setup
+) -> Result<Emulation> {
+    let architecture = loader.architecture();
+
+    let backing = loader.memory()?;
+    let memory = executor::Memory::new_with_backing(
+        architecture.endian(), Arc::new(backing));
+    let mut state = executor::State::new(memory);
+
+    let stack_pointer = architecture.stack_pointer().name().to_string();
+    state.set_scalar(
+        stack_pointer.as_str(),
+        il::Constant::new(DEFAULT_STACK_POINTER, architecture.word_size()));
+
+    for (name, value) in &setup.registers {
+        state.set_scalar(name.as_str(), value.clone());
+    }
+
+    for &(address, ref bytes) in &setup.memory {
+        for (i, byte) in bytes.iter().enumerate() {
+            let address =
+                address.checked_add(i as u64)
+                    .ok_or(format!("Memory at 0x{:x} runs past the address space", address))?;
+            state.memory_mut()
+                .store(address, &il::Constant::new(*byte as u64, 8))?;
+        }
+    }
+
+    set_return_address(architecture, &mut state)?;
+
+    let location: il::ProgramLocation =
+        il::RefProgramLocation::from_address(&program, address)
+            .ok_or(format!("No IL for address 0x{:x}", address))?
+            .into();
+
+    let mut written_registers: Vec<String> =
+        setup.registers.keys().cloned().collect();
+    written_registers.push(stack_pointer);
+
+    let mut driver = executor::Driver::new(
+        program.clone(),
+        location,
+        state,
+        Arc::new(architecture.box_clone()));
+
+    let mut trace = Vec::new();
+    let mut memory_writes = Vec::new();
+    let mut stop = Stop::StepLimit;
+
+    // Empty blocks step the driver without adding to the trace, so count
+    // steps rather than trace entries, or a loop of them never stops.
+    for _ in 0..setup.max_steps {
+        let instruction =
+            driver.location()
+                .apply(&program)
+                .and_then(|location| location.instruction().cloned());
+
+        if let Some(ref instruction) = instruction {
+            match instruction.operation() {
+                il::Operation::Branch { target } => {
+                    match evaluate(driver.state(), target) {
+                        Ok(RETURN_ADDRESS) => {
+                            stop = Stop::Returned;
+                            break;
+                        },
+                        Ok(target) => {
+                            if il::RefProgramLocation::from_address(&program, target)
+                                    .is_none() {
+                                stop = Stop::UnresolvedCall(target);
+                                break;
+                            }
+                        },
+                        Err(e) => {
+                            stop = Stop::Error(format!("{}", e));
+                            break;
+                        }
+                    }
+                },
+                il::Operation::Store { index, src } => {
+                    let address = evaluate(driver.state(), index);
+                    let value = driver.state()
+                        .symbolize_expression(src)
+                        .and_then(|src| executor::eval(&src));
+                    if let (Ok(address), Ok(value)) = (address, value) {
+                        memory_writes.push(MemoryWrite {
+                            address: address,
+                            value: value
+                        });
+                    }
+                },
+                il::Operation::Assign { dst, .. } |
+                il::Operation::Load { dst, .. } => {
+                    written_registers.push(dst.name().to_string());
+                },
+                _ => {}
+            }
+
+            trace.push(Step {
+                address: instruction.address(),
+                instruction: format!("{}", instruction)
+            });
+        }
+
+        // Stepping consumes the driver, so keep a copy around when we're
+        // about to do something likely to fail, to report the state from.
+        let previous = match instruction.as_ref().map(|i| i.operation()) {
+            Some(il::Operation::Branch { .. }) |
+            Some(il::Operation::Intrinsic { .. }) |
+            None => Some(driver.clone()),
+            _ => None
+        };
+
+        driver = match driver.step() {
+            Ok(driver) => driver,
+            Err(e) => {
+                return Ok(Emulation {
+                    trace: trace,
+                    registers:
+                        previous.map(|previous|
+                            final_registers(previous.state(), written_registers))
+                        .unwrap_or_default(),
+                    memory_writes: memory_writes,
+                    stop: Stop::Error(format!("{}", e))
+                });
+            }
+        };
+    }
+
+    let registers = final_registers(driver.state(), written_registers);
+
+    Ok(Emulation {
+        trace: trace,
+        registers: registers,
+        memory_writes: memory_writes,
+        stop: stop
+    })
+}
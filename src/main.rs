@@ -1,5 +1,6 @@
 #[macro_use] extern crate error_chain;
 extern crate falcon;
+extern crate falcon_capstone;
 extern crate jsonrpc_http_server;
 extern crate log;
 extern crate owning_ref;
@@ -13,7 +14,13 @@ use std::sync::Arc;
 
 mod register_api;
 
+pub mod analysis;
+pub mod discovery;
+pub mod disassembly;
 pub mod document;
+pub mod elf;
+pub mod emulator;
+pub mod pattern;
 pub mod store;
 pub mod translate;
 
@@ -26,6 +33,7 @@ pub mod error {
 
         foreign_links {
             Falcon(::falcon::error::Error);
+            Io(::std::io::Error);
             Raptor(::raptor::error::Error);
         }
     }
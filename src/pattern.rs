@@ -0,0 +1,923 @@
+//! Byte patterns, and a subset of YARA rules, for searching loaded memory.
+//!
+//! Hex patterns are written as in YARA, such as
+//! `6a 40 ?? 00 [2-4] (01 | 02 03) e?`, though jumps must be bounded, no
+//! wider than `MAX_JUMP` bytes, and no more than `MAX_JUMPS` to a pattern.
+//! A rule file holds rules with a
+//! `strings:` section of hex and text strings, and a `condition:` made of
+//! string identifiers, `and`, `or`, `not`, parentheses, `true`, `false`,
+//! and `any`, `all` or a number `of them`.
+
+use disassembly::{Disassembler, IrLocation};
+use error::*;
+use falcon::il;
+use falcon::loader::Loader;
+use falcon::memory::MemoryPermissions;
+use raptor::ir;
+use std::collections::BTreeMap;
+
+
+#[derive(Clone, Debug)]
+enum Element {
+    /// A byte whose bits under the mask equal the value's.
+    Byte { value: u8, mask: u8 },
+    /// Either of two bytes, for case insensitive text.
+    Either(u8, u8),
+    /// Between `min` and `max` bytes of anything.
+    Jump { min: usize, max: usize },
+    Alternatives(Vec<Vec<Element>>)
+}
+
+
+/// The most bytes a single jump may skip. Every width of a jump is tried
+/// in turn, so wide jumps make matching slow.
+pub const MAX_JUMP: usize = 256;
+
+
+/// The most jumps a hex pattern may hold. Each multiplies the continuations
+/// tried from every candidate start.
+pub const MAX_JUMPS: usize = 2;
+
+
+/// Jumps in `elements`, including those inside alternatives.
+fn jumps(elements: &[Element]) -> usize {
+    elements.iter()
+        .map(|element| match *element {
+            Element::Jump { .. } => 1,
+            Element::Alternatives(ref alternatives) =>
+                alternatives.iter().map(|alternative| jumps(alternative)).sum(),
+            _ => 0
+        })
+        .sum()
+}
+
+
+#[derive(Clone, Debug)]
+pub struct Pattern {
+    elements: Vec<Element>,
+    /// Matches must not be preceded or followed by an alphanumeric
+    /// character.
+    fullword: bool
+}
+
+
+fn nibble(c: char) -> Result<(u8, u8)> {
+    match c {
+        '?' => Ok((0, 0)),
+        c => c.to_digit(16)
+            .map(|digit| (digit as u8, 0xf))
+            .ok_or(format!("Invalid hex digit {}", c).into())
+    }
+}
+
+
+/// Parse hex elements from `chars` at `position`, stopping at the end, or
+/// at a `|` or `)` belonging to an enclosing alternative.
+fn hex_elements(chars: &[char], position: &mut usize) -> Result<Vec<Element>> {
+    let mut elements = Vec::new();
+
+    while *position < chars.len() {
+        let c = chars[*position];
+        match c {
+            ' ' | '\t' | '\n' | '\r' => *position += 1,
+            '|' | ')' => break,
+            '(' => {
+                *position += 1;
+                let mut alternatives = Vec::new();
+                loop {
+                    alternatives.push(hex_elements(chars, position)?);
+                    match chars.get(*position) {
+                        Some('|') => *position += 1,
+                        Some(')') => { *position += 1; break; },
+                        _ => bail!("Unterminated alternative")
+                    }
+                }
+                elements.push(Element::Alternatives(alternatives));
+            },
+            '[' => {
+                let start = *position + 1;
+                let end =
+                    chars[start..].iter()
+                        .position(|c| *c == ']')
+                        .map(|end| start + end)
+                        .ok_or("Unterminated jump")?;
+                let jump: String = chars[start..end].iter().filter(|c| !c.is_whitespace()).collect();
+                let bound = |s: &str| -> Result<usize> {
+                    Ok(s.parse::<usize>().map_err(|_| format!("Invalid jump [{}]", jump))?)
+                };
+                let (min, max) = match jump.find('-') {
+                    Some(dash) => {
+                        if dash + 1 == jump.len() {
+                            bail!("Unbounded jump [{}] is not supported", jump);
+                        }
+                        let min = if dash == 0 { 0 } else { bound(&jump[..dash])? };
+                        (min, bound(&jump[dash + 1..])?)
+                    },
+                    None => {
+                        let n = bound(&jump)?;
+                        (n, n)
+                    }
+                };
+                if max < min {
+                    bail!("Invalid jump [{}]", jump);
+                }
+                if max > MAX_JUMP {
+                    bail!("Jump [{}] is wider than {} bytes", jump, MAX_JUMP);
+                }
+                elements.push(Element::Jump { min: min, max: max });
+                *position = end + 1;
+            },
+            _ => {
+                let low = *chars.get(*position + 1).ok_or("Odd number of hex digits")?;
+                let (high_value, high_mask) = nibble(c)?;
+                let (low_value, low_mask) = nibble(low)?;
+                elements.push(Element::Byte {
+                    value: (high_value << 4) | low_value,
+                    mask: (high_mask << 4) | low_mask
+                });
+                *position += 2;
+            }
+        }
+    }
+
+    Ok(elements)
+}
+
+
+/// Whether the match of `length` bytes at `offset` is delimited by
+/// non-alphanumeric characters. Wide matches, recognised by their second
+/// byte being zero, are checked for wide characters either side.
+fn delimited(data: &[u8], offset: usize, length: usize) -> bool {
+    let alphanumeric = |i: usize|
+        data.get(i).map(|b| b.is_ascii_alphanumeric()).unwrap_or(false);
+    let wide = length > 1 && data[offset + 1] == 0;
+    let before =
+        if wide {
+            offset >= 2 && data[offset - 1] == 0 && alphanumeric(offset - 2)
+        }
+        else {
+            offset >= 1 && alphanumeric(offset - 1)
+        };
+    !before && !alphanumeric(offset + length)
+}
+
+
+/// Elements left to match after those of an alternative: `elements`, then
+/// whatever follows the alternative enclosing this one.
+struct Continuation<'a> {
+    elements: &'a [Element],
+    then: Option<&'a Continuation<'a>>
+}
+
+
+/// The end of a match of `elements` and then `then` from `position`.
+/// Bytes are matched in a loop, only jumps and alternatives backtrack.
+fn matches<'a>(
+    elements: &'a [Element],
+    then: Option<&'a Continuation<'a>>,
+    data: &[u8],
+    position: usize
+) -> Option<usize> {
+    let mut position = position;
+    for (i, element) in elements.iter().enumerate() {
+        let rest = &elements[i + 1..];
+        match *element {
+            Element::Byte { value, mask } => {
+                if data.get(position)? & mask != value & mask {
+                    return None;
+                }
+                position += 1;
+            },
+            Element::Either(a, b) => {
+                let byte = *data.get(position)?;
+                if byte != a && byte != b {
+                    return None;
+                }
+                position += 1;
+            },
+            Element::Jump { min, max } => {
+                let available = data.len().saturating_sub(position);
+                return (min..max.min(available) + 1)
+                    .filter_map(|n| matches(rest, then, data, position + n))
+                    .next();
+            },
+            Element::Alternatives(ref alternatives) => {
+                let rest = Continuation { elements: rest, then: then };
+                return alternatives.iter()
+                    .filter_map(|alternative| matches(alternative, Some(&rest), data, position))
+                    .next();
+            }
+        }
+    }
+    match then {
+        Some(then) => matches(then.elements, then.then, data, position),
+        None => Some(position)
+    }
+}
+
+
+impl Pattern {
+    /// A YARA hex string, without the enclosing braces.
+    pub fn hex(text: &str) -> Result<Pattern> {
+        let chars: Vec<char> = text.chars().collect();
+        let mut position = 0;
+        let elements = hex_elements(&chars, &mut position)?;
+        if position != chars.len() {
+            bail!("Unexpected {} in hex pattern", chars[position]);
+        }
+        match elements.first() {
+            None => bail!("Empty hex pattern"),
+            Some(Element::Jump { .. }) => bail!("Hex patterns can't start with a jump"),
+            _ => {}
+        }
+        if jumps(&elements) > MAX_JUMPS {
+            bail!("Hex patterns can't hold more than {} jumps", MAX_JUMPS);
+        }
+        Ok(Pattern {
+            elements: elements,
+            fullword: false
+        })
+    }
+
+    /// A text string. Without `ascii` or `wide`, the text is matched as
+    /// ascii.
+    pub fn text(text: &[u8], ascii: bool, wide: bool, nocase: bool, fullword: bool)
+        -> Result<Pattern> {
+
+        if text.is_empty() {
+            bail!("Empty text string");
+        }
+
+        let byte = |b: u8| {
+            if nocase && b.is_ascii_alphabetic() {
+                Element::Either(b.to_ascii_lowercase(), b.to_ascii_uppercase())
+            }
+            else {
+                Element::Byte { value: b, mask: 0xff }
+            }
+        };
+
+        let mut alternatives = Vec::new();
+        if ascii || !wide {
+            alternatives.push(text.iter().map(|b| byte(*b)).collect());
+        }
+        if wide {
+            alternatives.push(
+                text.iter()
+                    .flat_map(|b| vec![byte(*b), Element::Byte { value: 0, mask: 0xff }])
+                    .collect());
+        }
+
+        let elements =
+            if alternatives.len() == 1 {
+                alternatives.pop().unwrap()
+            }
+            else {
+                vec![Element::Alternatives(alternatives)]
+            };
+        Ok(Pattern {
+            elements: elements,
+            fullword: fullword
+        })
+    }
+
+    /// The length of the match starting at `offset` into `data`, if there
+    /// is one.
+    pub fn match_at(&self, data: &[u8], offset: usize) -> Option<usize> {
+        matches(&self.elements, None, data, offset)
+            .map(|end| end - offset)
+            .filter(|length| !self.fullword || delimited(data, offset, *length))
+    }
+
+    /// Every match in `data`, as offset and length.
+    pub fn find_all(&self, data: &[u8]) -> Vec<(usize, usize)> {
+        (0..data.len())
+            .filter_map(|offset|
+                self.match_at(data, offset).map(|length| (offset, length)))
+            .collect()
+    }
+}
+
+
+#[derive(Clone, Debug)]
+enum Condition {
+    Bool(bool),
+    String(String),
+    /// At least this many of the rule's strings, `None` for all of them.
+    Of(Option<usize>),
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>)
+}
+
+
+impl Condition {
+    /// The first string identifier used which isn't in `strings`.
+    fn undefined<'a>(&'a self, strings: &[(String, Pattern)]) -> Option<&'a str> {
+        match *self {
+            Condition::String(ref id) =>
+                if strings.iter().any(|&(ref defined, _)| defined == id) {
+                    None
+                }
+                else {
+                    Some(id)
+                },
+            Condition::Not(ref condition) => condition.undefined(strings),
+            Condition::And(ref lhs, ref rhs) |
+            Condition::Or(ref lhs, ref rhs) =>
+                lhs.undefined(strings).or_else(|| rhs.undefined(strings)),
+            Condition::Bool(_) | Condition::Of(_) => None
+        }
+    }
+
+    fn evaluate(&self, matched: &BTreeMap<String, bool>) -> bool {
+        match *self {
+            Condition::Bool(b) => b,
+            Condition::String(ref id) => matched.get(id).cloned().unwrap_or(false),
+            Condition::Of(count) => {
+                let hits = matched.values().filter(|hit| **hit).count();
+                hits >= count.unwrap_or(matched.len())
+            },
+            Condition::Not(ref condition) => !condition.evaluate(matched),
+            Condition::And(ref lhs, ref rhs) =>
+                lhs.evaluate(matched) && rhs.evaluate(matched),
+            Condition::Or(ref lhs, ref rhs) =>
+                lhs.evaluate(matched) || rhs.evaluate(matched)
+        }
+    }
+}
+
+
+#[derive(Clone, Debug)]
+pub struct Rule {
+    name: String,
+    strings: Vec<(String, Pattern)>,
+    condition: Condition
+}
+
+
+impl Rule {
+    pub fn name(&self) -> &str { &self.name }
+
+    /// The rule's strings by identifier, such as `$a`.
+    pub fn strings(&self) -> &[(String, Pattern)] { &self.strings }
+
+    /// Whether the rule holds, given which of its strings matched.
+    pub fn holds(&self, matched: &BTreeMap<String, bool>) -> bool {
+        self.condition.evaluate(matched)
+    }
+}
+
+
+/// A cursor over rule text.
+struct Reader<'a> {
+    chars: &'a [char],
+    position: usize
+}
+
+
+impl<'a> Reader<'a> {
+    fn skip_whitespace(&mut self) {
+        while self.position < self.chars.len() {
+            if self.chars[self.position].is_whitespace() {
+                self.position += 1;
+            }
+            else if self.rest_starts_with("//") {
+                while self.position < self.chars.len() && self.chars[self.position] != '\n' {
+                    self.position += 1;
+                }
+            }
+            else if self.rest_starts_with("/*") {
+                self.position += 2;
+                while self.position < self.chars.len() && !self.rest_starts_with("*/") {
+                    self.position += 1;
+                }
+                // An unterminated comment runs to the end of the rules.
+                self.position = (self.position + 2).min(self.chars.len());
+            }
+            else {
+                break;
+            }
+        }
+    }
+
+    fn rest_starts_with(&self, s: &str) -> bool {
+        let mut position = self.position;
+        for c in s.chars() {
+            if self.chars.get(position) != Some(&c) {
+                return false;
+            }
+            position += 1;
+        }
+        true
+    }
+
+    fn done(&mut self) -> bool {
+        self.skip_whitespace();
+        self.position >= self.chars.len()
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.chars.get(self.position).cloned()
+    }
+
+    /// The next identifier, including a leading `$`.
+    fn word(&mut self) -> String {
+        self.skip_whitespace();
+        let start = self.position;
+        while self.position < self.chars.len() {
+            let c = self.chars[self.position];
+            if c.is_alphanumeric() || c == '_' || (c == '$' && self.position == start) {
+                self.position += 1;
+            }
+            else {
+                break;
+            }
+        }
+        self.chars[start..self.position].iter().collect()
+    }
+
+    fn expect(&mut self, c: char) -> Result<()> {
+        match self.peek() {
+            Some(found) if found == c => {
+                self.position += 1;
+                Ok(())
+            },
+            Some(found) => bail!("Expected {}, found {}", c, found),
+            None => bail!("Expected {}, found the end of the rules", c)
+        }
+    }
+
+    fn expect_word(&mut self, word: &str) -> Result<()> {
+        let found = self.word();
+        if found != word {
+            bail!("Expected {}, found {}", word, found);
+        }
+        Ok(())
+    }
+
+    /// Read up to, and past, `end`.
+    fn until(&mut self, end: char) -> Result<String> {
+        let start = self.position;
+        let offset =
+            self.chars.get(start..)
+                .and_then(|rest| rest.iter().position(|c| *c == end))
+                .ok_or(format!("Missing {}", end))?;
+        self.position = start + offset + 1;
+        Ok(self.chars[start..start + offset].iter().collect())
+    }
+
+    fn text(&mut self) -> Result<Vec<u8>> {
+        self.expect('"')?;
+        let mut bytes = Vec::new();
+        loop {
+            let c = *self.chars.get(self.position).ok_or("Unterminated string")?;
+            self.position += 1;
+            match c {
+                '"' => break,
+                '\\' => {
+                    let escaped = *self.chars.get(self.position).ok_or("Unterminated string")?;
+                    self.position += 1;
+                    match escaped {
+                        'n' => bytes.push(b'\n'),
+                        't' => bytes.push(b'\t'),
+                        'r' => bytes.push(b'\r'),
+                        '\\' => bytes.push(b'\\'),
+                        '"' => bytes.push(b'"'),
+                        'x' => {
+                            let digits: String =
+                                self.chars.get(self.position..self.position + 2)
+                                    .ok_or("Truncated \\x escape")?
+                                    .iter()
+                                    .collect();
+                            bytes.push(u8::from_str_radix(&digits, 16)
+                                .map_err(|_| format!("Invalid escape \\x{}", digits))?);
+                            self.position += 2;
+                        },
+                        c => bail!("Unknown escape \\{}", c)
+                    }
+                },
+                c => {
+                    let mut buf = [0u8; 4];
+                    bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+            }
+        }
+        Ok(bytes)
+    }
+
+    fn string(&mut self) -> Result<(String, Pattern)> {
+        let id = self.word();
+        if !id.starts_with('$') {
+            bail!("Expected a string identifier, found {}", id);
+        }
+        self.expect('=')?;
+        let pattern = match self.peek() {
+            Some('{') => {
+                self.position += 1;
+                Pattern::hex(&self.until('}')?)?
+            },
+            Some('"') => {
+                let text = self.text()?;
+                let (mut ascii, mut wide, mut nocase, mut fullword) =
+                    (false, false, false, false);
+                loop {
+                    let start = self.position;
+                    match self.word().as_str() {
+                        "ascii" => ascii = true,
+                        "wide" => wide = true,
+                        "nocase" => nocase = true,
+                        "fullword" => fullword = true,
+                        "private" => {},
+                        _ => {
+                            self.position = start;
+                            break;
+                        }
+                    }
+                }
+                Pattern::text(&text, ascii, wide, nocase, fullword)?
+            },
+            _ => bail!("Only hex and text strings are supported, in {}", id)
+        };
+        Ok((id, pattern))
+    }
+
+    fn condition(&mut self) -> Result<Condition> {
+        let mut lhs = self.and_condition()?;
+        loop {
+            let start = self.position;
+            if self.word() == "or" {
+                lhs = Condition::Or(Box::new(lhs), Box::new(self.and_condition()?));
+            }
+            else {
+                self.position = start;
+                return Ok(lhs);
+            }
+        }
+    }
+
+    fn and_condition(&mut self) -> Result<Condition> {
+        let mut lhs = self.unary_condition()?;
+        loop {
+            let start = self.position;
+            if self.word() == "and" {
+                lhs = Condition::And(Box::new(lhs), Box::new(self.unary_condition()?));
+            }
+            else {
+                self.position = start;
+                return Ok(lhs);
+            }
+        }
+    }
+
+    fn unary_condition(&mut self) -> Result<Condition> {
+        if self.peek() == Some('(') {
+            self.position += 1;
+            let condition = self.condition()?;
+            self.expect(')')?;
+            return Ok(condition);
+        }
+
+        let word = self.word();
+        match word.as_str() {
+            "not" => Ok(Condition::Not(Box::new(self.unary_condition()?))),
+            "true" => Ok(Condition::Bool(true)),
+            "false" => Ok(Condition::Bool(false)),
+            "any" | "all" => {
+                self.expect_word("of")?;
+                self.expect_word("them")?;
+                Ok(Condition::Of(if word == "any" { Some(1) } else { None }))
+            },
+            id if id.starts_with('$') => Ok(Condition::String(id.to_string())),
+            n => match n.parse::<usize>() {
+                Ok(count) => {
+                    self.expect_word("of")?;
+                    self.expect_word("them")?;
+                    Ok(Condition::Of(Some(count)))
+                },
+                Err(_) => bail!("Unsupported condition {}", n)
+            }
+        }
+    }
+
+    fn rule(&mut self) -> Result<Rule> {
+        let mut keyword = self.word();
+        while keyword == "private" || keyword == "global" {
+            keyword = self.word();
+        }
+        if keyword != "rule" {
+            bail!("Expected rule, found {}", keyword);
+        }
+        let name = self.word();
+        if name.is_empty() {
+            bail!("Missing rule name");
+        }
+        // Tags
+        if self.peek() == Some(':') {
+            self.position += 1;
+            while self.peek() != Some('{') && !self.done() {
+                if self.word().is_empty() {
+                    self.position += 1;
+                }
+            }
+        }
+        self.expect('{')?;
+
+        let mut section = self.word();
+        if section == "meta" {
+            self.expect(':')?;
+            // Skip metadata up to the next section.
+            loop {
+                if self.done() {
+                    bail!("Unterminated rule {}", name);
+                }
+                let word = self.word();
+                if word.is_empty() {
+                    // Quoted values may hold anything, including section
+                    // names.
+                    if self.peek() == Some('"') {
+                        self.text()?;
+                    }
+                    else {
+                        self.position += 1;
+                    }
+                }
+                else if (word == "strings" || word == "condition") && self.peek() == Some(':') {
+                    section = word;
+                    break;
+                }
+            }
+        }
+
+        let mut strings = Vec::new();
+        if section == "strings" {
+            self.expect(':')?;
+            while self.peek() == Some('$') {
+                strings.push(self.string()?);
+            }
+            section = self.word();
+        }
+
+        if section != "condition" {
+            bail!("Rule {} has no condition", name);
+        }
+        self.expect(':')?;
+        let condition = self.condition()?;
+        self.expect('}')?;
+        if let Some(id) = condition.undefined(&strings) {
+            bail!("Rule {} uses undefined string {}", name, id);
+        }
+
+        Ok(Rule {
+            name: name,
+            strings: strings,
+            condition: condition
+        })
+    }
+}
+
+
+/// Parse a file of rules.
+pub fn parse_rules(text: &str) -> Result<Vec<Rule>> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut reader = Reader {
+        chars: &chars,
+        position: 0
+    };
+
+    let mut rules = Vec::new();
+    while !reader.done() {
+        // Imports and includes have nothing for us.
+        let start = reader.position;
+        let word = reader.word();
+        if word == "import" || word == "include" {
+            reader.text()?;
+            continue;
+        }
+        reader.position = start;
+        rules.push(reader.rule()?);
+    }
+    Ok(rules)
+}
+
+
+/// A match in the loaded image.
+pub struct Hit {
+    rule: Option<String>,
+    string: String,
+    address: u64,
+    bytes: Vec<u8>,
+    executable: bool,
+    ir: Vec<IrLocation>
+}
+
+
+impl Hit {
+    /// The rule matched, or `None` for a plain pattern.
+    pub fn rule(&self) -> Option<&str> { self.rule.as_ref().map(|rule| rule.as_str()) }
+
+    /// The identifier of the matching string, `$0` and up for plain
+    /// patterns.
+    pub fn string(&self) -> &str { &self.string }
+    pub fn address(&self) -> u64 { self.address }
+    pub fn bytes(&self) -> &[u8] { &self.bytes }
+    pub fn executable(&self) -> bool { self.executable }
+
+    /// IR instructions lifted from the machine instruction the hit starts
+    /// in, when it is in code.
+    pub fn ir(&self) -> &[IrLocation] { &self.ir }
+}
+
+
+/// Scans the loaded image of a document.
+pub struct Scanner<'l> {
+    loader: &'l Loader,
+    disassembler: Option<Disassembler<'l>>
+}
+
+
+impl<'l> Scanner<'l> {
+    /// Hits are mapped back to IR when we can disassemble the architecture.
+    pub fn new(
+        loader: &'l Loader,
+        il_program: &il::Program,
+        program: &ir::Program<ir::Constant>
+    ) -> Scanner<'l> {
+        Scanner {
+            loader: loader,
+            disassembler: Disassembler::new(loader, il_program, program).ok()
+        }
+    }
+
+    /// Matches of `pattern`, stopping after `limit` of them.
+    fn scan(&self, rule: Option<&str>, string: &str, pattern: &Pattern, limit: usize)
+        -> Result<Vec<Hit>> {
+
+        let memory = self.loader.memory()?;
+        let mut hits = Vec::new();
+        for (base, section) in memory.sections() {
+            let data = section.data();
+            let executable = section.permissions().contains(MemoryPermissions::EXECUTE);
+            for offset in 0..data.len() {
+                if hits.len() >= limit {
+                    return Ok(hits);
+                }
+                let length = match pattern.match_at(data, offset) {
+                    Some(length) => length,
+                    None => continue
+                };
+                let address = base + offset as u64;
+                let ir =
+                    match self.disassembler {
+                        Some(ref disassembler) if executable =>
+                            disassembler.containing(address)?
+                                .map(|instruction| instruction.ir().to_vec())
+                                .unwrap_or_default(),
+                        _ => Vec::new()
+                    };
+                hits.push(Hit {
+                    rule: rule.map(|rule| rule.to_string()),
+                    string: string.to_string(),
+                    address: address,
+                    bytes: data[offset..offset + length].to_vec(),
+                    executable: executable,
+                    ir: ir
+                });
+            }
+        }
+        Ok(hits)
+    }
+
+    /// Matches of each pattern, at most `limit` in all.
+    pub fn patterns(&self, patterns: &[Pattern], limit: usize) -> Result<Vec<Hit>> {
+        let mut hits = Vec::new();
+        for (i, pattern) in patterns.iter().enumerate() {
+            let remaining = limit - hits.len();
+            if remaining == 0 {
+                break;
+            }
+            hits.append(&mut self.scan(None, &format!("${}", i), pattern, remaining)?);
+        }
+        Ok(hits)
+    }
+
+    /// Matches of every string of each rule whose condition holds, at most
+    /// `limit` in all.
+    pub fn rules(&self, rules: &[Rule], limit: usize) -> Result<Vec<Hit>> {
+        let mut hits = Vec::new();
+        for rule in rules {
+            let remaining = limit - hits.len();
+            if remaining == 0 {
+                break;
+            }
+            let mut rule_hits = Vec::new();
+            let mut matched = BTreeMap::new();
+            for &(ref id, ref pattern) in rule.strings() {
+                let mut string_hits = self.scan(Some(rule.name()), id, pattern, remaining)?;
+                matched.insert(id.to_string(), !string_hits.is_empty());
+                rule_hits.append(&mut string_hits);
+            }
+            if rule.holds(&matched) {
+                rule_hits.truncate(remaining);
+                hits.append(&mut rule_hits);
+            }
+        }
+        Ok(hits)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex() {
+        let pattern = Pattern::hex("6a 40 ?? 00 [2-4] (01 | 02 03) e?").unwrap();
+        let data = [0x00, 0x6a, 0x40, 0x11, 0x00, 0xaa, 0xbb, 0xcc, 0x02, 0x03, 0xe5, 0x00];
+        assert_eq!(pattern.find_all(&data), vec![(1, 10)]);
+        assert_eq!(pattern.match_at(&data, 0), None);
+    }
+
+    #[test]
+    fn hex_alternatives_backtrack() {
+        let pattern = Pattern::hex("(01 | 01 02) 03").unwrap();
+        assert_eq!(pattern.find_all(&[0x01, 0x02, 0x03, 0x01, 0x03]), vec![(0, 3), (3, 2)]);
+    }
+
+    #[test]
+    fn hex_errors() {
+        assert!(Pattern::hex("").is_err());
+        assert!(Pattern::hex("6").is_err());
+        assert!(Pattern::hex("6g").is_err());
+        assert!(Pattern::hex("[2] 01").is_err());
+        assert!(Pattern::hex("01 [4-2] 02").is_err());
+        assert!(Pattern::hex("01 [2-] 02").is_err());
+        assert!(Pattern::hex("01 [0-100000] 02").is_err());
+        assert!(Pattern::hex("01 [0-256] 02 [0-256] 03").is_ok());
+        assert!(Pattern::hex("01 [0-256] [0-256] [0-256] 02").is_err());
+        assert!(Pattern::hex("01 [1] (02 [1] | 03 [1]) 04").is_err());
+        assert!(Pattern::hex("(01 | 02").is_err());
+        assert!(Pattern::hex("01 ) 02").is_err());
+    }
+
+    #[test]
+    fn text_modifiers() {
+        let pattern = Pattern::text(b"ab", true, true, true, false).unwrap();
+        assert_eq!(pattern.find_all(b"xAb a\0B\0"), vec![(1, 2), (4, 4)]);
+
+        let pattern = Pattern::text(b"ab", true, false, false, true).unwrap();
+        assert_eq!(pattern.find_all(b"ab xab abc ab."), vec![(0, 2), (11, 2)]);
+    }
+
+    #[test]
+    fn rules() {
+        let rules = parse_rules(r#"
+            import "pe"
+
+            // A comment
+            private rule first : tag1 tag2 {
+                meta:
+                    description = "strings: and condition: in meta"
+                strings:
+                    $a = { 6a 40 [1-2] 00 }
+                    $b = "hello\x21" wide nocase
+                    /* Another comment */
+                    $c = "word" fullword
+                condition:
+                    $a and not ($b or $c) or 3 of them
+            }
+
+            rule second { condition: true }
+        "#).unwrap();
+
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0].name(), "first");
+        let ids: Vec<&str> = rules[0].strings().iter().map(|&(ref id, _)| id.as_str()).collect();
+        assert_eq!(ids, vec!["$a", "$b", "$c"]);
+
+        let matched = |a: bool, b: bool, c: bool| -> BTreeMap<String, bool> {
+            vec![("$a".to_string(), a), ("$b".to_string(), b), ("$c".to_string(), c)]
+                .into_iter()
+                .collect()
+        };
+        assert!(rules[0].holds(&matched(true, false, false)));
+        assert!(!rules[0].holds(&matched(true, true, false)));
+        assert!(rules[0].holds(&matched(true, true, true)));
+        assert!(!rules[0].holds(&matched(false, false, true)));
+        assert!(rules[1].holds(&BTreeMap::new()));
+    }
+
+    #[test]
+    fn rule_errors() {
+        assert!(parse_rules("rule a { condition: }").is_err());
+        assert!(parse_rules("rule a { strings: $a = \"x\" }").is_err());
+        assert!(parse_rules("rule a { strings: $a = { 01 condition: $a }").is_err());
+        assert!(parse_rules("rule a { condition: true /* unterminated").is_err());
+        assert!(parse_rules("rule a { strings: $a = /re/ condition: $a }").is_err());
+        assert!(parse_rules("rule a { strings: $a = \"\" condition: $a }").is_err());
+        assert!(parse_rules("rule a { strings: $a = \"x\" condition: $a or $b }").is_err());
+        assert!(parse_rules("rule a { condition: not $a }").is_err());
+    }
+}
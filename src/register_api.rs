@@ -1,7 +1,11 @@
+use analysis;
+use disassembly;
 use document;
+use emulator;
 use falcon;
 use jsonrpc_http_server::*;
 use jsonrpc_http_server::jsonrpc_core::*;
+use pattern;
 use raptor::ir;
 use std::sync::Arc;
 use store;
@@ -69,7 +73,15 @@ fn register_api_document_new(io: &mut IoHandler, store: Arc<store::Store>) {
                 })
                 .map_err(|e| internal_server_error(format!("Error parsing binary: {}", e)))?;;
 
-        let mut document = document::Document::new(loader)
+        let pipeline =
+            match params.get("pipeline") {
+                Some(pipeline) =>
+                    translate::json_to_pipeline(pipeline)
+                        .map_err(|e| internal_server_error(format!("{}", e)))?,
+                None => document::Pipeline::default()
+            };
+
+        let mut document = document::Document::new(loader, bytes, pipeline)
             .map_err(|e| internal_server_error(format!(
                 "Error loading/lifting binary: {}", e)))?;
 
@@ -118,6 +130,8 @@ fn register_api_document_functions(io: &mut IoHandler, store: Arc<store::Store>)
                     let mut map = serde_json::Map::new();
                     map.insert("index".to_string(), function.index().unwrap().into());
                     map.insert("name".to_string(), function.name().into());
+                    map.insert("discovery".to_string(),
+                               document.discovery(function.address()).as_str().into());
                     map.into()
                 })
                 .collect::<Vec<Value>>();
@@ -231,6 +245,26 @@ fn register_api_function_ir(io: &mut IoHandler, store: Arc<store::Store>) {
                 .get(&name)
                 .ok_or(internal_server_error(format!("Could not find document {}", name)))?;
 
+        let unoptimized: bool =
+            match params.get("unoptimized") {
+                Some(unoptimized) =>
+                    unoptimized.as_bool()
+                        .ok_or(internal_server_error("unoptimized was not a bool"))?,
+                None => false
+            };
+
+        if unoptimized {
+            let function =
+                document.unoptimized()
+                    .ok_or(internal_server_error(
+                        "document was not created with keep-unoptimized"))?
+                    .function(index)
+                    .ok_or(internal_server_error(format!(
+                        "Could not find function-index: {}", index)))?;
+
+            return Ok(translate::function_to_json(function));
+        }
+
         let program =
             document.program()
                 .map_err(|e| internal_server_error(e.description()))?;
@@ -361,6 +395,1907 @@ fn register_api_calls_to_symbol(io: &mut IoHandler, store: Arc<store::Store>) {
 }
 
 
+fn register_api_slice(io: &mut IoHandler, store: Arc<store::Store>) {
+    io.add_method("slice", move |params| {
+        let params =
+            match params {
+                Params::Map(values) => values,
+                _ => Err(internal_server_error("params must be a map"))?
+            };
+
+        let name: String =
+            params.get("document-name")
+                .ok_or(internal_server_error("missing document-name field"))?
+                .as_str()
+                .ok_or(internal_server_error("name was not a string"))?
+                .to_string();
+
+        let criterion =
+            translate::json_to_instruction_location(
+                params.get("program-location")
+                    .ok_or(internal_server_error("missing program-location field"))?)
+                .map_err(|e| internal_server_error(format!("{}", e)))?;
+
+        let variables: Vec<ir::Variable> =
+            match (params.get("variable"), params.get("expression")) {
+                (Some(variable), _) => vec![
+                    translate::json_to_variable(variable)
+                        .map_err(|e| internal_server_error(format!("{}", e)))?],
+                (None, Some(expression)) =>
+                    translate::json_to_variables(expression)
+                        .map_err(|e| internal_server_error(format!("{}", e)))?,
+                (None, None) =>
+                    Err(internal_server_error("missing variable or expression field"))?
+            };
+
+        let direction =
+            match params.get("direction").map(|direction| direction.as_str()) {
+                None | Some(Some("backward")) => analysis::slice::Direction::Backward,
+                Some(Some("forward")) => analysis::slice::Direction::Forward,
+                _ => Err(internal_server_error(
+                    "direction must be backward or forward"))?
+            };
+
+        let interprocedural: bool =
+            match params.get("interprocedural") {
+                Some(interprocedural) =>
+                    interprocedural.as_bool()
+                        .ok_or(internal_server_error("interprocedural was not a bool"))?,
+                None => false
+            };
+
+        let store =
+            store.documents()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let document =
+            store
+                .get(&name)
+                .ok_or(internal_server_error(format!("Could not find document {}", name)))?;
+
+        let program =
+            document.program()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let slice =
+            analysis::slice::Slicer::new(&program, interprocedural)
+                .slice(criterion, &variables, direction)
+                .map_err(|e| internal_server_error(format!("{}", e)))?;
+
+        Ok(translate::slice_to_json(&slice, &program))
+    });
+}
+
+
+fn register_api_taint(io: &mut IoHandler, store: Arc<store::Store>) {
+    io.add_method("taint", move |params| {
+        let params =
+            match params {
+                Params::Map(values) => values,
+                _ => Err(internal_server_error("params must be a map"))?
+            };
+
+        let name: String =
+            params.get("document-name")
+                .ok_or(internal_server_error("missing document-name field"))?
+                .as_str()
+                .ok_or(internal_server_error("name was not a string"))?
+                .to_string();
+
+        let sources: Vec<analysis::taint::Source> =
+            params.get("sources")
+                .ok_or(internal_server_error("missing sources field"))?
+                .as_array()
+                .ok_or(internal_server_error("sources was not an array"))?
+                .into_iter()
+                .try_fold(Vec::new(), |mut sources, source| {
+                    sources.push(
+                        translate::json_to_taint_source(source)
+                            .map_err(|e| internal_server_error(format!("{}", e)))?);
+                    Ok(sources)
+                })?;
+
+        let sinks: Vec<analysis::taint::Sink> =
+            params.get("sinks")
+                .ok_or(internal_server_error("missing sinks field"))?
+                .as_array()
+                .ok_or(internal_server_error("sinks was not an array"))?
+                .into_iter()
+                .try_fold(Vec::new(), |mut sinks, sink| {
+                    sinks.push(
+                        translate::json_to_taint_sink(sink)
+                            .map_err(|e| internal_server_error(format!("{}", e)))?);
+                    Ok(sinks)
+                })?;
+
+        let store =
+            store.documents()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let document =
+            store
+                .get(&name)
+                .ok_or(internal_server_error(format!("Could not find document {}", name)))?;
+
+        let program =
+            document.program()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let flows =
+            analysis::taint::TaintAnalysis::new(&program, sources, sinks)
+                .flows()
+                .map_err(|e| internal_server_error(format!("{}", e)))?;
+
+        Ok(flows.iter()
+            .map(|flow| translate::taint_flow_to_json(flow))
+            .collect::<Vec<Value>>()
+            .into())
+    });
+}
+
+
+fn register_api_document_audit(io: &mut IoHandler, store: Arc<store::Store>) {
+    io.add_method("document-audit", move |params| {
+        let params =
+            match params {
+                Params::Map(values) => values,
+                _ => Err(internal_server_error("params must be a map"))?
+            };
+
+        let name: String =
+            params.get("document-name")
+                .ok_or(internal_server_error("missing document-name field"))?
+                .as_str()
+                .ok_or(internal_server_error("name was not a string"))?
+                .to_string();
+
+        let builtin: bool =
+            match params.get("builtin") {
+                Some(builtin) =>
+                    builtin.as_bool()
+                        .ok_or(internal_server_error("builtin was not a bool"))?,
+                None => true
+            };
+
+        let mut rules =
+            if builtin { analysis::audit::builtin_rules() } else { Vec::new() };
+
+        if let Some(extra) = params.get("rules") {
+            for rule in extra.as_array()
+                             .ok_or(internal_server_error("rules was not an array"))? {
+                rules.push(
+                    translate::json_to_audit_rule(rule)
+                        .map_err(|e| internal_server_error(format!("{}", e)))?);
+            }
+        }
+
+        let store =
+            store.documents()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let document =
+            store
+                .get(&name)
+                .ok_or(internal_server_error(format!("Could not find document {}", name)))?;
+
+        let program =
+            document.program()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let findings =
+            analysis::audit::audit(&program, &rules)
+                .map_err(|e| internal_server_error(format!("{}", e)))?;
+
+        Ok(findings.iter()
+            .map(|finding| translate::audit_finding_to_json(finding))
+            .collect::<Vec<Value>>()
+            .into())
+    });
+}
+
+
+fn register_api_format_strings(io: &mut IoHandler, store: Arc<store::Store>) {
+    io.add_method("format-strings", move |params| {
+        let params =
+            match params {
+                Params::Map(values) => values,
+                _ => Err(internal_server_error("params must be a map"))?
+            };
+
+        let name: String =
+            params.get("document-name")
+                .ok_or(internal_server_error("missing document-name field"))?
+                .as_str()
+                .ok_or(internal_server_error("name was not a string"))?
+                .to_string();
+
+        let store =
+            store.documents()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let document =
+            store
+                .get(&name)
+                .ok_or(internal_server_error(format!("Could not find document {}", name)))?;
+
+        let program =
+            document.program()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let format_strings =
+            analysis::format_string::format_strings(&program, document.loader())
+                .map_err(|e| internal_server_error(format!("{}", e)))?;
+
+        Ok(format_strings.iter()
+            .map(|format_string| translate::format_string_to_json(format_string))
+            .collect::<Vec<Value>>()
+            .into())
+    });
+}
+
+
+fn register_api_function_dominators(io: &mut IoHandler, store: Arc<store::Store>) {
+    io.add_method("function-dominators", move |params| {
+        let params =
+            match params {
+                Params::Map(values) => values,
+                _ => Err(internal_server_error("params must be a map"))?
+            };
+
+        let name: String =
+            params.get("document-name")
+                .ok_or(internal_server_error("missing document-name field"))?
+                .as_str()
+                .ok_or(internal_server_error("name was not a string"))?
+                .to_string();
+
+        let index: usize =
+            params.get("function-index")
+                .ok_or(internal_server_error("missing function-index field"))?
+                .as_u64()
+                .ok_or(internal_server_error("index was not a valid number"))?
+                as usize;
+
+        let store =
+            store.documents()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let document =
+            store
+                .get(&name)
+                .ok_or(internal_server_error(format!("Could not find document {}", name)))?;
+
+        let program =
+            document.program()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let function =
+            program
+                .function(index)
+                .ok_or(internal_server_error(format!(
+                    "Could not find function-index: {}", index)))?;
+
+        let cfg = analysis::cfg::Cfg::new(function);
+        let dominators = analysis::dominators::Dominators::new(&cfg);
+
+        Ok(translate::dominators_to_json(function, &dominators))
+    });
+}
+
+
+fn register_api_function_loops(io: &mut IoHandler, store: Arc<store::Store>) {
+    io.add_method("function-loops", move |params| {
+        let params =
+            match params {
+                Params::Map(values) => values,
+                _ => Err(internal_server_error("params must be a map"))?
+            };
+
+        let name: String =
+            params.get("document-name")
+                .ok_or(internal_server_error("missing document-name field"))?
+                .as_str()
+                .ok_or(internal_server_error("name was not a string"))?
+                .to_string();
+
+        let index: usize =
+            params.get("function-index")
+                .ok_or(internal_server_error("missing function-index field"))?
+                .as_u64()
+                .ok_or(internal_server_error("index was not a valid number"))?
+                as usize;
+
+        let store =
+            store.documents()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let document =
+            store
+                .get(&name)
+                .ok_or(internal_server_error(format!("Could not find document {}", name)))?;
+
+        let program =
+            document.program()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let function =
+            program
+                .function(index)
+                .ok_or(internal_server_error(format!(
+                    "Could not find function-index: {}", index)))?;
+
+        let cfg = analysis::cfg::Cfg::new(function);
+        let dominators = analysis::dominators::Dominators::new(&cfg);
+
+        Ok(analysis::loops::natural_loops(&cfg, &dominators)
+            .iter()
+            .map(|l| translate::loop_to_json(l))
+            .collect::<Vec<Value>>()
+            .into())
+    });
+}
+
+
+fn register_api_function_ssa(io: &mut IoHandler, store: Arc<store::Store>) {
+    io.add_method("function-ssa", move |params| {
+        let params =
+            match params {
+                Params::Map(values) => values,
+                _ => Err(internal_server_error("params must be a map"))?
+            };
+
+        let name: String =
+            params.get("document-name")
+                .ok_or(internal_server_error("missing document-name field"))?
+                .as_str()
+                .ok_or(internal_server_error("name was not a string"))?
+                .to_string();
+
+        let index: usize =
+            params.get("function-index")
+                .ok_or(internal_server_error("missing function-index field"))?
+                .as_u64()
+                .ok_or(internal_server_error("index was not a valid number"))?
+                as usize;
+
+        let store =
+            store.documents()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let document =
+            store
+                .get(&name)
+                .ok_or(internal_server_error(format!("Could not find document {}", name)))?;
+
+        let program =
+            document.program()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let function =
+            program
+                .function(index)
+                .ok_or(internal_server_error(format!(
+                    "Could not find function-index: {}", index)))?;
+
+        let ssa =
+            analysis::ssa::Ssa::new(function)
+                .ok_or(internal_server_error(format!(
+                    "Function {} has no index", function.name())))?;
+
+        Ok(translate::ssa_function_to_json(function, &ssa))
+    });
+}
+
+
+fn register_api_function_liveness(io: &mut IoHandler, store: Arc<store::Store>) {
+    io.add_method("function-liveness", move |params| {
+        let params =
+            match params {
+                Params::Map(values) => values,
+                _ => Err(internal_server_error("params must be a map"))?
+            };
+
+        let name: String =
+            params.get("document-name")
+                .ok_or(internal_server_error("missing document-name field"))?
+                .as_str()
+                .ok_or(internal_server_error("name was not a string"))?
+                .to_string();
+
+        let index: usize =
+            params.get("function-index")
+                .ok_or(internal_server_error("missing function-index field"))?
+                .as_u64()
+                .ok_or(internal_server_error("index was not a valid number"))?
+                as usize;
+
+        let store =
+            store.documents()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let document =
+            store
+                .get(&name)
+                .ok_or(internal_server_error(format!("Could not find document {}", name)))?;
+
+        let program =
+            document.program()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let function =
+            program
+                .function(index)
+                .ok_or(internal_server_error(format!(
+                    "Could not find function-index: {}", index)))?;
+
+        let return_register = analysis::liveness::return_register(document.loader());
+        let liveness =
+            analysis::liveness::Liveness::new(function, &return_register)
+                .ok_or(internal_server_error(format!(
+                    "Function {} has no index", function.name())))?;
+        let unused_results =
+            analysis::liveness::unused_results(function, &liveness);
+
+        Ok(translate::liveness_to_json(function, &liveness, &unused_results))
+    });
+}
+
+
+fn register_api_document_unused_results(io: &mut IoHandler, store: Arc<store::Store>) {
+    io.add_method("document-unused-results", move |params| {
+        let params =
+            match params {
+                Params::Map(values) => values,
+                _ => Err(internal_server_error("params must be a map"))?
+            };
+
+        let name: String =
+            params.get("document-name")
+                .ok_or(internal_server_error("missing document-name field"))?
+                .as_str()
+                .ok_or(internal_server_error("name was not a string"))?
+                .to_string();
+
+        let symbols: Option<Vec<String>> =
+            match params.get("symbols") {
+                Some(symbols) => Some(
+                    symbols.as_array()
+                        .ok_or(internal_server_error("symbols was not an array"))?
+                        .into_iter()
+                        .try_fold(Vec::new(), |mut symbols, symbol| {
+                            symbols.push(
+                                symbol.as_str()
+                                    .ok_or(internal_server_error("symbol was not a string"))?
+                                    .to_string());
+                            Ok(symbols)
+                        })?),
+                None => None
+            };
+
+        let store =
+            store.documents()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let document =
+            store
+                .get(&name)
+                .ok_or(internal_server_error(format!("Could not find document {}", name)))?;
+
+        let program =
+            document.program()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        // Only compute liveness for functions which call something we're
+        // looking for.
+        let calls_symbol = |function: &ir::Function<ir::Constant>| match symbols {
+            None => true,
+            Some(ref symbols) =>
+                function.blocks()
+                    .into_iter()
+                    .flat_map(|block| block.instructions())
+                    .filter_map(|instruction| instruction.operation().call())
+                    .filter_map(|call| call.target().symbol())
+                    .any(|symbol| symbols.iter().any(|s| s == symbol))
+        };
+
+        let return_register = analysis::liveness::return_register(document.loader());
+
+        Ok(program.functions()
+            .into_iter()
+            .filter(|function| calls_symbol(function))
+            .flat_map(|function|
+                analysis::liveness::Liveness::new(function, &return_register)
+                    .map(|liveness| analysis::liveness::unused_results(function, &liveness))
+                    .unwrap_or_default())
+            .filter(|unused|
+                symbols.as_ref()
+                    .map(|symbols| symbols.iter().any(|s| s == unused.target()))
+                    .unwrap_or(true))
+            .map(|unused| translate::unused_result_to_json(&unused))
+            .collect::<Vec<Value>>()
+            .into())
+    });
+}
+
+fn register_api_function_emulate(io: &mut IoHandler, store: Arc<store::Store>) {
+    io.add_method("function-emulate", move |params| {
+        let params =
+            match params {
+                Params::Map(values) => values,
+                _ => Err(internal_server_error("params must be a map"))?
+            };
+
+        let name: String =
+            params.get("document-name")
+                .ok_or(internal_server_error("missing document-name field"))?
+                .as_str()
+                .ok_or(internal_server_error("name was not a string"))?
+                .to_string();
+
+        let index: usize =
+            params.get("function-index")
+                .ok_or(internal_server_error("missing function-index field"))?
+                .as_u64()
+                .ok_or(internal_server_error("index was not a valid number"))?
+                as usize;
+
+        let max_steps: usize =
+            match params.get("max-steps") {
+                Some(max_steps) =>
+                    max_steps.as_u64()
+                        .ok_or(internal_server_error("max-steps was not a valid number"))?
+                        as usize,
+                None => 10000
+            };
+
+        let store =
+            store.documents()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let document =
+            store
+                .get(&name)
+                .ok_or(internal_server_error(format!("Could not find document {}", name)))?;
+
+        let word_size = document.loader().architecture().word_size();
+
+        let mut setup = emulator::Setup::new(max_steps);
+
+        if let Some(registers) = params.get("registers") {
+            let registers =
+                registers.as_array()
+                    .ok_or(internal_server_error("registers was not an array"))?;
+            for register in registers {
+                let register_name =
+                    register.get("name")
+                        .and_then(|name| name.as_str())
+                        .ok_or(internal_server_error("register missing name"))?;
+                let value =
+                    register.get("value")
+                        .and_then(|value| value.as_u64())
+                        .ok_or(internal_server_error("register missing value"))?;
+                let bits =
+                    match register.get("bits") {
+                        Some(bits) =>
+                            bits.as_u64()
+                                .ok_or(internal_server_error("register bits was not a valid number"))?
+                                as usize,
+                        None => word_size
+                    };
+                setup.set_register(register_name, falcon::il::Constant::new(value, bits));
+            }
+        }
+
+        if let Some(memory) = params.get("memory") {
+            let memory =
+                memory.as_array()
+                    .ok_or(internal_server_error("memory was not an array"))?;
+            for region in memory {
+                let address =
+                    region.get("address")
+                        .and_then(|address| address.as_u64())
+                        .ok_or(internal_server_error("memory missing address"))?;
+                let bytes: Vec<u8> =
+                    region.get("bytes")
+                        .ok_or(internal_server_error("memory missing bytes"))?
+                        .as_array()
+                        .ok_or(internal_server_error("bytes was not an array"))?
+                        .into_iter()
+                        .try_fold(Vec::new(), |mut bytes, byte_number| {
+                            bytes.push(
+                                byte_number.as_u64()
+                                    .ok_or(internal_server_error("byte was not valid number"))?
+                                as u8);
+                            Ok(bytes)
+                        })?;
+                setup.set_memory(address, bytes);
+            }
+        }
+
+        let address = {
+            let program =
+                document.program()
+                    .map_err(|e| internal_server_error(e.description()))?;
+
+            let function =
+                program
+                    .function(index)
+                    .ok_or(internal_server_error(format!(
+                        "Could not find function-index: {}", index)))?;
+
+            function.address()
+        };
+
+        let emulation =
+            emulator::emulate(document.loader(), document.il_program(), address, &setup)
+                .map_err(|e| internal_server_error(format!("Error emulating: {}", e)))?;
+
+        Ok(translate::emulation_to_json(&emulation))
+    });
+}
+
+
+fn register_api_function_explore(io: &mut IoHandler, store: Arc<store::Store>) {
+    io.add_method("function-explore", move |params| {
+        let params =
+            match params {
+                Params::Map(values) => values,
+                _ => Err(internal_server_error("params must be a map"))?
+            };
+
+        let name: String =
+            params.get("document-name")
+                .ok_or(internal_server_error("missing document-name field"))?
+                .as_str()
+                .ok_or(internal_server_error("name was not a string"))?
+                .to_string();
+
+        let index: usize =
+            params.get("function-index")
+                .ok_or(internal_server_error("missing function-index field"))?
+                .as_u64()
+                .ok_or(internal_server_error("index was not a valid number"))?
+                as usize;
+
+        let target: u64 =
+            params.get("target")
+                .ok_or(internal_server_error("missing target field"))?
+                .as_u64()
+                .ok_or(internal_server_error("target was not a valid number"))?;
+
+        let mut limits = analysis::symbolic::Limits::default();
+
+        if let Some(max_paths) = params.get("max-paths") {
+            limits.max_paths =
+                max_paths.as_u64()
+                    .ok_or(internal_server_error("max-paths was not a valid number"))?
+                    as usize;
+        }
+
+        if let Some(max_visits) = params.get("max-visits") {
+            limits.max_visits =
+                max_visits.as_u64()
+                    .ok_or(internal_server_error("max-visits was not a valid number"))?
+                    as usize;
+        }
+
+        if let Some(timeout) = params.get("timeout") {
+            limits.timeout =
+                ::std::time::Duration::from_secs(
+                    timeout.as_u64()
+                        .ok_or(internal_server_error("timeout was not a valid number"))?);
+        }
+
+        let store =
+            store.documents()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let document =
+            store
+                .get(&name)
+                .ok_or(internal_server_error(format!("Could not find document {}", name)))?;
+
+        let program =
+            document.program()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let function =
+            program
+                .function(index)
+                .ok_or(internal_server_error(format!(
+                    "Could not find function-index: {}", index)))?;
+
+        let exploration =
+            analysis::symbolic::explore(document.loader(), function, target, &limits)
+                .map_err(|e| internal_server_error(format!("Error exploring: {}", e)))?;
+
+        Ok(translate::exploration_to_json(&exploration))
+    });
+}
+
+
+fn register_api_path_smtlib(io: &mut IoHandler, store: Arc<store::Store>) {
+    io.add_method("path-smtlib", move |params| {
+        let params =
+            match params {
+                Params::Map(values) => values,
+                _ => Err(internal_server_error("params must be a map"))?
+            };
+
+        let name: String =
+            params.get("document-name")
+                .ok_or(internal_server_error("missing document-name field"))?
+                .as_str()
+                .ok_or(internal_server_error("name was not a string"))?
+                .to_string();
+
+        let start =
+            translate::json_to_instruction_location(
+                params.get("start")
+                    .ok_or(internal_server_error("missing start field"))?)
+                .map_err(|e| internal_server_error(format!("{}", e)))?;
+
+        let end =
+            translate::json_to_instruction_location(
+                params.get("end")
+                    .ok_or(internal_server_error("missing end field"))?)
+                .map_err(|e| internal_server_error(format!("{}", e)))?;
+
+        if start.function_index() != end.function_index() {
+            Err(internal_server_error("start and end must be in the same function"))?;
+        }
+
+        let path: Option<Vec<usize>> =
+            match params.get("path") {
+                Some(path) => Some(
+                    path.as_array()
+                        .ok_or(internal_server_error("path was not an array"))?
+                        .into_iter()
+                        .try_fold(Vec::new(), |mut path, index| {
+                            path.push(
+                                index.as_u64()
+                                    .ok_or(internal_server_error("block index was not a valid number"))?
+                                as usize);
+                            Ok(path)
+                        })?),
+                None => None
+            };
+
+        let store =
+            store.documents()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let document =
+            store
+                .get(&name)
+                .ok_or(internal_server_error(format!("Could not find document {}", name)))?;
+
+        let program =
+            document.program()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let function =
+            program
+                .function(start.function_index())
+                .ok_or(internal_server_error(format!(
+                    "Could not find function-index: {}", start.function_index())))?;
+
+        let (path, encoder) =
+            analysis::symbolic::path_constraints(
+                document.loader(), function, &start, &end, path)
+                .map_err(|e| internal_server_error(format!("{}", e)))?;
+
+        Ok(translate::path_constraints_to_json(&path, &encoder))
+    });
+}
+
+
+fn register_api_function_paths(io: &mut IoHandler, store: Arc<store::Store>) {
+    io.add_method("function-paths", move |params| {
+        let params =
+            match params {
+                Params::Map(values) => values,
+                _ => Err(internal_server_error("params must be a map"))?
+            };
+
+        let name: String =
+            params.get("document-name")
+                .ok_or(internal_server_error("missing document-name field"))?
+                .as_str()
+                .ok_or(internal_server_error("name was not a string"))?
+                .to_string();
+
+        let source =
+            translate::json_to_instruction_location(
+                params.get("source")
+                    .ok_or(internal_server_error("missing source field"))?)
+                .map_err(|e| internal_server_error(format!("{}", e)))?;
+
+        let target =
+            translate::json_to_instruction_location(
+                params.get("target")
+                    .ok_or(internal_server_error("missing target field"))?)
+                .map_err(|e| internal_server_error(format!("{}", e)))?;
+
+        if source.function_index() != target.function_index() {
+            Err(internal_server_error("source and target must be in the same function"))?;
+        }
+
+        let limit: usize =
+            match params.get("limit") {
+                Some(limit) =>
+                    limit.as_u64()
+                        .ok_or(internal_server_error("limit was not a valid number"))?
+                        as usize,
+                None => 100
+            };
+
+        let max_visits: usize =
+            match params.get("max-visits") {
+                Some(max_visits) =>
+                    max_visits.as_u64()
+                        .ok_or(internal_server_error("max-visits was not a valid number"))?
+                        as usize,
+                None => 1
+            };
+
+        let shortest: bool =
+            match params.get("shortest") {
+                Some(shortest) =>
+                    shortest.as_bool()
+                        .ok_or(internal_server_error("shortest was not a bool"))?,
+                None => false
+            };
+
+        let store =
+            store.documents()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let document =
+            store
+                .get(&name)
+                .ok_or(internal_server_error(format!("Could not find document {}", name)))?;
+
+        let program =
+            document.program()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let function =
+            program
+                .function(source.function_index())
+                .ok_or(internal_server_error(format!(
+                    "Could not find function-index: {}", source.function_index())))?;
+
+        let cfg = analysis::cfg::Cfg::new(function);
+        if !cfg.blocks().contains(&source.block_index()) ||
+           !cfg.blocks().contains(&target.block_index()) {
+            Err(internal_server_error("source or target block not in function"))?;
+        }
+        let successors = |index: usize| cfg.successors(index).clone();
+
+        // When the target comes before the source in the same block, paths
+        // have to leave the block and loop back to it, as in
+        // `symbolic::path_constraints`.
+        let leave_first =
+            source.block_index() == target.block_index() &&
+            source.instruction_index() > target.instruction_index();
+        let starts: Vec<usize> =
+            if leave_first {
+                cfg.successors(source.block_index()).iter().cloned().collect()
+            }
+            else {
+                vec![source.block_index()]
+            };
+        let from_source = |path: Vec<usize>|
+            if leave_first {
+                let mut full = vec![source.block_index()];
+                full.extend(path);
+                full
+            }
+            else {
+                path
+            };
+
+        let (paths, truncated) =
+            if shortest {
+                let path =
+                    starts.iter()
+                        .filter_map(|start|
+                            analysis::paths::shortest(
+                                &successors, *start, target.block_index()))
+                        .min_by_key(|path| path.len())
+                        .map(&from_source);
+                (path.into_iter().collect::<Vec<Vec<usize>>>(), false)
+            }
+            else {
+                let mut paths = Vec::new();
+                let mut truncated = false;
+                for start in starts {
+                    if paths.len() >= limit {
+                        truncated = true;
+                        break;
+                    }
+                    let found = analysis::paths::enumerate(
+                        &successors,
+                        start,
+                        target.block_index(),
+                        max_visits,
+                        limit - paths.len());
+                    truncated |= found.truncated();
+                    paths.extend(found.into_paths().into_iter().map(&from_source));
+                }
+                (paths, truncated)
+            };
+
+        Ok(translate::block_paths_to_json(&paths, truncated))
+    });
+}
+
+
+fn register_api_callgraph_paths(io: &mut IoHandler, store: Arc<store::Store>) {
+    io.add_method("callgraph-paths", move |params| {
+        let params =
+            match params {
+                Params::Map(values) => values,
+                _ => Err(internal_server_error("params must be a map"))?
+            };
+
+        let name: String =
+            params.get("document-name")
+                .ok_or(internal_server_error("missing document-name field"))?
+                .as_str()
+                .ok_or(internal_server_error("name was not a string"))?
+                .to_string();
+
+        let source =
+            translate::json_to_function_index(
+                params.get("source")
+                    .ok_or(internal_server_error("missing source field"))?)
+                .map_err(|e| internal_server_error(format!("{}", e)))?;
+
+        let target =
+            translate::json_to_function_index(
+                params.get("target")
+                    .ok_or(internal_server_error("missing target field"))?)
+                .map_err(|e| internal_server_error(format!("{}", e)))?;
+
+        let limit: usize =
+            match params.get("limit") {
+                Some(limit) =>
+                    limit.as_u64()
+                        .ok_or(internal_server_error("limit was not a valid number"))?
+                        as usize,
+                None => 100
+            };
+
+        let max_visits: usize =
+            match params.get("max-visits") {
+                Some(max_visits) =>
+                    max_visits.as_u64()
+                        .ok_or(internal_server_error("max-visits was not a valid number"))?
+                        as usize,
+                None => 1
+            };
+
+        let shortest: bool =
+            match params.get("shortest") {
+                Some(shortest) =>
+                    shortest.as_bool()
+                        .ok_or(internal_server_error("shortest was not a bool"))?,
+                None => false
+            };
+
+        let store =
+            store.documents()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let document =
+            store
+                .get(&name)
+                .ok_or(internal_server_error(format!("Could not find document {}", name)))?;
+
+        let program =
+            document.program()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        for index in &[source, target] {
+            program
+                .function(*index)
+                .ok_or(internal_server_error(format!(
+                    "Could not find function-index: {}", index)))?;
+        }
+
+        let call_graph = analysis::callgraph::CallGraph::new(&program);
+        let successors = |index: usize| call_graph.callees(index);
+
+        let (paths, truncated) =
+            if shortest {
+                let path = analysis::paths::shortest(successors, source, target);
+                (path.into_iter().collect::<Vec<Vec<usize>>>(), false)
+            }
+            else {
+                let paths = analysis::paths::enumerate(
+                    successors, source, target, max_visits, limit);
+                let truncated = paths.truncated();
+                (paths.into_paths(), truncated)
+            };
+
+        Ok(translate::callgraph_paths_to_json(&paths, truncated, &call_graph))
+    });
+}
+
+
+fn register_api_document_reachability(io: &mut IoHandler, store: Arc<store::Store>) {
+    io.add_method("document-reachability", move |params| {
+        let params =
+            match params {
+                Params::Map(values) => values,
+                _ => Err(internal_server_error("params must be a map"))?
+            };
+
+        let name: String =
+            params.get("document-name")
+                .ok_or(internal_server_error("missing document-name field"))?
+                .as_str()
+                .ok_or(internal_server_error("name was not a string"))?
+                .to_string();
+
+        let mut roots =
+            match params.get("roots") {
+                Some(roots) => {
+                    let mut r = analysis::reachability::Roots::default();
+                    for root in
+                        roots.as_array()
+                            .ok_or(internal_server_error("roots was not an array"))? {
+                        match root.as_str() {
+                            Some("entry") => r.entry = true,
+                            Some("exports") => r.exports = true,
+                            Some("init-array") => r.init_array = true,
+                            _ => Err(internal_server_error(
+                                "roots must be entry, exports or init-array"))?
+                        }
+                    }
+                    r
+                },
+                None => analysis::reachability::Roots::all()
+            };
+
+        if let Some(functions) = params.get("functions") {
+            roots.functions =
+                functions.as_array()
+                    .ok_or(internal_server_error("functions was not an array"))?
+                    .into_iter()
+                    .try_fold(Vec::new(), |mut functions, index| {
+                        functions.push(
+                            index.as_u64()
+                                .ok_or(internal_server_error("function index was not a valid number"))?
+                            as usize);
+                        Ok(functions)
+                    })?;
+        }
+
+        let input_sources: Vec<String> =
+            match params.get("input-sources") {
+                Some(input_sources) =>
+                    input_sources.as_array()
+                        .ok_or(internal_server_error("input-sources was not an array"))?
+                        .into_iter()
+                        .try_fold(Vec::new(), |mut symbols, symbol| {
+                            symbols.push(
+                                symbol.as_str()
+                                    .ok_or(internal_server_error("input source was not a string"))?
+                                    .to_string());
+                            Ok(symbols)
+                        })?,
+                None =>
+                    analysis::reachability::INPUT_SOURCES
+                        .into_iter()
+                        .map(|symbol| symbol.to_string())
+                        .collect()
+            };
+
+        let store =
+            store.documents()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let document =
+            store
+                .get(&name)
+                .ok_or(internal_server_error(format!("Could not find document {}", name)))?;
+
+        let program =
+            document.program()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let reachability =
+            analysis::reachability::reachability(
+                &program,
+                document.loader(),
+                document.bytes(),
+                &roots,
+                &input_sources)
+                .map_err(|e| internal_server_error(format!("{}", e)))?;
+
+        Ok(translate::reachability_to_json(&reachability))
+    });
+}
+
+
+fn register_api_function_create(io: &mut IoHandler, store: Arc<store::Store>) {
+    io.add_method("function-create", move |params| {
+        let params =
+            match params {
+                Params::Map(values) => values,
+                _ => Err(internal_server_error("params must be a map"))?
+            };
+
+        let name: String =
+            params.get("document-name")
+                .ok_or(internal_server_error("missing document-name field"))?
+                .as_str()
+                .ok_or(internal_server_error("name was not a string"))?
+                .to_string();
+
+        let address: u64 =
+            params.get("address")
+                .ok_or(internal_server_error("missing address field"))?
+                .as_u64()
+                .ok_or(internal_server_error("address was not a valid number"))?;
+
+        // Lift under the read lock, and only take the write lock to add
+        // the result.
+        let lifted = {
+            let documents =
+                store.documents()
+                    .map_err(|e| internal_server_error(e.description()))?;
+
+            let document =
+                documents
+                    .get(&name)
+                    .ok_or(internal_server_error(format!("Could not find document {}", name)))?;
+
+            document.lift_function(address)
+                .map_err(|e| internal_server_error(format!(
+                    "Error creating function: {}", e)))?
+        };
+
+        let mut documents =
+            store.documents_mut()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let document =
+            documents
+                .get_mut(&name)
+                .ok_or(internal_server_error(format!("Could not find document {}", name)))?;
+
+        let index =
+            document.add_function(lifted)
+                .map_err(|e| internal_server_error(format!(
+                    "Error creating function: {}", e)))?;
+
+        Ok(index.into())
+    });
+}
+
+
+fn register_api_function_retranslate(io: &mut IoHandler, store: Arc<store::Store>) {
+    io.add_method("function-retranslate", move |params| {
+        let params =
+            match params {
+                Params::Map(values) => values,
+                _ => Err(internal_server_error("params must be a map"))?
+            };
+
+        let name: String =
+            params.get("document-name")
+                .ok_or(internal_server_error("missing document-name field"))?
+                .as_str()
+                .ok_or(internal_server_error("name was not a string"))?
+                .to_string();
+
+        let index: usize =
+            params.get("function-index")
+                .ok_or(internal_server_error("missing function-index field"))?
+                .as_u64()
+                .ok_or(internal_server_error("index was not a valid number"))?
+                as usize;
+
+        // Lift under the read lock, and only take the write lock to put
+        // the result in place.
+        let lifted = {
+            let documents =
+                store.documents()
+                    .map_err(|e| internal_server_error(e.description()))?;
+
+            let document =
+                documents
+                    .get(&name)
+                    .ok_or(internal_server_error(format!("Could not find document {}", name)))?;
+
+            document.relift_function(index)
+                .map_err(|e| internal_server_error(format!(
+                    "Error retranslating function: {}", e)))?
+        };
+
+        let mut documents =
+            store.documents_mut()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let document =
+            documents
+                .get_mut(&name)
+                .ok_or(internal_server_error(format!("Could not find document {}", name)))?;
+
+        document.replace_function(lifted)
+            .map_err(|e| internal_server_error(format!(
+                "Error retranslating function: {}", e)))?;
+
+        Ok(String::from("function retranslated").into())
+    });
+}
+
+
+fn register_api_function_il(io: &mut IoHandler, store: Arc<store::Store>) {
+    io.add_method("function-il", move |params| {
+        let params =
+            match params {
+                Params::Map(values) => values,
+                _ => Err(internal_server_error("params must be a map"))?
+            };
+
+        let name: String =
+            params.get("document-name")
+                .ok_or(internal_server_error("missing document-name field"))?
+                .as_str()
+                .ok_or(internal_server_error("name was not a string"))?
+                .to_string();
+
+        let index: usize =
+            params.get("function-index")
+                .ok_or(internal_server_error("missing function-index field"))?
+                .as_u64()
+                .ok_or(internal_server_error("index was not a valid number"))?
+                as usize;
+
+        let store =
+            store.documents()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let document =
+            store
+                .get(&name)
+                .ok_or(internal_server_error(format!("Could not find document {}", name)))?;
+
+        let program =
+            document.program()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let function =
+            program
+                .function(index)
+                .ok_or(internal_server_error(format!(
+                    "Could not find function-index: {}", index)))?;
+
+        let il_program = document.il_program();
+
+        let il_function =
+            il_program
+                .functions()
+                .into_iter()
+                .find(|il_function| il_function.address() == function.address())
+                .ok_or(internal_server_error(format!(
+                    "Could not find IL for function-index: {}", index)))?;
+
+        Ok(translate::il_function_to_json(il_function, function))
+    });
+}
+
+
+fn register_api_disassemble(io: &mut IoHandler, store: Arc<store::Store>) {
+    io.add_method("disassemble", move |params| {
+        let params =
+            match params {
+                Params::Map(values) => values,
+                _ => Err(internal_server_error("params must be a map"))?
+            };
+
+        let name: String =
+            params.get("document-name")
+                .ok_or(internal_server_error("missing document-name field"))?
+                .as_str()
+                .ok_or(internal_server_error("name was not a string"))?
+                .to_string();
+
+        let start: u64 =
+            params.get("start")
+                .ok_or(internal_server_error("missing start field"))?
+                .as_u64()
+                .ok_or(internal_server_error("start was not a valid number"))?;
+
+        let end: u64 =
+            params.get("end")
+                .ok_or(internal_server_error("missing end field"))?
+                .as_u64()
+                .ok_or(internal_server_error("end was not a valid number"))?;
+
+        let store =
+            store.documents()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let document =
+            store
+                .get(&name)
+                .ok_or(internal_server_error(format!("Could not find document {}", name)))?;
+
+        let program =
+            document.program()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let disassembler =
+            disassembly::Disassembler::new(document.loader(), &document.il_program(), &program)
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let instructions =
+            disassembler.range(start, end)
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        Ok(translate::disassembly_to_json(&instructions))
+    });
+}
+
+
+fn register_api_function_disassembly(io: &mut IoHandler, store: Arc<store::Store>) {
+    io.add_method("function-disassembly", move |params| {
+        let params =
+            match params {
+                Params::Map(values) => values,
+                _ => Err(internal_server_error("params must be a map"))?
+            };
+
+        let name: String =
+            params.get("document-name")
+                .ok_or(internal_server_error("missing document-name field"))?
+                .as_str()
+                .ok_or(internal_server_error("name was not a string"))?
+                .to_string();
+
+        let index: usize =
+            params.get("function-index")
+                .ok_or(internal_server_error("missing function-index field"))?
+                .as_u64()
+                .ok_or(internal_server_error("index was not a valid number"))?
+                as usize;
+
+        let store =
+            store.documents()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let document =
+            store
+                .get(&name)
+                .ok_or(internal_server_error(format!("Could not find document {}", name)))?;
+
+        let program =
+            document.program()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let il_program = document.il_program();
+
+        let il_function =
+            il_program
+                .function(index)
+                .ok_or(internal_server_error(format!(
+                    "Could not find function-index: {}", index)))?;
+
+        let function =
+            program
+                .function(index)
+                .ok_or(internal_server_error(format!(
+                    "Could not find function-index: {}", index)))?;
+
+        let disassembler =
+            disassembly::Disassembler::for_function(document.loader(), il_function, function)
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let instructions =
+            disassembler.function(il_function)
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        Ok(translate::disassembly_to_json(&instructions))
+    });
+}
+
+
+fn register_api_instructions_in_range(io: &mut IoHandler, store: Arc<store::Store>) {
+    io.add_method("instructions-in-range", move |params| {
+        let params =
+            match params {
+                Params::Map(values) => values,
+                _ => Err(internal_server_error("params must be a map"))?
+            };
+
+        let name: String =
+            params.get("document-name")
+                .ok_or(internal_server_error("missing document-name field"))?
+                .as_str()
+                .ok_or(internal_server_error("name was not a string"))?
+                .to_string();
+
+        let start: u64 =
+            params.get("start")
+                .ok_or(internal_server_error("missing start field"))?
+                .as_u64()
+                .ok_or(internal_server_error("start was not a valid number"))?;
+
+        let end: u64 =
+            params.get("end")
+                .ok_or(internal_server_error("missing end field"))?
+                .as_u64()
+                .ok_or(internal_server_error("end was not a valid number"))?;
+
+        let store =
+            store.documents()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let document =
+            store
+                .get(&name)
+                .ok_or(internal_server_error(format!("Could not find document {}", name)))?;
+
+        let program =
+            document.program()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let il_program = document.il_program();
+
+        // The machine instruction covering `address`, decoded from the start
+        // of its block in the one function holding it. Architectures we
+        // can't decode just go without.
+        let covering = |address: u64| -> ::error::Result<Option<disassembly::Instruction>> {
+            let il_function = match disassembly::il_function_at(&il_program, address) {
+                Some(il_function) => il_function,
+                None => return Ok(None)
+            };
+            let function =
+                match il_function.index().and_then(|index| program.function(index)) {
+                    Some(function) => function,
+                    None => return Ok(None)
+                };
+            match disassembly::Disassembler::for_function(
+                document.loader(), il_function, function) {
+                Ok(disassembler) => disassembler.containing(address),
+                Err(_) => Ok(None)
+            }
+        };
+
+        // Instructions are only taken from the requested bounds. Bounds
+        // falling inside a machine instruction report the blocks holding it.
+        let first =
+            covering(start)
+                .map_err(|e| internal_server_error(e.description()))?
+                .filter(|instruction| instruction.address() < start);
+        let last =
+            if end > start {
+                covering(end - 1)
+                    .map_err(|e| internal_server_error(e.description()))?
+                    .filter(|instruction|
+                        instruction.address() + instruction.length() as u64 > end)
+            }
+            else {
+                None
+            };
+        let containing: Vec<(usize, usize, usize)> =
+            first.iter()
+                .chain(last.iter())
+                .flat_map(|instruction| instruction.ir().to_vec())
+                .collect();
+
+        let mut instructions = Vec::new();
+        for function in program.functions() {
+            let function_index = match function.index() {
+                Some(index) => index,
+                None => continue
+            };
+            for block in function.blocks() {
+                for instruction in block.instructions() {
+                    let address = match instruction.address() {
+                        Some(address) => address,
+                        None => continue
+                    };
+                    if address >= start && address < end {
+                        instructions.push(
+                            (function_index, block.index(), instruction.index()));
+                    }
+                }
+            }
+        }
+
+        Ok(translate::instructions_in_range_to_json(
+            &program, &instructions, &containing))
+    });
+}
+
+
+fn register_api_search_constant(io: &mut IoHandler, store: Arc<store::Store>) {
+    io.add_method("search-constant", move |params| {
+        let params =
+            match params {
+                Params::Map(values) => values,
+                _ => Err(internal_server_error("params must be a map"))?
+            };
+
+        let name: String =
+            params.get("document-name")
+                .ok_or(internal_server_error("missing document-name field"))?
+                .as_str()
+                .ok_or(internal_server_error("name was not a string"))?
+                .to_string();
+
+        let matcher =
+            translate::json_to_constant_matcher(
+                params.get("match")
+                    .ok_or(internal_server_error("missing match field"))?)
+                .map_err(|e| internal_server_error(format!("{}", e)))?;
+
+        let function_index: Option<usize> =
+            match params.get("function-index") {
+                Some(index) => Some(
+                    index.as_u64()
+                        .ok_or(internal_server_error("function-index was not a valid number"))?
+                    as usize),
+                None => None
+            };
+
+        let store =
+            store.documents()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let document =
+            store
+                .get(&name)
+                .ok_or(internal_server_error(format!("Could not find document {}", name)))?;
+
+        let program =
+            document.program()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let uses =
+            match function_index {
+                Some(index) => {
+                    let function =
+                        program
+                            .function(index)
+                            .ok_or(internal_server_error(format!(
+                                "Could not find function-index: {}", index)))?;
+                    analysis::constants::function_search(function, &matcher)
+                },
+                None => analysis::constants::search(&program, &matcher)
+            };
+
+        Ok(uses.iter()
+            .map(|constant_use| translate::constant_use_to_json(constant_use))
+            .collect::<Vec<Value>>()
+            .into())
+    });
+}
+
+
+fn register_api_query(io: &mut IoHandler, store: Arc<store::Store>) {
+    io.add_method("query", move |params| {
+        let params =
+            match params {
+                Params::Map(values) => values,
+                _ => Err(internal_server_error("params must be a map"))?
+            };
+
+        let name: String =
+            params.get("document-name")
+                .ok_or(internal_server_error("missing document-name field"))?
+                .as_str()
+                .ok_or(internal_server_error("name was not a string"))?
+                .to_string();
+
+        let query =
+            analysis::query::parse(
+                params.get("query")
+                    .ok_or(internal_server_error("missing query field"))?
+                    .as_str()
+                    .ok_or(internal_server_error("query was not a string"))?)
+                .map_err(|e| internal_server_error(format!("{}", e)))?;
+
+        let function_index: Option<usize> =
+            match params.get("function-index") {
+                Some(index) => Some(
+                    index.as_u64()
+                        .ok_or(internal_server_error("function-index was not a valid number"))?
+                    as usize),
+                None => None
+            };
+
+        let store =
+            store.documents()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let document =
+            store
+                .get(&name)
+                .ok_or(internal_server_error(format!("Could not find document {}", name)))?;
+
+        let program =
+            document.program()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let matches =
+            match function_index {
+                Some(index) => {
+                    let function =
+                        program
+                            .function(index)
+                            .ok_or(internal_server_error(format!(
+                                "Could not find function-index: {}", index)))?;
+                    analysis::query::function_matches(function, &query)
+                },
+                None => analysis::query::matches(&program, &query)
+            };
+
+        Ok(matches.iter()
+            .map(|query_match| translate::query_match_to_json(query_match))
+            .collect::<Vec<Value>>()
+            .into())
+    });
+}
+
+
+fn register_api_memory_accesses(io: &mut IoHandler, store: Arc<store::Store>) {
+    io.add_method("memory-accesses", move |params| {
+        let params =
+            match params {
+                Params::Map(values) => values,
+                _ => Err(internal_server_error("params must be a map"))?
+            };
+
+        let name: String =
+            params.get("document-name")
+                .ok_or(internal_server_error("missing document-name field"))?
+                .as_str()
+                .ok_or(internal_server_error("name was not a string"))?
+                .to_string();
+
+        let start: u64 =
+            params.get("start")
+                .ok_or(internal_server_error("missing start field"))?
+                .as_u64()
+                .ok_or(internal_server_error("start was not a valid number"))?;
+
+        // Without an end, look for accesses of the single address.
+        let end: u64 =
+            match params.get("end") {
+                Some(end) =>
+                    end.as_u64()
+                        .ok_or(internal_server_error("end was not a valid number"))?,
+                None => start.saturating_add(1)
+            };
+
+        let store =
+            store.documents()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let document =
+            store
+                .get(&name)
+                .ok_or(internal_server_error(format!("Could not find document {}", name)))?;
+
+        let program =
+            document.program()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let accesses =
+            analysis::memory_accesses::memory_accesses(&program, start, end);
+
+        Ok(translate::memory_accesses_to_json(&accesses))
+    });
+}
+
+
+fn register_api_document_intrinsics(io: &mut IoHandler, store: Arc<store::Store>) {
+    io.add_method("document-intrinsics", move |params| {
+        let params =
+            match params {
+                Params::Map(values) => values,
+                _ => Err(internal_server_error("params must be a map"))?
+            };
+
+        let name: String =
+            params.get("document-name")
+                .ok_or(internal_server_error("missing document-name field"))?
+                .as_str()
+                .ok_or(internal_server_error("name was not a string"))?
+                .to_string();
+
+        let store =
+            store.documents()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let document =
+            store
+                .get(&name)
+                .ok_or(internal_server_error(format!("Could not find document {}", name)))?;
+
+        let program =
+            document.program()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let intrinsics = analysis::intrinsics::intrinsics(&program);
+
+        Ok(translate::intrinsics_to_json(&program, &intrinsics))
+    });
+}
+
+
+fn register_api_document_syscalls(io: &mut IoHandler, store: Arc<store::Store>) {
+    io.add_method("document-syscalls", move |params| {
+        let params =
+            match params {
+                Params::Map(values) => values,
+                _ => Err(internal_server_error("params must be a map"))?
+            };
+
+        let name: String =
+            params.get("document-name")
+                .ok_or(internal_server_error("missing document-name field"))?
+                .as_str()
+                .ok_or(internal_server_error("name was not a string"))?
+                .to_string();
+
+        let store =
+            store.documents()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let document =
+            store
+                .get(&name)
+                .ok_or(internal_server_error(format!("Could not find document {}", name)))?;
+
+        let program =
+            document.program()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let syscalls =
+            analysis::syscalls::syscalls(&program, document.loader())
+                .map_err(|e| internal_server_error(format!("{}", e)))?;
+
+        Ok(syscalls.iter()
+            .map(|syscall| translate::syscall_to_json(syscall))
+            .collect::<Vec<Value>>()
+            .into())
+    });
+}
+
+
+fn register_api_document_crypto(io: &mut IoHandler, store: Arc<store::Store>) {
+    io.add_method("document-crypto", move |params| {
+        let params =
+            match params {
+                Params::Map(values) => values,
+                _ => Err(internal_server_error("params must be a map"))?
+            };
+
+        let name: String =
+            params.get("document-name")
+                .ok_or(internal_server_error("missing document-name field"))?
+                .as_str()
+                .ok_or(internal_server_error("name was not a string"))?
+                .to_string();
+
+        let store =
+            store.documents()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let document =
+            store
+                .get(&name)
+                .ok_or(internal_server_error(format!("Could not find document {}", name)))?;
+
+        let program =
+            document.program()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let crypto =
+            analysis::crypto::crypto(&program, document.loader())
+                .map_err(|e| internal_server_error(format!("{}", e)))?;
+
+        Ok(translate::crypto_to_json(&program, &crypto))
+    });
+}
+
+
+fn register_api_memory_search(io: &mut IoHandler, store: Arc<store::Store>) {
+    io.add_method("memory-search", move |params| {
+        let params =
+            match params {
+                Params::Map(values) => values,
+                _ => Err(internal_server_error("params must be a map"))?
+            };
+
+        let name: String =
+            params.get("document-name")
+                .ok_or(internal_server_error("missing document-name field"))?
+                .as_str()
+                .ok_or(internal_server_error("name was not a string"))?
+                .to_string();
+
+        let patterns: Vec<pattern::Pattern> =
+            match params.get("patterns") {
+                Some(patterns) =>
+                    patterns.as_array()
+                        .ok_or(internal_server_error("patterns was not an array"))?
+                        .into_iter()
+                        .try_fold(Vec::new(), |mut patterns, p| {
+                            patterns.push(
+                                pattern::Pattern::hex(
+                                    p.as_str()
+                                        .ok_or(internal_server_error("pattern was not a string"))?)
+                                    .map_err(|e| internal_server_error(format!("{}", e)))?);
+                            Ok(patterns)
+                        })?,
+                None => Vec::new()
+            };
+
+        let rules: Vec<pattern::Rule> =
+            match params.get("rules") {
+                Some(rules) =>
+                    pattern::parse_rules(
+                        rules.as_str()
+                            .ok_or(internal_server_error("rules was not a string"))?)
+                        .map_err(|e| internal_server_error(format!("{}", e)))?,
+                None => Vec::new()
+            };
+
+        if patterns.is_empty() && rules.is_empty() {
+            Err(internal_server_error("missing patterns or rules field"))?;
+        }
+
+        let max_hits: usize =
+            match params.get("max-hits") {
+                Some(max_hits) =>
+                    max_hits.as_u64()
+                        .ok_or(internal_server_error("max-hits was not a valid number"))?
+                        as usize,
+                None => 10000
+            };
+
+        let store =
+            store.documents()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let document =
+            store
+                .get(&name)
+                .ok_or(internal_server_error(format!("Could not find document {}", name)))?;
+
+        let program =
+            document.program()
+                .map_err(|e| internal_server_error(e.description()))?;
+
+        let scanner = pattern::Scanner::new(document.loader(), &document.il_program(), &program);
+
+        let mut hits =
+            scanner.patterns(&patterns, max_hits)
+                .map_err(|e| internal_server_error(e.description()))?;
+        let remaining = max_hits - hits.len();
+        hits.append(
+            &mut scanner.rules(&rules, remaining)
+                .map_err(|e| internal_server_error(e.description()))?);
+
+        Ok(hits.iter()
+            .map(|hit| translate::hit_to_json(&program, hit))
+            .collect::<Vec<Value>>()
+            .into())
+    });
+}
+
+
+
 pub fn register_endpoints(global_store: Arc<store::Store>) -> IoHandler {
 
     let mut io = IoHandler::default();
@@ -376,6 +2311,34 @@ pub fn register_endpoints(global_store: Arc<store::Store>) -> IoHandler {
     register_api_function_ir(&mut io, global_store.clone());
     register_api_instruction_at(&mut io, global_store.clone());
     register_api_calls_to_symbol(&mut io, global_store.clone());
+    register_api_slice(&mut io, global_store.clone());
+    register_api_taint(&mut io, global_store.clone());
+    register_api_document_audit(&mut io, global_store.clone());
+    register_api_format_strings(&mut io, global_store.clone());
+    register_api_function_dominators(&mut io, global_store.clone());
+    register_api_function_loops(&mut io, global_store.clone());
+    register_api_function_ssa(&mut io, global_store.clone());
+    register_api_function_liveness(&mut io, global_store.clone());
+    register_api_document_unused_results(&mut io, global_store.clone());
+    register_api_function_emulate(&mut io, global_store.clone());
+    register_api_function_explore(&mut io, global_store.clone());
+    register_api_path_smtlib(&mut io, global_store.clone());
+    register_api_function_paths(&mut io, global_store.clone());
+    register_api_callgraph_paths(&mut io, global_store.clone());
+    register_api_document_reachability(&mut io, global_store.clone());
+    register_api_function_create(&mut io, global_store.clone());
+    register_api_function_retranslate(&mut io, global_store.clone());
+    register_api_function_il(&mut io, global_store.clone());
+    register_api_disassemble(&mut io, global_store.clone());
+    register_api_function_disassembly(&mut io, global_store.clone());
+    register_api_instructions_in_range(&mut io, global_store.clone());
+    register_api_search_constant(&mut io, global_store.clone());
+    register_api_query(&mut io, global_store.clone());
+    register_api_memory_accesses(&mut io, global_store.clone());
+    register_api_document_intrinsics(&mut io, global_store.clone());
+    register_api_document_syscalls(&mut io, global_store.clone());
+    register_api_document_crypto(&mut io, global_store.clone());
+    register_api_memory_search(&mut io, global_store.clone());
 
     io
 }
\ No newline at end of file
@@ -1,6 +1,6 @@
 use document::Document;
 use error::*;
-use owning_ref::RwLockReadGuardRef;
+use owning_ref::{RwLockReadGuardRef, RwLockWriteGuardRefMut};
 use std::collections::HashMap;
 use std::sync::RwLock;
 
@@ -25,6 +25,15 @@ impl Store {
         Ok(documents)
     }
 
+    pub fn documents_mut(&self) -> Result<RwLockWriteGuardRefMut<HashMap<String, Document>, HashMap<String, Document>>> {
+        let documents = match self.documents.write() {
+            Ok(documents) => RwLockWriteGuardRefMut::new(documents),
+            Err(_) => bail!("Lock poisoned for store document")
+        };
+
+        Ok(documents)
+    }
+
     pub fn add_document<S: Into<String>>(
         &self,
         name: S,
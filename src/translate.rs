@@ -1,8 +1,33 @@
+use analysis::audit;
+use analysis::callgraph::CallGraph;
+use analysis::constants::{ConstantUse, Matcher};
+use analysis::crypto::Crypto;
+use analysis::dominators::Dominators;
+use analysis::format_string;
+use analysis::InstructionLocation;
+use analysis::intrinsics::{Coverage, Intrinsics};
+use analysis::liveness::{Liveness, UnusedResult};
+use analysis::loops::Loop;
+use analysis::memory_accesses::{Access, MemoryAccesses};
+use analysis::query::{Captured, QueryMatch};
+use analysis::reachability::Reachability;
+use analysis::slice::Slice;
+use analysis::smt::Encoder;
+use analysis::ssa::Ssa;
+use analysis::symbolic::{Exploration, Outcome};
+use analysis::syscalls::{self, Syscall};
+use analysis::taint;
+use disassembly;
+use document::Pipeline;
+use emulator::{Emulation, Stop};
+use pattern::Hit;
+use error::*;
 use falcon::il;
 use jsonrpc_http_server::jsonrpc_core::Value;
 use raptor::features::XRefs;
 use raptor::ir;
 use serde_json::Map;
+use std::collections::{BTreeMap, BTreeSet};
 
 
 
@@ -390,4 +415,1373 @@ pub fn xrefs_to_json(xrefs: &XRefs) -> Value {
     m.insert("to_from".to_string(), to_from.into());
 
     m.into()
-}
\ No newline at end of file
+}
+
+pub fn instruction_location_to_json(location: &InstructionLocation) -> Value {
+    let mut fl = Map::new();
+    fl.insert("block-index".to_string(), location.block_index().into());
+    fl.insert("instruction-index".to_string(),
+              location.instruction_index().into());
+
+    let mut m = Map::new();
+    m.insert("function-index".to_string(), location.function_index().into());
+    m.insert("function-location".to_string(), fl.into());
+
+    m.into()
+}
+
+
+pub fn json_to_instruction_location(value: &Value) -> Result<InstructionLocation> {
+    let function_index =
+        value.get("function-index")
+            .and_then(|index| index.as_u64())
+            .ok_or("program location missing function-index")?;
+    let function_location =
+        value.get("function-location")
+            .ok_or("program location missing function-location")?;
+    let block_index =
+        function_location.get("block-index")
+            .and_then(|index| index.as_u64())
+            .ok_or("function location missing block-index")?;
+    let instruction_index =
+        function_location.get("instruction-index")
+            .and_then(|index| index.as_u64())
+            .ok_or("function location missing instruction-index")?;
+
+    Ok(InstructionLocation::new(
+        function_index as usize,
+        block_index as usize,
+        instruction_index as usize))
+}
+
+
+/// A function index given directly, or the function holding a program
+/// location.
+pub fn json_to_function_index(value: &Value) -> Result<usize> {
+    match value.as_u64() {
+        Some(index) => Ok(index as usize),
+        None => Ok(json_to_instruction_location(value)?.function_index())
+    }
+}
+
+
+/// A pipeline like `{"passes": ["optimize", "dead-code-elimination"],
+/// "max-iterations": 64, "keep-unoptimized": false,
+/// "discover-functions": false}`. Missing fields keep their defaults.
+pub fn json_to_pipeline(value: &Value) -> Result<Pipeline> {
+    let mut pipeline = Pipeline::default();
+
+    if let Some(passes) = value.get("passes") {
+        pipeline.optimize = false;
+        pipeline.dead_code_elimination = false;
+        for pass in passes.as_array().ok_or("passes was not an array")? {
+            match pass.as_str() {
+                Some("optimize") => pipeline.optimize = true,
+                Some("dead-code-elimination") => pipeline.dead_code_elimination = true,
+                _ => bail!("Unknown pass {}", pass)
+            }
+        }
+    }
+
+    if let Some(max_iterations) = value.get("max-iterations") {
+        pipeline.max_iterations =
+            max_iterations.as_u64().ok_or("max-iterations was not a valid number")?
+                as usize;
+    }
+
+    if let Some(keep_unoptimized) = value.get("keep-unoptimized") {
+        pipeline.keep_unoptimized =
+            keep_unoptimized.as_bool().ok_or("keep-unoptimized was not a bool")?;
+    }
+
+    if let Some(discover_functions) = value.get("discover-functions") {
+        pipeline.discover_functions =
+            discover_functions.as_bool().ok_or("discover-functions was not a bool")?;
+    }
+
+    Ok(pipeline)
+}
+
+
+pub fn json_to_variable(value: &Value) -> Result<ir::Variable> {
+    let bits =
+        value.get("bits")
+            .and_then(|bits| bits.as_u64())
+            .ok_or("variable missing bits")? as usize;
+
+    match value.get("type").and_then(|t| t.as_str()) {
+        Some("scalar") => {
+            let name =
+                value.get("name")
+                    .and_then(|name| name.as_str())
+                    .ok_or("scalar missing name")?;
+            Ok(ir::Variable::Scalar(ir::Scalar::new(name, bits)))
+        },
+        Some("stack_variable") => {
+            let offset =
+                value.get("offset")
+                    .and_then(|offset| offset.as_i64())
+                    .ok_or("stack_variable missing offset")?;
+            Ok(ir::Variable::StackVariable(
+                ir::StackVariable::new(offset as isize, bits)))
+        },
+        _ => bail!("variable type must be scalar or stack_variable")
+    }
+}
+
+
+/// Every variable referenced in an expression in the format produced by
+/// `expression_to_json`.
+pub fn json_to_variables(value: &Value) -> Result<Vec<ir::Variable>> {
+    let mut variables = Vec::new();
+    match value {
+        Value::Object(m) => {
+            match m.get("type").and_then(|t| t.as_str()) {
+                Some("scalar") | Some("stack_variable") =>
+                    variables.push(json_to_variable(value)?),
+                _ => {
+                    for v in m.values() {
+                        variables.append(&mut json_to_variables(v)?);
+                    }
+                }
+            }
+        },
+        Value::Array(a) => {
+            for v in a {
+                variables.append(&mut json_to_variables(v)?);
+            }
+        },
+        _ => {}
+    }
+    Ok(variables)
+}
+
+
+pub fn slice_to_json(
+    slice: &Slice,
+    program: &ir::Program<ir::Constant>
+) -> Value {
+    let functions =
+        slice.functions()
+            .into_iter()
+            .filter_map(|function_index| {
+                let function = program.function(function_index)?;
+                let blocks = slice.blocks(function_index);
+
+                let mut m = Map::new();
+                m.insert("function-index".to_string(), function_index.into());
+                m.insert("name".to_string(), function.name().into());
+                m.insert("blocks".to_string(),
+                    function.blocks()
+                        .into_iter()
+                        .filter_map(|block| {
+                            let indices = blocks.get(&block.index())?;
+                            let mut b = Map::new();
+                            b.insert("index".to_string(), block.index().into());
+                            b.insert("instructions".to_string(),
+                                block.instructions()
+                                    .into_iter()
+                                    .filter(|instruction|
+                                        indices.contains(&instruction.index()))
+                                    .map(|instruction| instruction_to_json(instruction))
+                                    .collect::<Vec<Value>>()
+                                    .into());
+                            Some(b.into())
+                        })
+                        .collect::<Vec<Value>>()
+                        .into());
+                m.insert("edges".to_string(),
+                    slice.edges(function_index)
+                        .map(|edges| block_edges_to_json(edges))
+                        .unwrap_or(Value::Null));
+                Some(m.into())
+            })
+            .collect::<Vec<Value>>();
+
+    let mut m = Map::new();
+    m.insert("locations".to_string(),
+        slice.locations()
+            .into_iter()
+            .map(|location| instruction_location_to_json(location))
+            .collect::<Vec<Value>>()
+            .into());
+    m.insert("functions".to_string(), functions.into());
+
+    m.into()
+}
+
+
+
+fn json_to_positions(value: Option<&Value>) -> Result<Vec<usize>> {
+    match value {
+        Some(value) =>
+            value.as_array()
+                .ok_or("arguments was not an array")?
+                .into_iter()
+                .try_fold(Vec::new(), |mut positions, position| {
+                    positions.push(
+                        position.as_u64()
+                            .ok_or("argument position was not a valid number")?
+                        as usize);
+                    Ok(positions)
+                }),
+        None => Ok(Vec::new())
+    }
+}
+
+
+pub fn json_to_taint_source(value: &Value) -> Result<taint::Source> {
+    let symbol =
+        value.get("symbol")
+            .and_then(|symbol| symbol.as_str())
+            .ok_or("source missing symbol")?;
+    let return_value =
+        value.get("return")
+            .map(|r| r.as_bool().ok_or("source return was not a bool"))
+            .unwrap_or(Ok(false))?;
+    let arguments = json_to_positions(value.get("arguments"))?;
+
+    Ok(taint::Source::new(symbol, return_value, arguments))
+}
+
+
+pub fn json_to_taint_sink(value: &Value) -> Result<taint::Sink> {
+    let symbol =
+        value.get("symbol")
+            .and_then(|symbol| symbol.as_str())
+            .ok_or("sink missing symbol")?;
+    let arguments = json_to_positions(value.get("arguments"))?;
+
+    Ok(taint::Sink::new(symbol, arguments))
+}
+
+
+pub fn taint_flow_to_json(flow: &taint::Flow) -> Value {
+    let mut source = Map::new();
+    source.insert("symbol".to_string(), flow.source().into());
+    source.insert("location".to_string(),
+                  instruction_location_to_json(flow.source_location()));
+
+    let mut sink = Map::new();
+    sink.insert("symbol".to_string(), flow.sink().into());
+    sink.insert("location".to_string(),
+                instruction_location_to_json(flow.sink_location()));
+    sink.insert("argument".to_string(), flow.argument().into());
+
+    let mut m = Map::new();
+    m.insert("source".to_string(), source.into());
+    m.insert("sink".to_string(), sink.into());
+    m.insert("path".to_string(),
+        flow.path()
+            .into_iter()
+            .map(|location| instruction_location_to_json(location))
+            .collect::<Vec<Value>>()
+            .into());
+
+    m.into()
+}
+
+
+
+pub fn json_to_audit_rule(value: &Value) -> Result<audit::Rule> {
+    let symbol =
+        value.get("symbol")
+            .and_then(|symbol| symbol.as_str())
+            .ok_or("rule missing symbol")?;
+    let severity =
+        audit::Severity::from_name(
+            value.get("severity")
+                .and_then(|severity| severity.as_str())
+                .ok_or("rule missing severity")?)?;
+    let description =
+        value.get("description")
+            .and_then(|description| description.as_str())
+            .unwrap_or("");
+
+    let position = |check: &Value, field: &str| -> Result<usize> {
+        Ok(check.get(field)
+            .and_then(|position| position.as_u64())
+            .ok_or(format!("check missing {}", field))? as usize)
+    };
+
+    let checks =
+        value.get("checks")
+            .and_then(|checks| checks.as_array())
+            .ok_or("rule missing checks")?
+            .into_iter()
+            .try_fold(Vec::new(), |mut checks, check| -> Result<Vec<audit::Check>> {
+                checks.push(
+                    match check.get("type").and_then(|t| t.as_str()) {
+                        Some("always") => audit::Check::Always,
+                        Some("non-constant") =>
+                            audit::Check::NonConstant(position(check, "argument")?),
+                        Some("stack-destination") =>
+                            audit::Check::StackDestination(position(check, "argument")?),
+                        Some("size-exceeds-destination") =>
+                            audit::Check::SizeExceedsDestination {
+                                destination: position(check, "destination")?,
+                                size: position(check, "size")?
+                            },
+                        _ => bail!("Unknown check type")
+                    });
+                Ok(checks)
+            })?;
+
+    Ok(audit::Rule::new(symbol, checks, severity, description))
+}
+
+
+pub fn audit_finding_to_json(finding: &audit::Finding) -> Value {
+    let mut m = Map::new();
+
+    m.insert("symbol".to_string(), finding.rule().symbol().into());
+    m.insert("severity".to_string(), finding.rule().severity().as_str().into());
+    m.insert("description".to_string(), finding.rule().description().into());
+    m.insert("check".to_string(), finding.check().name().into());
+    m.insert("details".to_string(), finding.details().into());
+    m.insert("location".to_string(),
+             instruction_location_to_json(finding.location()));
+
+    m.into()
+}
+
+
+
+pub fn variable_to_string(variable: &ir::Variable) -> String {
+    match variable {
+        ir::Variable::Scalar(scalar) =>
+            format!("{}:{}", scalar.name(), scalar.bits()),
+        ir::Variable::StackVariable(stack_variable) =>
+            if stack_variable.offset() < 0 {
+                format!("var_0x{:x}:{}",
+                        -stack_variable.offset(), stack_variable.bits())
+            }
+            else {
+                format!("arg_0x{:x}:{}",
+                        stack_variable.offset(), stack_variable.bits())
+            }
+    }
+}
+
+
+/// Render an expression as text, in the same notation as the python client.
+pub fn expression_to_string(expression: &ir::Expression<ir::Constant>) -> String {
+    fn lvalue_to_string(lvalue: &ir::LValue<ir::Constant>) -> String {
+        match lvalue {
+            ir::LValue::Variable(variable) => variable_to_string(variable),
+            ir::LValue::Dereference(dereference) =>
+                format!("*({})", expression_to_string(dereference.expression()))
+        }
+    }
+
+    fn rvalue_to_string(rvalue: &ir::RValue<ir::Constant>) -> String {
+        match rvalue {
+            ir::RValue::Value(constant) =>
+                format!("0x{}:{}", constant.value().to_str_radix(16), constant.bits()),
+            ir::RValue::Reference(reference) =>
+                format!("&({})", expression_to_string(reference.expression()))
+        }
+    }
+
+    fn binop(
+        op: &str,
+        lhs: &ir::Expression<ir::Constant>,
+        rhs: &ir::Expression<ir::Constant>
+    ) -> String {
+        format!("({} {} {})",
+                expression_to_string(lhs), op, expression_to_string(rhs))
+    }
+
+    match expression {
+        ir::Expression::LValue(lvalue) => lvalue_to_string(lvalue),
+        ir::Expression::RValue(rvalue) => rvalue_to_string(rvalue),
+        ir::Expression::Add(lhs, rhs) => binop("+", lhs, rhs),
+        ir::Expression::Sub(lhs, rhs) => binop("-", lhs, rhs),
+        ir::Expression::Mul(lhs, rhs) => binop("*", lhs, rhs),
+        ir::Expression::Divu(lhs, rhs) => binop("/u", lhs, rhs),
+        ir::Expression::Modu(lhs, rhs) => binop("%u", lhs, rhs),
+        ir::Expression::Divs(lhs, rhs) => binop("/s", lhs, rhs),
+        ir::Expression::Mods(lhs, rhs) => binop("%s", lhs, rhs),
+        ir::Expression::And(lhs, rhs) => binop("&", lhs, rhs),
+        ir::Expression::Or(lhs, rhs) => binop("|", lhs, rhs),
+        ir::Expression::Xor(lhs, rhs) => binop("^", lhs, rhs),
+        ir::Expression::Shl(lhs, rhs) => binop("<<", lhs, rhs),
+        ir::Expression::Shr(lhs, rhs) => binop(">>", lhs, rhs),
+        ir::Expression::Cmpeq(lhs, rhs) => binop("==", lhs, rhs),
+        ir::Expression::Cmpneq(lhs, rhs) => binop("!=", lhs, rhs),
+        ir::Expression::Cmplts(lhs, rhs) => binop("<s", lhs, rhs),
+        ir::Expression::Cmpltu(lhs, rhs) => binop("<u", lhs, rhs),
+        ir::Expression::Trun(bits, rhs) =>
+            format!("trun.{}({})", bits, expression_to_string(rhs)),
+        ir::Expression::Sext(bits, rhs) =>
+            format!("sext.{}({})", bits, expression_to_string(rhs)),
+        ir::Expression::Zext(bits, rhs) =>
+            format!("zext.{}({})", bits, expression_to_string(rhs)),
+        ir::Expression::Ite(cond, then, else_) =>
+            format!("ite({}, {}, {})",
+                    expression_to_string(cond),
+                    expression_to_string(then),
+                    expression_to_string(else_))
+    }
+}
+
+
+pub fn format_string_to_json(format_string: &format_string::FormatString) -> Value {
+    let mut m = Map::new();
+
+    m.insert("symbol".to_string(), format_string.symbol().into());
+    m.insert("location".to_string(),
+             instruction_location_to_json(format_string.location()));
+    m.insert("argument".to_string(), format_string.argument().into());
+    m.insert("expression".to_string(),
+             expression_to_string(format_string.expression()).into());
+    m.insert("address".to_string(),
+        format_string.address()
+            .map(|address| address.into())
+            .unwrap_or(Value::Null));
+    m.insert("reason".to_string(), format_string.reason().as_str().into());
+    m.insert("parameter-influenced".to_string(),
+             format_string.parameter_influenced().into());
+
+    m.into()
+}
+
+
+
+fn index_set_to_json<'a, I: IntoIterator<Item=&'a usize>>(blocks: I) -> Value {
+    blocks.into_iter()
+        .map(|index| (*index).into())
+        .collect::<Vec<Value>>()
+        .into()
+}
+
+
+fn block_edges_to_json<'a, I: IntoIterator<Item=&'a (usize, usize)>>(edges: I)
+    -> Value {
+
+    edges.into_iter()
+        .map(|&(head, tail)| {
+            let mut e = Map::new();
+            e.insert("head".to_string(), head.into());
+            e.insert("tail".to_string(), tail.into());
+            e.into()
+        })
+        .collect::<Vec<Value>>()
+        .into()
+}
+
+
+pub fn dominators_to_json(
+    function: &ir::Function<ir::Constant>,
+    dominators: &Dominators
+) -> Value {
+    let blocks =
+        function.blocks()
+            .into_iter()
+            .map(|block| {
+                let index = block.index();
+                let mut b = Map::new();
+                b.insert("index".to_string(), index.into());
+                b.insert("immediate-dominator".to_string(),
+                    dominators.immediate_dominator(index)
+                        .map(|idom| idom.into())
+                        .unwrap_or(Value::Null));
+                b.insert("immediate-post-dominator".to_string(),
+                    dominators.immediate_post_dominator(index)
+                        .map(|ipdom| ipdom.into())
+                        .unwrap_or(Value::Null));
+                b.insert("dominance-frontier".to_string(),
+                    dominators.frontier(index)
+                        .map(|frontier| index_set_to_json(frontier))
+                        .unwrap_or(Value::Null));
+                b.insert("post-dominance-frontier".to_string(),
+                    dominators.post_frontier(index)
+                        .map(|frontier| index_set_to_json(frontier))
+                        .unwrap_or(Value::Null));
+                b.into()
+            })
+            .collect::<Vec<Value>>();
+
+    let mut m = Map::new();
+    m.insert("entry".to_string(),
+        dominators.entry()
+            .map(|entry| entry.into())
+            .unwrap_or(Value::Null));
+    m.insert("blocks".to_string(), blocks.into());
+
+    m.into()
+}
+
+
+pub fn loop_to_json(l: &Loop) -> Value {
+    let mut m = Map::new();
+
+    m.insert("header".to_string(), l.header().into());
+    m.insert("blocks".to_string(), index_set_to_json(l.blocks()));
+    m.insert("back-edges".to_string(), block_edges_to_json(l.back_edges()));
+    m.insert("exits".to_string(), block_edges_to_json(l.exits()));
+    m.insert("depth".to_string(), l.depth().into());
+    m.insert("parent".to_string(),
+        l.parent()
+            .map(|parent| parent.into())
+            .unwrap_or(Value::Null));
+
+    m.into()
+}
+
+
+
+/// Add a `version` field to every variable in `value`, which holds an
+/// operation serialized by `operation_to_json`.
+fn annotate_versions(
+    value: &mut Value,
+    uses: &BTreeMap<ir::Variable, usize>,
+    defs: &BTreeMap<ir::Variable, usize>,
+    written: bool
+) {
+    if let Ok(variable) = json_to_variable(value) {
+        let versions = if written { defs } else { uses };
+        if let (Some(version), Value::Object(m)) = (versions.get(&variable), value) {
+            m.insert("version".to_string(), (*version).into());
+        }
+        return;
+    }
+
+    match value {
+        Value::Object(m) => {
+            for (key, v) in m.iter_mut() {
+                let written = written ||
+                    key == "dst" ||
+                    key == "variables_written" ||
+                    key == "written_expressions";
+                annotate_versions(v, uses, defs, written);
+            }
+        },
+        Value::Array(a) => {
+            for v in a.iter_mut() {
+                annotate_versions(v, uses, defs, written);
+            }
+        },
+        _ => {}
+    }
+}
+
+
+pub fn ssa_function_to_json(function: &ir::Function<ir::Constant>, ssa: &Ssa) -> Value {
+    let function_index = ssa.function_index();
+    let empty = BTreeMap::new();
+
+    let blocks =
+        function.blocks()
+            .into_iter()
+            .map(|block| {
+                let mut b = Map::new();
+                b.insert("index".to_string(), block.index().into());
+                b.insert("phis".to_string(),
+                    ssa.phis(block.index())
+                        .map(|phis| phis
+                            .into_iter()
+                            .map(|phi| {
+                                let mut variable = variable_to_json(phi.variable());
+                                if let Value::Object(ref mut v) = variable {
+                                    v.insert("version".to_string(), phi.version().into());
+                                }
+                                let mut p = Map::new();
+                                p.insert("dst".to_string(), variable);
+                                p.insert("arguments".to_string(),
+                                    phi.arguments()
+                                        .into_iter()
+                                        .map(|(block_index, version)| {
+                                            let mut a = Map::new();
+                                            a.insert("block-index".to_string(),
+                                                     (*block_index).into());
+                                            a.insert("version".to_string(),
+                                                     (*version).into());
+                                            a.into()
+                                        })
+                                        .collect::<Vec<Value>>()
+                                        .into());
+                                p.into()
+                            })
+                            .collect::<Vec<Value>>()
+                            .into())
+                        .unwrap_or(Value::Array(Vec::new())));
+                b.insert("instructions".to_string(),
+                    block.instructions()
+                        .into_iter()
+                        .map(|instruction| {
+                            let location = InstructionLocation::new(
+                                function_index, block.index(), instruction.index());
+                            let mut value = instruction_to_json(instruction);
+                            if let Some(operation) = value.get_mut("operation") {
+                                annotate_versions(
+                                    operation,
+                                    ssa.uses(&location).unwrap_or(&empty),
+                                    ssa.defs(&location).unwrap_or(&empty),
+                                    false);
+                            }
+                            value
+                        })
+                        .collect::<Vec<Value>>()
+                        .into());
+                b.into()
+            })
+            .collect::<Vec<Value>>();
+
+    let mut m = Map::new();
+
+    m.insert("address".to_string(), function.address().into());
+    m.insert("index".to_string(),
+        function.index()
+            .map(|index| index.into())
+            .unwrap_or(Value::Null));
+    m.insert("name".to_string(), function.name().into());
+    m.insert("blocks".to_string(), blocks.into());
+    m.insert("edges".to_string(),
+        function.edges()
+            .into_iter()
+            .map(|edge| edge_to_json(edge))
+            .collect::<Vec<Value>>()
+            .into());
+
+    m.into()
+}
+
+
+
+fn variable_set_to_json(variables: Option<&BTreeSet<ir::Variable>>) -> Value {
+    variables
+        .map(|variables| variables
+            .into_iter()
+            .map(|variable| variable_to_json(variable))
+            .collect::<Vec<Value>>()
+            .into())
+        .unwrap_or(Value::Null)
+}
+
+
+pub fn unused_result_to_json(unused: &UnusedResult) -> Value {
+    let mut m = Map::new();
+
+    m.insert("location".to_string(), instruction_location_to_json(unused.location()));
+    m.insert("target".to_string(), unused.target().into());
+    m.insert("variables_written".to_string(),
+        unused.variables_written()
+            .into_iter()
+            .map(|variable| variable_to_json(variable))
+            .collect::<Vec<Value>>()
+            .into());
+
+    m.into()
+}
+
+
+pub fn liveness_to_json(
+    function: &ir::Function<ir::Constant>,
+    liveness: &Liveness,
+    unused_results: &[UnusedResult]
+) -> Value {
+    let function_index = liveness.function_index();
+
+    let blocks =
+        function.blocks()
+            .into_iter()
+            .map(|block| {
+                let mut b = Map::new();
+                b.insert("index".to_string(), block.index().into());
+                b.insert("live-in".to_string(),
+                         variable_set_to_json(liveness.block_in(block.index())));
+                b.insert("live-out".to_string(),
+                         variable_set_to_json(liveness.block_out(block.index())));
+                b.insert("instructions".to_string(),
+                    block.instructions()
+                        .into_iter()
+                        .map(|instruction| {
+                            let location = InstructionLocation::new(
+                                function_index, block.index(), instruction.index());
+                            let mut i = Map::new();
+                            i.insert("index".to_string(), instruction.index().into());
+                            i.insert("live-in".to_string(),
+                                variable_set_to_json(liveness.instruction_in(&location)));
+                            i.insert("live-out".to_string(),
+                                variable_set_to_json(liveness.instruction_out(&location)));
+                            i.into()
+                        })
+                        .collect::<Vec<Value>>()
+                        .into());
+                b.into()
+            })
+            .collect::<Vec<Value>>();
+
+    let mut m = Map::new();
+    m.insert("blocks".to_string(), blocks.into());
+    m.insert("unused-results".to_string(),
+        unused_results.into_iter()
+            .map(|unused| unused_result_to_json(unused))
+            .collect::<Vec<Value>>()
+            .into());
+
+    m.into()
+}
+
+
+fn il_constant_to_json(constant: &il::Constant) -> Value {
+    let mut m = Map::new();
+    m.insert("value".to_string(),
+             format!("0x{}", constant.value().to_str_radix(16)).into());
+    m.insert("bits".to_string(), constant.bits().into());
+    m.into()
+}
+
+
+pub fn emulation_to_json(emulation: &Emulation) -> Value {
+    let mut m = Map::new();
+
+    m.insert("trace".to_string(),
+        emulation.trace()
+            .into_iter()
+            .map(|step| {
+                let mut s = Map::new();
+                s.insert("address".to_string(),
+                    match step.address() {
+                        Some(address) => address.into(),
+                        None => Value::Null
+                    });
+                s.insert("instruction".to_string(), step.instruction().into());
+                s.into()
+            })
+            .collect::<Vec<Value>>()
+            .into());
+
+    let mut registers = Map::new();
+    for (name, value) in emulation.registers() {
+        registers.insert(name.to_string(), il_constant_to_json(value));
+    }
+    m.insert("registers".to_string(), registers.into());
+
+    m.insert("memory-writes".to_string(),
+        emulation.memory_writes()
+            .into_iter()
+            .map(|write| {
+                let mut w = il_constant_to_json(write.value());
+                if let Value::Object(ref mut w) = w {
+                    w.insert("address".to_string(), write.address().into());
+                }
+                w
+            })
+            .collect::<Vec<Value>>()
+            .into());
+
+    m.insert("stop".to_string(), emulation.stop().as_str().into());
+    match *emulation.stop() {
+        Stop::UnresolvedCall(target) => {
+            m.insert("target".to_string(), target.into());
+        },
+        Stop::Error(ref message) => {
+            m.insert("error".to_string(), message.as_str().into());
+        },
+        _ => {}
+    }
+
+    m.into()
+}
+
+
+pub fn exploration_to_json(exploration: &Exploration) -> Value {
+    let mut m = Map::new();
+
+    m.insert("paths-explored".to_string(), exploration.paths_explored().into());
+
+    match exploration.outcome() {
+        Outcome::Reached { path, assignments } => {
+            m.insert("outcome".to_string(), "reached".into());
+            m.insert("path".to_string(),
+                path.into_iter()
+                    .map(|index| (*index).into())
+                    .collect::<Vec<Value>>()
+                    .into());
+            m.insert("assignments".to_string(),
+                assignments.into_iter()
+                    .map(|(variable, value)| {
+                        let mut a = Map::new();
+                        a.insert("variable".to_string(), variable_to_json(variable));
+                        a.insert("value".to_string(), (*value).into());
+                        a.into()
+                    })
+                    .collect::<Vec<Value>>()
+                    .into());
+        },
+        Outcome::Unreachable => {
+            m.insert("outcome".to_string(), "unreachable".into());
+        },
+        Outcome::Unknown => {
+            m.insert("outcome".to_string(), "unknown".into());
+        }
+    }
+
+    m.into()
+}
+
+
+pub fn path_constraints_to_json(path: &[usize], encoder: &Encoder) -> Value {
+    let mut m = Map::new();
+
+    m.insert("path".to_string(),
+        path.into_iter()
+            .map(|index| (*index).into())
+            .collect::<Vec<Value>>()
+            .into());
+    m.insert("inputs".to_string(),
+        encoder.inputs()
+            .into_iter()
+            .map(|(symbol, variable)| {
+                let mut i = Map::new();
+                i.insert("symbol".to_string(), symbol.as_str().into());
+                i.insert("variable".to_string(), variable_to_json(variable));
+                i.into()
+            })
+            .collect::<Vec<Value>>()
+            .into());
+    m.insert("smtlib".to_string(), format!("{}(check-sat)\n", encoder.smtlib()).into());
+
+    m.into()
+}
+
+
+pub fn block_paths_to_json(paths: &[Vec<usize>], truncated: bool) -> Value {
+    let mut m = Map::new();
+
+    m.insert("paths".to_string(),
+        paths.into_iter()
+            .map(|path|
+                path.into_iter()
+                    .map(|index| (*index).into())
+                    .collect::<Vec<Value>>()
+                    .into())
+            .collect::<Vec<Value>>()
+            .into());
+    m.insert("truncated".to_string(), truncated.into());
+
+    m.into()
+}
+
+
+pub fn callgraph_paths_to_json(
+    paths: &[Vec<usize>],
+    truncated: bool,
+    call_graph: &CallGraph
+) -> Value {
+    let mut m = Map::new();
+
+    m.insert("paths".to_string(),
+        paths.into_iter()
+            .map(|path| {
+                let mut p = Map::new();
+                p.insert("functions".to_string(),
+                    path.into_iter()
+                        .map(|index| (*index).into())
+                        .collect::<Vec<Value>>()
+                        .into());
+                p.insert("call-sites".to_string(),
+                    path.windows(2)
+                        .map(|hop|
+                            call_graph.call_sites(hop[0], hop[1])
+                                .into_iter()
+                                .map(|location| instruction_location_to_json(location))
+                                .collect::<Vec<Value>>()
+                                .into())
+                        .collect::<Vec<Value>>()
+                        .into());
+                p.into()
+            })
+            .collect::<Vec<Value>>()
+            .into());
+    m.insert("truncated".to_string(), truncated.into());
+
+    m.into()
+}
+
+
+pub fn reachability_to_json(reachability: &Reachability) -> Value {
+    let mut unreachable_blocks = Map::new();
+    for (index, blocks) in reachability.unreachable_blocks() {
+        unreachable_blocks.insert(format!("{}", index), index_set_to_json(blocks));
+    }
+
+    let mut m = Map::new();
+
+    m.insert("roots".to_string(), index_set_to_json(reachability.roots()));
+    m.insert("reachable".to_string(), index_set_to_json(reachability.reachable()));
+    m.insert("unreachable".to_string(), index_set_to_json(reachability.unreachable()));
+    m.insert("input-callers".to_string(),
+             index_set_to_json(reachability.input_callers()));
+    m.insert("input-paths".to_string(), index_set_to_json(reachability.input_paths()));
+    m.insert("unreachable-blocks".to_string(), unreachable_blocks.into());
+
+    m.into()
+}
+
+
+pub fn il_instruction_to_json(instruction: &il::Instruction) -> Value {
+    let kind = match instruction.operation() {
+        il::Operation::Assign { .. } => "assign",
+        il::Operation::Store { .. } => "store",
+        il::Operation::Load { .. } => "load",
+        il::Operation::Branch { .. } => "branch",
+        il::Operation::Intrinsic { .. } => "intrinsic",
+        _ => "nop"
+    };
+
+    let mut m = Map::new();
+    m.insert("index".to_string(), instruction.index().into());
+    m.insert("address".to_string(),
+        instruction.address()
+            .map(|address| address.into())
+            .unwrap_or(Value::Null));
+    m.insert("kind".to_string(), kind.into());
+    m.insert("operation".to_string(), format!("{}", instruction.operation()).into());
+    m.into()
+}
+
+
+/// The IL of `il_function`, and for each instruction of `function`, its
+/// raptor translation, the IL instructions lifted from the same address.
+pub fn il_function_to_json(
+    il_function: &il::Function,
+    function: &ir::Function<ir::Constant>
+) -> Value {
+    let mut by_address: BTreeMap<u64, Vec<Value>> = BTreeMap::new();
+
+    let blocks =
+        il_function.blocks()
+            .into_iter()
+            .map(|block| {
+                let mut b = Map::new();
+                b.insert("index".to_string(), block.index().into());
+                b.insert("instructions".to_string(),
+                    block.instructions()
+                        .into_iter()
+                        .map(|instruction| {
+                            if let Some(address) = instruction.address() {
+                                let mut l = Map::new();
+                                l.insert("block-index".to_string(), block.index().into());
+                                l.insert("instruction-index".to_string(),
+                                         instruction.index().into());
+                                by_address.entry(address)
+                                    .or_insert_with(Vec::new)
+                                    .push(l.into());
+                            }
+                            il_instruction_to_json(instruction)
+                        })
+                        .collect::<Vec<Value>>()
+                        .into());
+                b.into()
+            })
+            .collect::<Vec<Value>>();
+
+    let edges =
+        il_function.control_flow_graph()
+            .edges()
+            .into_iter()
+            .map(|edge| {
+                let mut e = Map::new();
+                e.insert("head".to_string(), edge.head().into());
+                e.insert("tail".to_string(), edge.tail().into());
+                e.insert("condition".to_string(),
+                    edge.condition()
+                        .map(|condition| format!("{}", condition).into())
+                        .unwrap_or(Value::Null));
+                e.into()
+            })
+            .collect::<Vec<Value>>();
+
+    let mut mapping = Vec::new();
+    for block in function.blocks() {
+        for instruction in block.instructions() {
+            let mut i = Map::new();
+            i.insert("block-index".to_string(), block.index().into());
+            i.insert("instruction-index".to_string(), instruction.index().into());
+            i.insert("address".to_string(),
+                instruction.address()
+                    .map(|address| address.into())
+                    .unwrap_or(Value::Null));
+            i.insert("il".to_string(),
+                instruction.address()
+                    .and_then(|address| by_address.get(&address).cloned())
+                    .unwrap_or_default()
+                    .into());
+            mapping.push(i.into());
+        }
+    }
+
+    let mut m = Map::new();
+    m.insert("address".to_string(), il_function.address().into());
+    m.insert("index".to_string(),
+        il_function.index()
+            .map(|index| index.into())
+            .unwrap_or(Value::Null));
+    m.insert("name".to_string(), il_function.name().into());
+    m.insert("blocks".to_string(), blocks.into());
+    m.insert("edges".to_string(), edges.into());
+    m.insert("mapping".to_string(), mapping.into());
+    m.into()
+}
+
+
+pub fn disassembly_to_json(instructions: &[disassembly::Instruction]) -> Value {
+    instructions.iter()
+        .map(|instruction| {
+            let ir =
+                instruction.ir()
+                    .iter()
+                    .map(|&(function_index, block_index, instruction_index)| {
+                        let mut l = Map::new();
+                        l.insert("function-index".to_string(), function_index.into());
+                        l.insert("block-index".to_string(), block_index.into());
+                        l.insert("instruction-index".to_string(), instruction_index.into());
+                        l.into()
+                    })
+                    .collect::<Vec<Value>>();
+
+            let mut m = Map::new();
+            m.insert("address".to_string(), instruction.address().into());
+            m.insert("length".to_string(), instruction.length().into());
+            m.insert("bytes".to_string(),
+                instruction.bytes()
+                    .iter()
+                    .map(|b| format!("{:02x}", b))
+                    .collect::<String>()
+                    .into());
+            m.insert("mnemonic".to_string(), instruction.mnemonic().into());
+            m.insert("operands".to_string(), instruction.operands().into());
+            m.insert("valid".to_string(), instruction.valid().into());
+            m.insert("ir".to_string(), ir.into());
+            m.into()
+        })
+        .collect::<Vec<Value>>()
+        .into()
+}
+
+
+/// IR instructions in an address range, and the blocks holding the machine
+/// instructions its bounds fall in the middle of.
+pub fn instructions_in_range_to_json(
+    program: &ir::Program<ir::Constant>,
+    instructions: &[(usize, usize, usize)],
+    containing: &[(usize, usize, usize)]
+) -> Value {
+    let located = |&(function_index, block_index, instruction_index): &(usize, usize, usize)| {
+        let function = program.function(function_index)?;
+        let block =
+            function.blocks()
+                .into_iter()
+                .find(|block| block.index() == block_index)?;
+        let instruction =
+            block.instructions()
+                .into_iter()
+                .find(|instruction| instruction.index() == instruction_index)?;
+        let mut m = Map::new();
+        m.insert("function-index".to_string(), function_index.into());
+        m.insert("function-name".to_string(), function.name().into());
+        m.insert("block-index".to_string(), block_index.into());
+        m.insert("instruction".to_string(), instruction_to_json(instruction));
+        Some((m, block))
+    };
+
+    let instructions =
+        instructions.iter()
+            .filter_map(|location| located(location).map(|(m, _)| m.into()))
+            .collect::<Vec<Value>>();
+
+    let containing =
+        containing.iter()
+            .filter_map(|location| located(location).map(|(mut m, block)| {
+                m.insert("block".to_string(), block_to_json(block));
+                m.into()
+            }))
+            .collect::<Vec<Value>>();
+
+    let mut m = Map::new();
+    m.insert("instructions".to_string(), instructions.into());
+    m.insert("containing".to_string(), containing.into());
+    m.into()
+}
+
+
+pub fn json_to_constant_matcher(value: &Value) -> Result<Matcher> {
+    let field = |name: &str| -> Result<u64> {
+        Ok(value.get(name)
+            .ok_or(format!("match missing {}", name))?
+            .as_u64()
+            .ok_or(format!("match {} was not a valid number", name))?)
+    };
+
+    match value.get("type").and_then(|t| t.as_str()) {
+        Some("exact") => Ok(Matcher::Exact(field("value")?)),
+        Some("range") => Ok(Matcher::Range(field("low")?, field("high")?)),
+        Some("mask") => Ok(Matcher::Mask { mask: field("mask")?, value: field("value")? }),
+        Some(t) => bail!("Unknown match type {}", t),
+        None => bail!("match missing type")
+    }
+}
+
+
+pub fn constant_use_to_json(constant_use: &ConstantUse) -> Value {
+    let mut m = Map::new();
+    m.insert("location".to_string(),
+             instruction_location_to_json(constant_use.location()));
+    m.insert("path".to_string(), constant_use.path().into());
+    m.insert("constant".to_string(), constant_to_json(constant_use.constant()));
+    m.into()
+}
+
+
+pub fn query_match_to_json(query_match: &QueryMatch) -> Value {
+    let captures =
+        query_match.captures()
+            .iter()
+            .map(|(name, capture)| {
+                let (kind, value) = match capture.value() {
+                    Captured::Expression(expression) =>
+                        ("expression", expression_to_string(expression).into()),
+                    Captured::Variable(variable) =>
+                        ("variable", variable_to_string(variable).into()),
+                    Captured::Name(name) => ("name", name.as_str().into()),
+                    Captured::Integer(value) => ("integer", (*value).into())
+                };
+                let mut c = Map::new();
+                c.insert("path".to_string(), capture.path().into());
+                c.insert("type".to_string(), kind.into());
+                c.insert("value".to_string(), value);
+                (name.to_string(), c.into())
+            })
+            .collect::<Map<String, Value>>();
+
+    let mut m = Map::new();
+    m.insert("location".to_string(),
+             instruction_location_to_json(query_match.location()));
+    m.insert("path".to_string(),
+        query_match.path()
+            .map(|path| path.into())
+            .unwrap_or(Value::Null));
+    m.insert("captures".to_string(), captures.into());
+    m.into()
+}
+
+
+fn access_to_json(access: &Access) -> Value {
+    let mut m = Map::new();
+    m.insert("location".to_string(), instruction_location_to_json(access.location()));
+    m.insert("path".to_string(), access.path().into());
+    m.insert("address".to_string(), access.address().into());
+    m.insert("index".to_string(), expression_to_string(access.index()).into());
+    m.into()
+}
+
+
+pub fn memory_accesses_to_json(accesses: &MemoryAccesses) -> Value {
+    let mut m = Map::new();
+    m.insert("readers".to_string(),
+        accesses.readers()
+            .iter()
+            .map(|access| access_to_json(access))
+            .collect::<Vec<Value>>()
+            .into());
+    m.insert("writers".to_string(),
+        accesses.writers()
+            .iter()
+            .map(|access| access_to_json(access))
+            .collect::<Vec<Value>>()
+            .into());
+    m.insert("references".to_string(),
+        accesses.references()
+            .iter()
+            .map(|constant_use| constant_use_to_json(constant_use))
+            .collect::<Vec<Value>>()
+            .into());
+    m.into()
+}
+
+
+fn coverage_to_json(coverage: &Coverage) -> Map<String, Value> {
+    let mut m = Map::new();
+    m.insert("instructions".to_string(), coverage.instructions().into());
+    m.insert("intrinsics".to_string(), coverage.intrinsics().into());
+    m.insert("modeled".to_string(), coverage.modeled().into());
+    m
+}
+
+
+pub fn intrinsics_to_json(program: &ir::Program<ir::Constant>, intrinsics: &Intrinsics)
+    -> Value {
+
+    let mnemonics =
+        intrinsics.mnemonics()
+            .iter()
+            .map(|(name, mnemonic)| {
+                let mut m = Map::new();
+                m.insert("mnemonic".to_string(), name.as_str().into());
+                m.insert("count".to_string(), mnemonic.count().into());
+                m.insert("instruction-strs".to_string(),
+                    mnemonic.instruction_strs()
+                        .iter()
+                        .map(|s| s.as_str().into())
+                        .collect::<Vec<Value>>()
+                        .into());
+                m.insert("locations".to_string(),
+                    mnemonic.locations()
+                        .iter()
+                        .map(|location| instruction_location_to_json(location))
+                        .collect::<Vec<Value>>()
+                        .into());
+                m.into()
+            })
+            .collect::<Vec<Value>>();
+
+    let functions =
+        intrinsics.coverage()
+            .iter()
+            .map(|function_coverage| {
+                let mut m = coverage_to_json(function_coverage.coverage());
+                m.insert("function-index".to_string(),
+                    function_coverage.function_index().into());
+                m.insert("function-name".to_string(),
+                    program.function(function_coverage.function_index())
+                        .map(|function| function.name().into())
+                        .unwrap_or(Value::Null));
+                m.into()
+            })
+            .collect::<Vec<Value>>();
+
+    let mut m = Map::new();
+    m.insert("mnemonics".to_string(), mnemonics.into());
+    m.insert("functions".to_string(), functions.into());
+    m.insert("total".to_string(), coverage_to_json(&intrinsics.total()).into());
+    m.into()
+}
+
+
+fn syscall_argument_to_json(argument: &syscalls::Argument) -> Value {
+    let mut m = Map::new();
+    m.insert("register".to_string(), argument.register().into());
+    m.insert("value".to_string(),
+        argument.value()
+            .map(|value| value.into())
+            .unwrap_or(Value::Null));
+    m.insert("expression".to_string(), expression_to_string(argument.expression()).into());
+    m.into()
+}
+
+
+pub fn syscall_to_json(syscall: &Syscall) -> Value {
+    let mut m = Map::new();
+    m.insert("location".to_string(), instruction_location_to_json(syscall.location()));
+    m.insert("instruction-str".to_string(), syscall.instruction_str().into());
+    m.insert("number".to_string(), syscall_argument_to_json(syscall.number()));
+    m.insert("name".to_string(),
+        syscall.name()
+            .map(|name| name.into())
+            .unwrap_or(Value::Null));
+    m.insert("arguments".to_string(),
+        syscall.arguments()
+            .iter()
+            .map(|argument| syscall_argument_to_json(argument))
+            .collect::<Vec<Value>>()
+            .into());
+    m.into()
+}
+
+
+pub fn crypto_to_json(program: &ir::Program<ir::Constant>, crypto: &Crypto) -> Value {
+    let function_name = |index: usize| -> Value {
+        program.function(index)
+            .map(|function| function.name().into())
+            .unwrap_or(Value::Null)
+    };
+
+    let tables =
+        crypto.tables()
+            .iter()
+            .map(|table| {
+                let mut m = Map::new();
+                m.insert("name".to_string(), table.name().into());
+                m.insert("address".to_string(), table.address().into());
+                m.insert("length".to_string(), table.length().into());
+                m.insert("functions".to_string(),
+                    table.functions()
+                        .iter()
+                        .map(|index| {
+                            let mut f = Map::new();
+                            f.insert("function-index".to_string(), (*index).into());
+                            f.insert("function-name".to_string(), function_name(*index));
+                            f.into()
+                        })
+                        .collect::<Vec<Value>>()
+                        .into());
+                m.into()
+            })
+            .collect::<Vec<Value>>();
+
+    let constants =
+        crypto.constants()
+            .iter()
+            .map(|constant_match| {
+                let mut m = Map::new();
+                m.insert("name".to_string(), constant_match.name().into());
+                m.insert("function-index".to_string(),
+                         constant_match.function_index().into());
+                m.insert("function-name".to_string(),
+                         function_name(constant_match.function_index()));
+                m.insert("uses".to_string(),
+                    constant_match.uses()
+                        .iter()
+                        .map(|constant_use| constant_use_to_json(constant_use))
+                        .collect::<Vec<Value>>()
+                        .into());
+                m.into()
+            })
+            .collect::<Vec<Value>>();
+
+    let mut m = Map::new();
+    m.insert("tables".to_string(), tables.into());
+    m.insert("constants".to_string(), constants.into());
+    m.into()
+}
+
+
+pub fn hit_to_json(program: &ir::Program<ir::Constant>, hit: &Hit) -> Value {
+    let function_index = hit.ir().first().map(|&(function_index, _, _)| function_index);
+
+    let mut m = Map::new();
+    m.insert("rule".to_string(),
+        hit.rule()
+            .map(|rule| rule.into())
+            .unwrap_or(Value::Null));
+    m.insert("string".to_string(), hit.string().into());
+    m.insert("address".to_string(), hit.address().into());
+    m.insert("bytes".to_string(),
+        hit.bytes()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>()
+            .into());
+    m.insert("executable".to_string(), hit.executable().into());
+    m.insert("function-index".to_string(),
+        function_index
+            .map(|index| index.into())
+            .unwrap_or(Value::Null));
+    m.insert("function-name".to_string(),
+        function_index
+            .and_then(|index| program.function(index))
+            .map(|function| function.name().into())
+            .unwrap_or(Value::Null));
+    m.insert("ir".to_string(),
+        hit.ir()
+            .iter()
+            .map(|&(function_index, block_index, instruction_index)|
+                instruction_location_to_json(&InstructionLocation::new(
+                    function_index, block_index, instruction_index)))
+            .collect::<Vec<Value>>()
+            .into());
+    m.into()
+}